@@ -0,0 +1,137 @@
+//! Reference implementation of a keystore plugin. It keeps each key as a JSON file in the directory
+//! specified by `WARP_KEYSTORE_DIR`, which is not secure and should only be used for testing.
+//!
+//! Copy the built program to `warp-keystore-dir` somewhere on `PATH` then Warp will have a keystore
+//! with ID `dir`.
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use serde_json::{json, Value};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake128;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let dir = match std::env::var_os("WARP_KEYSTORE_DIR") {
+        Some(v) => PathBuf::from(v),
+        None => {
+            eprintln!("WARP_KEYSTORE_DIR is not set.");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        eprintln!("Failed to create {}: {}.", dir.display(), e);
+        return ExitCode::FAILURE;
+    }
+
+    // Process requests until Warp close our standard input.
+    let stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+
+    for line in stdin.lines() {
+        let line = match line {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to read a request: {e}.");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let res = match serde_json::from_str(&line) {
+            Ok(v) => exec(&dir, v).unwrap_or_else(|e| json!({ "error": e })),
+            Err(e) => json!({ "error": format!("invalid request: {e}") }),
+        };
+
+        if writeln!(stdout, "{res}")
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn exec(dir: &Path, req: Value) -> Result<Value, String> {
+    let str = |name: &str| {
+        req[name]
+            .as_str()
+            .ok_or_else(|| format!("missing '{name}'"))
+    };
+
+    match str("request")? {
+        "hello" => Ok(json!({ "version": 1 })),
+        "list" => {
+            let mut keys = Vec::new();
+
+            for item in std::fs::read_dir(dir).map_err(|e| e.to_string())? {
+                let path = item.map_err(|e| e.to_string())?.path();
+                let id = path.file_name().unwrap().to_str();
+                let key = read(&path)?;
+
+                keys.push(json!({ "id": id, "data": key["data"] }));
+            }
+
+            Ok(json!({ "keys": keys }))
+        }
+        "generate" => {
+            let mut key = [0u8; 16];
+
+            getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
+
+            let id = key_id(&key);
+
+            write(dir, &id, &hex::encode(key), str("data")?)?;
+
+            Ok(json!({ "id": id }))
+        }
+        "get" => {
+            let path = dir.join(str("id")?);
+
+            if path.exists() {
+                Ok(json!({ "material": read(&path)? }))
+            } else {
+                Ok(json!({ "material": null }))
+            }
+        }
+        "store" => {
+            write(dir, str("id")?, str("key")?, str("data")?)?;
+            Ok(json!({}))
+        }
+        "delete" => match std::fs::remove_file(dir.join(str("id")?)) {
+            Ok(_) => Ok(json!({ "deleted": true })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(json!({ "deleted": false })),
+            Err(e) => Err(e.to_string()),
+        },
+        v => Err(format!("unknown request '{v}'")),
+    }
+}
+
+fn read(path: &Path) -> Result<Value, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    serde_json::from_slice(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write(dir: &Path, id: &str, key: &str, data: &str) -> Result<(), String> {
+    let path = dir.join(id);
+    let data = json!({ "key": key, "data": data }).to_string();
+
+    std::fs::write(&path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Same algorithm as `KeyId::from_key` in Warp.
+fn key_id(key: &[u8; 16]) -> String {
+    let mut kcv = [0u8; 16];
+    let mut id = [0u8; 16];
+    let mut hasher = Shake128::default();
+
+    Aes128::new(key.into()).encrypt_block((&mut kcv).into());
+    hasher.update(&kcv);
+    hasher.finalize_xof().read(&mut id);
+
+    hex::encode(id)
+}
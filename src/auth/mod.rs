@@ -0,0 +1,104 @@
+use crate::key::KeyMgr;
+use crate::server::{Client, UrlError};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use thiserror::Error;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Credentials to access a Warp server.
+///
+/// The credentials are stored as a secret in one of the keystores so the refresh token get the same
+/// protection as the file encryption keys.
+#[derive(Serialize, Deserialize)]
+pub struct Credentials {
+    pub account: String,
+    pub refresh_token: Zeroizing<String>,
+}
+
+impl Credentials {
+    /// Load the credentials for `server`. Returns [`None`] if not logged in to `server`.
+    pub fn load(keymgr: &KeyMgr, server: &Url) -> Result<Option<Self>, CredentialsError> {
+        let data = match keymgr.get_secret(&Self::secret(server)) {
+            Ok(Some(v)) => v,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(CredentialsError::LoadFailed(e)),
+        };
+
+        match serde_json::from_slice(&data) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(CredentialsError::InvalidCredentials(e)),
+        }
+    }
+
+    /// Store the credentials for `server` to `store`, replacing the existing one in all stores.
+    pub fn save(&self, keymgr: &KeyMgr, store: &str, server: &Url) -> Result<(), CredentialsError> {
+        let name = Self::secret(server);
+        let data = Zeroizing::new(serde_json::to_vec(self).unwrap());
+
+        // Write the new credentials first so a failure does not remove the existing one.
+        match keymgr.set_secret(store, &name, &data) {
+            Ok(true) => {}
+            Ok(false) => return Err(CredentialsError::UnknownStore(store.to_owned())),
+            Err(e) => return Err(CredentialsError::StoreFailed(e)),
+        }
+
+        // Remove the old credentials from the other stores so it does not shadow the new one.
+        for s in keymgr.stores().filter(|s| s.id() != store) {
+            s.delete_secret(&name)
+                .map_err(CredentialsError::DeleteFailed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the credentials for `server`. Returns `false` if not logged in to `server`.
+    pub fn delete(keymgr: &KeyMgr, server: &Url) -> Result<bool, CredentialsError> {
+        keymgr
+            .delete_secret(&Self::secret(server))
+            .map_err(CredentialsError::DeleteFailed)
+    }
+
+    fn secret(server: &Url) -> String {
+        format!("credentials:{server}")
+    }
+}
+
+/// Create a [`Client`] for `server` that authenticate with the stored [`Credentials`].
+pub fn connect(keymgr: &KeyMgr, server: &Url) -> Result<Client, ConnectError> {
+    let client = Client::new(server.clone()).map_err(ConnectError::InvalidUrl)?;
+
+    match Credentials::load(keymgr, server)? {
+        Some(v) => Ok(client.with_refresh_token(v.refresh_token)),
+        None => Ok(client),
+    }
+}
+
+/// Represents an error when [`connect()`] fails.
+#[derive(Debug, Error)]
+pub enum ConnectError {
+    #[error("the URL of the server is not valid")]
+    InvalidUrl(#[source] UrlError),
+
+    #[error(transparent)]
+    LoadCredentialsFailed(#[from] CredentialsError),
+}
+
+/// Represents an error when [`Credentials`] fails.
+#[derive(Debug, Error)]
+pub enum CredentialsError {
+    #[error("couldn't load the credentials")]
+    LoadFailed(#[source] Box<dyn Error>),
+
+    #[error("the stored credentials is not valid")]
+    InvalidCredentials(#[source] serde_json::Error),
+
+    #[error("keystore '{0}' does not exists")]
+    UnknownStore(String),
+
+    #[error("couldn't store the credentials")]
+    StoreFailed(#[source] Box<dyn Error>),
+
+    #[error("couldn't remove the credentials")]
+    DeleteFailed(#[source] Box<dyn Error>),
+}
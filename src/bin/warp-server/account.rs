@@ -0,0 +1,258 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Accounts that stored in `accounts.yml` inside the data directory.
+///
+/// Only the hash of each token and password is stored so the file does not need to be kept secret.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Accounts(BTreeMap<String, Account>);
+
+impl Accounts {
+    pub fn load(data: &Path) -> Result<Self, AccountError> {
+        let path = Self::path(data);
+        let file = match File::open(&path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(AccountError::OpenFileFailed(path, e)),
+        };
+
+        match serde_yaml::from_reader(BufReader::new(file)) {
+            Ok(v) => Ok(v),
+            Err(e) => Err(AccountError::ReadFileFailed(path, e)),
+        }
+    }
+
+    pub fn save(&self, data: &Path) -> Result<(), AccountError> {
+        // Write to a temporary file.
+        let path = Self::path(data);
+        let tmp = path.with_extension("tmp");
+        let file = match File::create(&tmp) {
+            Ok(v) => v,
+            Err(e) => return Err(AccountError::CreateFileFailed(tmp, e)),
+        };
+
+        let mut file = BufWriter::new(file);
+
+        if let Err(e) = serde_yaml::to_writer(&mut file, self) {
+            return Err(AccountError::WriteAccountsFailed(tmp, e));
+        }
+
+        if let Err(e) = file.flush() {
+            return Err(AccountError::WriteFileFailed(tmp, e));
+        }
+
+        // Replace the old file.
+        drop(file);
+
+        if let Err(e) = std::fs::rename(&tmp, &path) {
+            return Err(AccountError::WriteFileFailed(path, e));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the name of the account that own `token`.
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        let hash = Self::hash(token);
+
+        self.0
+            .iter()
+            .find(|(_, a)| a.tokens.contains(&hash))
+            .map(|(n, _)| n.as_str())
+    }
+
+    /// Start a new session for the account if `password` is correct. Returns a refresh token for
+    /// the session or [`None`] if the account does not exists or the password is wrong.
+    pub fn login(&mut self, name: &str, password: &str) -> Result<Option<String>, AccountError> {
+        // Check the password.
+        let account = match self.0.get_mut(name) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let hash = match &account.password {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let hash = PasswordHash::new(hash).map_err(AccountError::InvalidPasswordHash)?;
+
+        if Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_err()
+        {
+            return Ok(None);
+        }
+
+        // Issue a refresh token.
+        let token = Self::generate()?;
+
+        account.sessions.push(Self::hash(&token));
+
+        Ok(Some(token))
+    }
+
+    /// Returns the name of the account that own the session of `token`.
+    pub fn session(&self, token: &str) -> Option<&str> {
+        let hash = Self::hash(token);
+
+        self.0
+            .iter()
+            .find(|(_, a)| a.sessions.contains(&hash))
+            .map(|(n, _)| n.as_str())
+    }
+
+    /// End the session of `token`. Returns `false` if the session does not exists.
+    pub fn logout(&mut self, token: &str) -> bool {
+        let hash = Self::hash(token);
+
+        for a in self.0.values_mut() {
+            if let Some(i) = a.sessions.iter().position(|v| *v == hash) {
+                a.sessions.remove(i);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|v| v.as_str())
+    }
+
+    /// Returns `false` if the account is already exists.
+    pub fn add(&mut self, name: &str) -> bool {
+        if self.0.contains_key(name) {
+            return false;
+        }
+
+        self.0.insert(name.to_owned(), Account::default());
+
+        true
+    }
+
+    /// Returns `false` if the account does not exists.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.0.remove(name).is_some()
+    }
+
+    /// Issue a new token for the account. Returns [`None`] if the account does not exists.
+    pub fn issue(&mut self, name: &str) -> Result<Option<String>, AccountError> {
+        let account = match self.0.get_mut(name) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let token = Self::generate()?;
+
+        account.tokens.push(Self::hash(&token));
+
+        Ok(Some(token))
+    }
+
+    /// Revoke all tokens and sessions of the account. Returns `false` if the account does not
+    /// exists.
+    pub fn revoke(&mut self, name: &str) -> bool {
+        match self.0.get_mut(name) {
+            Some(v) => {
+                v.tokens.clear();
+                v.sessions.clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set a password to login to the account and end all of its sessions. Returns `false` if the
+    /// account does not exists.
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<bool, AccountError> {
+        let account = match self.0.get_mut(name) {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        let mut salt = [0u8; 16];
+
+        getrandom(&mut salt).map_err(AccountError::GenerateSaltFailed)?;
+
+        let salt = SaltString::encode_b64(&salt).unwrap();
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(AccountError::HashPasswordFailed)?;
+
+        account.password = Some(hash.to_string());
+        account.sessions.clear();
+
+        Ok(true)
+    }
+
+    fn generate() -> Result<String, AccountError> {
+        let mut token = [0u8; 32];
+
+        getrandom(&mut token).map_err(AccountError::GenerateTokenFailed)?;
+
+        Ok(hex::encode(token))
+    }
+
+    fn hash(token: &str) -> String {
+        hex::encode(Sha3_256::digest(token.as_bytes()))
+    }
+
+    fn path(data: &Path) -> PathBuf {
+        data.join("accounts.yml")
+    }
+}
+
+/// A single account in [`Accounts`].
+#[derive(Default, Serialize, Deserialize)]
+pub struct Account {
+    /// SHA3-256 of each token in hex.
+    tokens: Vec<String>,
+
+    /// Argon2 hash of the password in PHC string format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+
+    /// SHA3-256 of each refresh token in hex.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sessions: Vec<String>,
+}
+
+/// Represents an error when [`Accounts`] fails.
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error("couldn't open {0}")]
+    OpenFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteAccountsFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't generate a token")]
+    GenerateTokenFailed(#[source] getrandom::Error),
+
+    #[error("couldn't generate a salt")]
+    GenerateSaltFailed(#[source] getrandom::Error),
+
+    #[error("couldn't hash the password")]
+    HashPasswordFailed(#[source] argon2::password_hash::Error),
+
+    #[error("the stored password hash is not valid")]
+    InvalidPasswordHash(#[source] argon2::password_hash::Error),
+}
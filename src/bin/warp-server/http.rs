@@ -0,0 +1,332 @@
+use crate::account::{AccountError, Accounts};
+use crate::storage::{is_valid_name, Storage};
+use crate::token::AccessTokens;
+use erdp::ErrorDisplay;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response, ResponseBox};
+
+/// State shared by all requests.
+pub struct Context {
+    pub data: PathBuf,
+    pub storage: Storage,
+    pub tokens: AccessTokens,
+    pub accounts: Mutex<()>,
+}
+
+/// Handle a single request from the client.
+pub fn handle(cx: &Context, mut req: Request) {
+    let method = req.method().clone();
+    let url = req.url().to_owned();
+    let res = match route(cx, &mut req) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to handle {method} {url}: {}.", e.display());
+            status(500)
+        }
+    };
+
+    if let Err(e) = req.respond(res) {
+        eprintln!("Failed to respond to {method} {url}: {}.", e.display());
+    }
+}
+
+fn route(cx: &Context, req: &mut Request) -> Result<ResponseBox, HandleError> {
+    // Parse the path.
+    let url = req.url().to_owned();
+    let path = url.split('?').next().unwrap();
+    let path: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let method = req.method().clone();
+
+    // Handle the endpoints that does not require authentication.
+    match (&method, path.as_slice()) {
+        (Method::Post, ["auth", "login"]) => return login(cx, req),
+        (Method::Post, ["auth", "token"]) => return refresh(cx, req),
+        (Method::Post, ["auth", "logout"]) => return logout(cx, req),
+        _ => {}
+    }
+
+    // Authenticate. The accounts are loaded for each request so the changes from the other
+    // commands take effect immediately.
+    let accounts = Accounts::load(&cx.data)?;
+    let account = match header(req, "Authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|v| {
+            cx.tokens
+                .verify(v)
+                .filter(|&a| accounts.names().any(|n| n == a))
+                .or_else(|| accounts.authenticate(v))
+        }) {
+        Some(v) => v.to_owned(),
+        None => return Ok(status(401)),
+    };
+
+    let a = account.as_str();
+
+    match (method, path.as_slice()) {
+        (Method::Get, ["auth", "whoami"]) => Ok(json(200, &Identity { account: a })),
+        (Method::Get, ["repos"]) => {
+            let repos = cx.storage.list_repos(a)?;
+            let repos: Vec<RepoInfo> = repos.iter().map(|name| RepoInfo { name }).collect();
+
+            Ok(json(200, &repos))
+        }
+        (Method::Post, ["repos"]) => create_repo(cx, req, a),
+        (method, ["repos", repo, rest @ ..]) => {
+            if !is_valid_name(repo) {
+                return Ok(status(404));
+            }
+
+            // Check if the repository exists.
+            if method != Method::Delete && !cx.storage.has_repo(a, repo) {
+                return Ok(status(404));
+            }
+
+            match (method, rest) {
+                (Method::Get, []) => Ok(json(200, &RepoInfo { name: repo })),
+                (Method::Delete, []) => match cx.storage.delete_repo(a, repo)? {
+                    true => Ok(status(204)),
+                    false => Ok(status(404)),
+                },
+                (Method::Get, [doc @ ("manifest" | "keyring")]) => get_document(cx, a, repo, doc),
+                (Method::Put, [doc @ ("manifest" | "keyring")]) => {
+                    put_document(cx, req, a, repo, doc)
+                }
+                (Method::Get, ["objects"]) => {
+                    let mut objects = cx.storage.list_objects(a, repo)?;
+
+                    objects.retain(|v| is_valid_id(v));
+
+                    Ok(json(200, &objects))
+                }
+                (Method::Get | Method::Head, ["objects", id]) if is_valid_id(id) => {
+                    match cx.storage.object(a, repo, id)? {
+                        Some(v) => Ok(Response::from_file(v).boxed()),
+                        None => Ok(status(404)),
+                    }
+                }
+                (Method::Put, ["objects", id]) if is_valid_id(id) => {
+                    cx.storage.put_object(a, repo, id, req.as_reader())?;
+                    Ok(status(204))
+                }
+                (Method::Delete, ["objects", id]) if is_valid_id(id) => {
+                    match cx.storage.delete_object(a, repo, id)? {
+                        true => Ok(status(204)),
+                        false => Ok(status(404)),
+                    }
+                }
+                _ => Ok(status(404)),
+            }
+        }
+        _ => Ok(status(404)),
+    }
+}
+
+fn login(cx: &Context, req: &mut Request) -> Result<ResponseBox, HandleError> {
+    #[derive(Deserialize)]
+    struct Body {
+        username: String,
+        password: String,
+    }
+
+    #[derive(Serialize)]
+    struct Session<'a> {
+        account: &'a str,
+        refresh_token: &'a str,
+    }
+
+    let body: Body = match serde_json::from_reader(req.as_reader()) {
+        Ok(v) => v,
+        Err(_) => return Ok(status(400)),
+    };
+
+    // Start a session.
+    let _lock = cx.accounts.lock().unwrap();
+    let mut accounts = Accounts::load(&cx.data)?;
+    let token = match accounts.login(&body.username, &body.password)? {
+        Some(v) => v,
+        None => return Ok(status(401)),
+    };
+
+    accounts.save(&cx.data)?;
+
+    Ok(json(
+        200,
+        &Session {
+            account: &body.username,
+            refresh_token: &token,
+        },
+    ))
+}
+
+fn refresh(cx: &Context, req: &mut Request) -> Result<ResponseBox, HandleError> {
+    #[derive(Serialize)]
+    struct Token {
+        access_token: String,
+        expires_in: u64,
+    }
+
+    let body: SessionBody = match serde_json::from_reader(req.as_reader()) {
+        Ok(v) => v,
+        Err(_) => return Ok(status(400)),
+    };
+
+    // Issue an access token.
+    let accounts = Accounts::load(&cx.data)?;
+    let account = match accounts.session(&body.refresh_token) {
+        Some(v) => v,
+        None => return Ok(status(401)),
+    };
+
+    Ok(json(
+        200,
+        &Token {
+            access_token: cx.tokens.issue(account),
+            expires_in: AccessTokens::LIFETIME.as_secs(),
+        },
+    ))
+}
+
+fn logout(cx: &Context, req: &mut Request) -> Result<ResponseBox, HandleError> {
+    let body: SessionBody = match serde_json::from_reader(req.as_reader()) {
+        Ok(v) => v,
+        Err(_) => return Ok(status(400)),
+    };
+
+    // End the session.
+    let _lock = cx.accounts.lock().unwrap();
+    let mut accounts = Accounts::load(&cx.data)?;
+
+    if accounts.logout(&body.refresh_token) {
+        accounts.save(&cx.data)?;
+    }
+
+    Ok(status(204))
+}
+
+fn create_repo(cx: &Context, req: &mut Request, account: &str) -> Result<ResponseBox, HandleError> {
+    #[derive(Deserialize)]
+    struct Body {
+        name: String,
+    }
+
+    let body: Body = match serde_json::from_reader(req.as_reader()) {
+        Ok(v) => v,
+        Err(_) => return Ok(status(400)),
+    };
+
+    if !is_valid_name(&body.name) {
+        return Ok(status(400));
+    }
+
+    match cx.storage.create_repo(account, &body.name)? {
+        true => Ok(json(201, &RepoInfo { name: &body.name })),
+        false => Ok(status(409)),
+    }
+}
+
+fn get_document(
+    cx: &Context,
+    account: &str,
+    repo: &str,
+    name: &str,
+) -> Result<ResponseBox, HandleError> {
+    let (data, rev) = match cx.storage.document(account, repo, name)? {
+        Some(v) => v,
+        None => return Ok(status(404)),
+    };
+
+    Ok(Response::from_data(data).with_header(etag(&rev)).boxed())
+}
+
+fn put_document(
+    cx: &Context,
+    req: &mut Request,
+    account: &str,
+    repo: &str,
+    name: &str,
+) -> Result<ResponseBox, HandleError> {
+    // Get the expected revision. The client always specify one of these headers.
+    let base = match (header(req, "If-Match"), header(req, "If-None-Match")) {
+        (Some(v), None) => Some(v.trim_matches('"').to_owned()),
+        (None, Some("*")) => None,
+        _ => return Ok(status(428)),
+    };
+
+    // Read the document.
+    let mut data = Vec::new();
+
+    req.as_reader()
+        .read_to_end(&mut data)
+        .map_err(HandleError::ReadBodyFailed)?;
+
+    // Write the document.
+    match cx
+        .storage
+        .put_document(account, repo, name, &data, base.as_deref())?
+    {
+        Some(v) => Ok(status(200).with_header(etag(&v))),
+        None => Ok(status(412)),
+    }
+}
+
+fn header<'a>(req: &'a Request, name: &'static str) -> Option<&'a str> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn status(code: u16) -> ResponseBox {
+    Response::empty(code).boxed()
+}
+
+fn json(code: u16, body: &impl Serialize) -> ResponseBox {
+    let ty = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    Response::from_data(serde_json::to_vec(body).unwrap())
+        .with_status_code(code)
+        .with_header(ty)
+        .boxed()
+}
+
+fn etag(rev: &str) -> Header {
+    Header::from_bytes("ETag", format!("\"{rev}\"")).unwrap()
+}
+
+/// Request body that contains a refresh token.
+#[derive(Deserialize)]
+struct SessionBody {
+    refresh_token: String,
+}
+
+/// Account of the authenticated client.
+#[derive(Serialize)]
+struct Identity<'a> {
+    account: &'a str,
+}
+
+/// Information of a repository.
+#[derive(Serialize)]
+struct RepoInfo<'a> {
+    name: &'a str,
+}
+
+/// Represents an error when the request fails to handle.
+#[derive(Debug, Error)]
+enum HandleError {
+    #[error("couldn't load or save accounts")]
+    AccountsFailed(#[from] AccountError),
+
+    #[error("couldn't read the request")]
+    ReadBodyFailed(#[source] std::io::Error),
+
+    #[error("couldn't access the storage")]
+    StorageFailed(#[from] std::io::Error),
+}
@@ -0,0 +1,308 @@
+#![allow(clippy::enum_variant_names)]
+
+use self::account::Accounts;
+use self::http::{handle, Context};
+use self::storage::{is_valid_name, Storage};
+use self::token::AccessTokens;
+use clap::builder::NonEmptyStringValueParser;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+mod account;
+mod http;
+mod storage;
+mod token;
+
+fn main() -> ExitCode {
+    // Parse arguments.
+    let data = Arg::new("data")
+        .help("Directory to store accounts and repositories")
+        .long("data")
+        .value_name("DIR")
+        .value_parser(value_parser!(PathBuf))
+        .required(true);
+    let name = Arg::new("name")
+        .help("Name of the account")
+        .value_name("NAME")
+        .value_parser(NonEmptyStringValueParser::new())
+        .required(true);
+    let args = Command::new("warp-server")
+        .about("Reference server for Warp")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("serve")
+                .about("Serve Warp API over HTTP")
+                .arg(data.clone())
+                .arg(
+                    Arg::new("listen")
+                        .help("Address to listen for HTTP requests")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .value_parser(value_parser!(SocketAddr))
+                        .default_value("127.0.0.1:8080"),
+                ),
+        )
+        .subcommand(
+            Command::new("account")
+                .about("Manage accounts")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Create a new account and print its token")
+                        .arg(data.clone())
+                        .arg(name.clone()),
+                )
+                .subcommand(
+                    Command::new("token")
+                        .about("Issue a new token for an account")
+                        .arg(data.clone())
+                        .arg(name.clone())
+                        .arg(
+                            Arg::new("revoke")
+                                .help("Revoke all existing tokens of the account")
+                                .long("revoke")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("passwd")
+                        .about("Set a password to login to an account")
+                        .arg(data.clone())
+                        .arg(name.clone()),
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove an account (repositories are not removed)")
+                        .arg(data.clone())
+                        .arg(name),
+                )
+                .subcommand(Command::new("ls").about("List all accounts").arg(data)),
+        )
+        .get_matches();
+
+    // Execute the command.
+    match args.subcommand().unwrap() {
+        ("serve", args) => serve(args),
+        ("account", args) => match args.subcommand().unwrap() {
+            ("add", args) => account_add(args),
+            ("token", args) => account_token(args),
+            ("passwd", args) => account_passwd(args),
+            ("rm", args) => account_rm(args),
+            ("ls", args) => account_ls(args),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn serve(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let addr: &SocketAddr = args.get_one("listen").unwrap();
+
+    // Check if accounts can be loaded.
+    if let Err(e) = Accounts::load(data) {
+        eprintln!("Failed to load accounts: {}.", e.display());
+        return ExitCode::FAILURE;
+    }
+
+    // Load the key to sign access tokens.
+    let tokens = match AccessTokens::load(data) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to load the key for access tokens: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Start the server.
+    let server = match tiny_http::Server::http(addr) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to listen on {addr}: {e}.");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let cx = Arc::new(Context {
+        data: data.clone(),
+        storage: Storage::new(data),
+        tokens,
+        accounts: Mutex::new(()),
+    });
+
+    // Get the actual address in case the port was chosen by the OS.
+    let addr = server.server_addr().to_ip().unwrap_or(*addr);
+
+    eprintln!("Listening on http://{addr}.");
+
+    for req in server.incoming_requests() {
+        let cx = cx.clone();
+
+        std::thread::spawn(move || handle(&cx, req));
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn account_add(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let name: &String = args.get_one("name").unwrap();
+
+    if !is_valid_name(name) {
+        eprintln!("Account name can contains only letters, digits, '.', '_' and '-'.");
+        return ExitCode::FAILURE;
+    }
+
+    update_accounts(data, |accounts| {
+        if !accounts.add(name) {
+            eprintln!("Account '{name}' already exists.");
+            return false;
+        }
+
+        issue_token(accounts, name)
+    })
+}
+
+fn account_token(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let name: &String = args.get_one("name").unwrap();
+
+    update_accounts(data, |accounts| {
+        if args.get_flag("revoke") && !accounts.revoke(name) {
+            eprintln!("Account '{name}' does not exists.");
+            return false;
+        }
+
+        issue_token(accounts, name)
+    })
+}
+
+fn account_passwd(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let name: &String = args.get_one("name").unwrap();
+    let password = match password() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to get the password: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    update_accounts(data, |accounts| {
+        match accounts.set_password(name, &password) {
+            Ok(true) => true,
+            Ok(false) => {
+                eprintln!("Account '{name}' does not exists.");
+                false
+            }
+            Err(e) => {
+                eprintln!("Failed to set the password: {}.", e.display());
+                false
+            }
+        }
+    })
+}
+
+fn account_rm(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let name: &String = args.get_one("name").unwrap();
+
+    update_accounts(data, |accounts| {
+        if !accounts.remove(name) {
+            eprintln!("Account '{name}' does not exists.");
+            return false;
+        }
+
+        true
+    })
+}
+
+fn account_ls(args: &ArgMatches) -> ExitCode {
+    let data: &PathBuf = args.get_one("data").unwrap();
+    let accounts = match Accounts::load(data) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to load accounts: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for name in accounts.names() {
+        println!("{name}");
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn issue_token(accounts: &mut Accounts, name: &str) -> bool {
+    match accounts.issue(name) {
+        Ok(Some(v)) => {
+            println!("{v}");
+            true
+        }
+        Ok(None) => {
+            eprintln!("Account '{name}' does not exists.");
+            false
+        }
+        Err(e) => {
+            eprintln!("Failed to issue a token: {}.", e.display());
+            false
+        }
+    }
+}
+
+/// Get password from `WARP_PASSWORD` or prompt the user if it is not set.
+fn password() -> Result<Zeroizing<String>, std::io::Error> {
+    if let Some(v) = std::env::var_os("WARP_PASSWORD") {
+        return v.into_string().map(Zeroizing::new).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "WARP_PASSWORD is not a valid UTF-8",
+            )
+        });
+    }
+
+    loop {
+        let pass = Zeroizing::new(rpassword::prompt_password("Password: ")?);
+        let again = Zeroizing::new(rpassword::prompt_password("Confirm password: ")?);
+
+        if pass == again {
+            return Ok(pass);
+        }
+
+        eprintln!("Passwords do not match, please try again.");
+    }
+}
+
+fn update_accounts(data: &Path, f: impl FnOnce(&mut Accounts) -> bool) -> ExitCode {
+    // Load accounts.
+    if let Err(e) = std::fs::create_dir_all(data) {
+        eprintln!("Failed to create {}: {}.", data.display(), e.display());
+        return ExitCode::FAILURE;
+    }
+
+    let mut accounts = match Accounts::load(data) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to load accounts: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Update.
+    if !f(&mut accounts) {
+        return ExitCode::FAILURE;
+    }
+
+    if let Err(e) = accounts.save(data) {
+        eprintln!("Failed to save accounts: {}.", e.display());
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,227 @@
+use getrandom::getrandom;
+use sha3::{Digest, Sha3_256};
+use std::fs::{create_dir, create_dir_all, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Repositories that stored on the local filesystem.
+///
+/// Each repository is stored in `repos/<account>/<repo>` inside the data directory with the
+/// documents (`manifest` and `keyring`) in the files with the same name and the objects in
+/// `objects/<id>`. The server never see the plaintext so everything is stored as-is.
+pub struct Storage {
+    root: PathBuf,
+    documents: Mutex<()>,
+}
+
+impl Storage {
+    pub fn new(data: &Path) -> Self {
+        Self {
+            root: data.join("repos"),
+            documents: Mutex::new(()),
+        }
+    }
+
+    /// Returns `false` if the repository is already exists.
+    pub fn create_repo(&self, account: &str, repo: &str) -> std::io::Result<bool> {
+        let path = self.repo(account, repo);
+
+        create_dir_all(path.parent().unwrap())?;
+
+        match create_dir(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        create_dir(path.join("objects"))?;
+
+        Ok(true)
+    }
+
+    /// Returns the name of all repositories that owned by `account`.
+    pub fn list_repos(&self, account: &str) -> std::io::Result<Vec<String>> {
+        let dir = match std::fs::read_dir(self.root.join(account)) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut repos = Vec::new();
+
+        for e in dir {
+            let e = e?;
+
+            if !e.file_type()?.is_dir() {
+                continue;
+            }
+
+            if let Some(v) = e.file_name().to_str().filter(|v| is_valid_name(v)) {
+                repos.push(v.to_owned());
+            }
+        }
+
+        repos.sort_unstable();
+
+        Ok(repos)
+    }
+
+    pub fn has_repo(&self, account: &str, repo: &str) -> bool {
+        self.repo(account, repo).is_dir()
+    }
+
+    /// Returns `false` if the repository does not exists.
+    pub fn delete_repo(&self, account: &str, repo: &str) -> std::io::Result<bool> {
+        match std::fs::remove_dir_all(self.repo(account, repo)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the document (e.g. `manifest`) and its revision or [`None`] if nothing has been
+    /// pushed.
+    pub fn document(
+        &self,
+        account: &str,
+        repo: &str,
+        name: &str,
+    ) -> std::io::Result<Option<(Vec<u8>, String)>> {
+        let _lock = self.documents.lock().unwrap();
+
+        self.read_document(account, repo, name)
+    }
+
+    /// Replace the document if its current revision is `base` (or nothing has been pushed if
+    /// `base` is [`None`]). Returns the new revision or [`None`] if the revision does not match.
+    pub fn put_document(
+        &self,
+        account: &str,
+        repo: &str,
+        name: &str,
+        data: &[u8],
+        base: Option<&str>,
+    ) -> std::io::Result<Option<String>> {
+        // Check current revision.
+        let _lock = self.documents.lock().unwrap();
+        let current = self.read_document(account, repo, name)?.map(|v| v.1);
+
+        if current.as_deref() != base {
+            return Ok(None);
+        }
+
+        // Write the document.
+        let path = self.repo(account, repo).join(name);
+
+        Self::write(&path, data)?;
+
+        Ok(Some(Self::revision(data)))
+    }
+
+    /// Returns the name of all files in `objects`, including the temporary files of the uploads
+    /// that in progress.
+    pub fn list_objects(&self, account: &str, repo: &str) -> std::io::Result<Vec<String>> {
+        let mut objects = Vec::new();
+
+        for e in std::fs::read_dir(self.repo(account, repo).join("objects"))? {
+            if let Ok(v) = e?.file_name().into_string() {
+                objects.push(v);
+            }
+        }
+
+        objects.sort_unstable();
+
+        Ok(objects)
+    }
+
+    /// Returns [`None`] if the object does not exists.
+    pub fn object(&self, account: &str, repo: &str, id: &str) -> std::io::Result<Option<File>> {
+        match File::open(self.repo(account, repo).join("objects").join(id)) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn put_object(
+        &self,
+        account: &str,
+        repo: &str,
+        id: &str,
+        data: &mut dyn Read,
+    ) -> std::io::Result<()> {
+        let path = self.repo(account, repo).join("objects").join(id);
+        let mut suffix = [0u8; 8];
+
+        getrandom(&mut suffix)?;
+
+        // The same object may be uploaded concurrently so the temporary file need to be unique.
+        let tmp = path.with_extension(format!("{}.tmp", hex::encode(suffix)));
+        let mut file = File::create(&tmp)?;
+
+        if let Err(e) = std::io::copy(data, &mut file).and_then(|_| file.sync_all()) {
+            drop(file);
+            std::fs::remove_file(&tmp)?;
+            return Err(e);
+        }
+
+        drop(file);
+        std::fs::rename(&tmp, &path)
+    }
+
+    pub fn delete_object(&self, account: &str, repo: &str, id: &str) -> std::io::Result<bool> {
+        match std::fs::remove_file(self.repo(account, repo).join("objects").join(id)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_document(
+        &self,
+        account: &str,
+        repo: &str,
+        name: &str,
+    ) -> std::io::Result<Option<(Vec<u8>, String)>> {
+        let data = match std::fs::read(self.repo(account, repo).join(name)) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let rev = Self::revision(&data);
+
+        Ok(Some((data, rev)))
+    }
+
+    fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+
+        file.write_all(data)?;
+        file.sync_all()?;
+
+        drop(file);
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Each document always contains a random salt so its hash is unique for each push.
+    fn revision(data: &[u8]) -> String {
+        hex::encode(Sha3_256::digest(data))
+    }
+
+    fn repo(&self, account: &str, repo: &str) -> PathBuf {
+        self.root.join(account).join(repo)
+    }
+}
+
+/// Returns `true` if `name` can be used as a name of an account or a repository.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 100
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || b == b'-')
+}
@@ -0,0 +1,111 @@
+use getrandom::getrandom;
+use hmac::{Hmac, Mac};
+use sha3::Sha3_256;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Issuer of short-lived access tokens for the clients that logged in with a password.
+///
+/// The access token is `<account>.<expiration>.<mac>` so the server does not need to keep track of
+/// it. The key to sign the token is stored in `secret` inside the data directory.
+pub struct AccessTokens {
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl AccessTokens {
+    pub const LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+    /// Load the signing key or generate a new one if it does not exists.
+    pub fn load(data: &Path) -> Result<Self, TokenError> {
+        let path = data.join("secret");
+        let mut key = Zeroizing::new([0u8; 32]);
+
+        // Load the key.
+        match std::fs::read(&path) {
+            Ok(v) => match <[u8; 32]>::try_from(v.as_slice()) {
+                Ok(v) => {
+                    *key = v;
+                    return Ok(Self { key });
+                }
+                Err(_) => return Err(TokenError::InvalidKey(path)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(TokenError::ReadKeyFailed(path, e)),
+        }
+
+        // Generate a new key.
+        getrandom(key.as_mut()).map_err(TokenError::GenerateKeyFailed)?;
+
+        let mut opts = OpenOptions::new();
+
+        opts.write(true).create_new(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+
+        if let Err(e) = std::fs::create_dir_all(data)
+            .and_then(|_| opts.open(&path))
+            .and_then(|mut f| f.write_all(key.as_ref()))
+        {
+            return Err(TokenError::WriteKeyFailed(path, e));
+        }
+
+        Ok(Self { key })
+    }
+
+    /// Issue a new access token for `account`.
+    pub fn issue(&self, account: &str) -> String {
+        let expires = SystemTime::now() + Self::LIFETIME;
+        let expires = expires.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let payload = format!("{account}.{expires}");
+        let mac = self.mac(&payload).finalize().into_bytes();
+
+        format!("{payload}.{}", hex::encode(mac))
+    }
+
+    /// Returns the name of the account that `token` was issued for or [`None`] if the token is
+    /// not valid or expired.
+    pub fn verify<'a>(&self, token: &'a str) -> Option<&'a str> {
+        // Check the MAC. The account name may contains '.' so we need to split from the right.
+        let (payload, mac) = token.rsplit_once('.')?;
+        let mac = hex::decode(mac).ok()?;
+
+        self.mac(payload).verify_slice(&mac).ok()?;
+
+        // Check the expiration.
+        let (account, expires) = payload.rsplit_once('.')?;
+        let expires = UNIX_EPOCH + Duration::from_secs(expires.parse().ok()?);
+
+        if expires <= SystemTime::now() {
+            return None;
+        }
+
+        Some(account)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha3_256> {
+        let mut mac = Hmac::<Sha3_256>::new_from_slice(self.key.as_ref()).unwrap();
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Represents an error when [`AccessTokens::load()`] fails.
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("couldn't read {0}")]
+    ReadKeyFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} contains an invalid key")]
+    InvalidKey(PathBuf),
+
+    #[error("couldn't generate a key to sign access tokens")]
+    GenerateKeyFailed(#[source] getrandom::Error),
+
+    #[error("couldn't write {0}")]
+    WriteKeyFailed(PathBuf, #[source] std::io::Error),
+}
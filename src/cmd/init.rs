@@ -1,22 +1,99 @@
 use super::Key;
+use crate::auth::connect;
 use crate::config::AppConfig;
-use crate::key::KeyMgr;
-use clap::{value_parser, Arg, ArgMatches, Command};
-use std::path::PathBuf;
+use crate::home::Home;
+use crate::key::{KeyId, KeyMgr};
+use crate::repo::{DataKey, Keyring, Repo, RepoCreateError};
+use crate::server::{parse_url, ClientError};
+use clap::builder::NonEmptyStringValueParser;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::Arc;
+use url::Url;
 
 /// Command to initialize a new respotiroy.
 pub struct Init {
     config: Arc<AppConfig>,
+    home: Arc<Home>,
     keymgr: Arc<KeyMgr>,
 }
 
 impl Init {
     pub const NAME: &'static str = "init";
 
-    pub fn new(config: Arc<AppConfig>, keymgr: Arc<KeyMgr>) -> Self {
-        Self { config, keymgr }
+    pub fn new(config: Arc<AppConfig>, home: Arc<Home>, keymgr: Arc<KeyMgr>) -> Self {
+        Self {
+            config,
+            home,
+            keymgr,
+        }
+    }
+
+    fn select_key(&self) -> Option<KeyId> {
+        // Use the key if we have only one.
+        let mut keys = Vec::new();
+
+        self.keymgr.for_each_key(|k| keys.push(k.clone()));
+
+        if keys.len() == 1 {
+            return Some(keys[0].id().clone());
+        }
+
+        // Check if we can prompt.
+        let mut stdin = std::io::stdin().lock();
+
+        if !stdin.is_terminal() {
+            eprintln!(
+                "Multiple file encryption keys available, use --key to specify which one to use."
+            );
+            return None;
+        }
+
+        // Prompt.
+        keys.sort_by_key(|k| k.created());
+
+        eprintln!("Multiple file encryption keys available:");
+
+        for (i, k) in keys.iter().enumerate() {
+            eprintln!("{}. {}", i + 1, k.id());
+        }
+
+        loop {
+            let mut line = String::new();
+
+            eprint!(
+                "Select a key to encrypt this directory [1-{}]: ",
+                keys.len()
+            );
+            std::io::stderr().flush().unwrap();
+
+            match stdin.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Failed to read the selection: {}.", e.display());
+                    return None;
+                }
+            }
+
+            match line.trim().parse::<usize>() {
+                Ok(v) if v >= 1 && v <= keys.len() => return Some(keys[v - 1].id().clone()),
+                _ => continue,
+            }
+        }
+    }
+
+    fn delete(repo: Repo, dir: &Path) {
+        if let Err(e) = repo.delete() {
+            eprintln!(
+                "Failed to remove {}: {}.",
+                dir.join(".warp").display(),
+                e.display()
+            );
+        }
     }
 }
 
@@ -32,7 +109,8 @@ impl super::Command for Init {
                 Arg::new("name")
                     .help("Unique name of this directory on the server (default to directory name)")
                     .long("name")
-                    .value_name("NAME"),
+                    .value_name("NAME")
+                    .value_parser(NonEmptyStringValueParser::new()),
             )
             .arg(
                 Arg::new("server")
@@ -41,7 +119,21 @@ impl super::Command for Init {
                         self.config.default_server
                     ))
                     .long("server")
-                    .value_name("URL"),
+                    .value_name("URL")
+                    .value_parser(parse_url),
+            )
+            .arg(
+                Arg::new("key")
+                    .help("ID of the key to encrypt files (default to the only available key)")
+                    .long("key")
+                    .value_name("ID")
+                    .value_parser(|v: &str| v.parse::<KeyId>()),
+            )
+            .arg(
+                Arg::new("gitignore")
+                    .help("Exclude files that match .gitignore in addition to .warpignore")
+                    .long("gitignore")
+                    .action(ArgAction::SetTrue),
             )
             .arg(
                 Arg::new("directory")
@@ -51,13 +143,168 @@ impl super::Command for Init {
             )
     }
 
-    fn exec(&self, _: &ArgMatches) -> ExitCode {
+    fn exec(&self, args: &ArgMatches) -> ExitCode {
         // Check if we have at least one key to encrypt.
         if !self.keymgr.has_keys() {
             eprintln!("No file encryption keys available, invoke Warp with '{} --help' to see how to create a new key.", Key::NAME);
             return ExitCode::FAILURE;
         }
 
-        todo!()
+        // Get target directory.
+        let dir = match args.get_one::<PathBuf>("directory") {
+            Some(v) => v.clone(),
+            None => match std::env::current_dir() {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Failed to get current directory: {}.", e.display());
+                    return ExitCode::FAILURE;
+                }
+            },
+        };
+
+        let dir = match dir.canonicalize() {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to resolve {}: {}.", dir.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Get repository name.
+        let name = match args.get_one::<String>("name") {
+            Some(v) => v.clone(),
+            None => match dir.file_name() {
+                Some(v) => v.to_string_lossy().into_owned(),
+                None => {
+                    eprintln!(
+                        "Couldn't determine the name from {}, use --name to specify it.",
+                        dir.display()
+                    );
+                    return ExitCode::FAILURE;
+                }
+            },
+        };
+
+        // Get the key.
+        let key = match args.get_one::<KeyId>("key") {
+            Some(v) => match self.keymgr.get(v) {
+                Some(v) => v.id().clone(),
+                None => {
+                    eprintln!("Unknown key '{v}'.");
+                    return ExitCode::FAILURE;
+                }
+            },
+            None => match self.select_key() {
+                Some(v) => v,
+                None => return ExitCode::FAILURE,
+            },
+        };
+
+        // Generate a data key and authorize the selected key to access it.
+        let material = match self.keymgr.material(&key) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                eprintln!("Unknown key '{key}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to load key {}: {}.", key, e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let data = match DataKey::generate() {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to generate a data key: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut keyring = Keyring::new(name.as_str(), &data);
+
+        if let Err(e) = keyring.add(&data, &material) {
+            eprintln!("Failed to wrap the data key: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+
+        // Connect to the server.
+        let server = args
+            .get_one::<Url>("server")
+            .unwrap_or(&self.config.default_server);
+        let client = match connect(&self.keymgr, server) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to connect to {server}: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Create the repository.
+        let gitignore = args.get_flag("gitignore");
+        let repo = match Repo::create(&dir, name, server.clone(), data.id(), gitignore) {
+            Ok(v) => v,
+            Err(RepoCreateError::AlreadyExists) => {
+                eprintln!("{} is already a Warp repository.", dir.display());
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to setup {}: {}.", dir.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Register the repository.
+        if let Err(e) = client.create_repo(repo.name()) {
+            match e {
+                ClientError::Conflict => eprintln!(
+                    "Repository {} already exists on {}, use --name to choose a different name.",
+                    repo.name(),
+                    repo.server()
+                ),
+                e => eprintln!(
+                    "Failed to register {} on {}: {}.",
+                    repo.name(),
+                    repo.server(),
+                    e.display()
+                ),
+            }
+
+            Self::delete(repo, &dir);
+            return ExitCode::FAILURE;
+        }
+
+        // Upload the keyring.
+        if let Err(e) = client.put_keyring(repo.name(), &keyring.to_bytes(&data), None) {
+            eprintln!(
+                "Failed to upload the keyring of {} to {}: {}.",
+                repo.name(),
+                repo.server(),
+                e.display()
+            );
+
+            if let Err(e) = client.delete_repo(repo.name()) {
+                eprintln!(
+                    "Failed to remove {} from {}: {}.",
+                    repo.name(),
+                    repo.server(),
+                    e.display()
+                );
+            }
+
+            Self::delete(repo, &dir);
+            return ExitCode::FAILURE;
+        }
+
+        // Remember the repository so we can tell which keys it depends on.
+        if let Err(e) = repo.register(&self.home) {
+            eprintln!(
+                "Failed to add {} to the list of repositories: {}.",
+                dir.display(),
+                e.display()
+            );
+        }
+
+        ExitCode::SUCCESS
     }
 }
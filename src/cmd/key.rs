@@ -1,24 +1,48 @@
+use crate::auth::connect;
 use crate::config::AppConfig;
-use crate::key::KeyMgr;
+use crate::home::Home;
+use crate::key::{
+    key_to_phrase, phrase_to_key, read_passphrase, BackupError, KeyBackup, KeyData, KeyId,
+    KeyMaterial, KeyMgr,
+};
+use crate::repo::{Registry, Repo, RepoLoadError};
+use crate::server::{parse_url, Client, ClientError};
+use crate::sync::{fetch_keyring, rotate, RotateError, UnlockError};
 use clap::builder::NonEmptyStringValueParser;
-use clap::{Arg, ArgMatches, Command};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
 use erdp::ErrorDisplay;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::SystemTime;
 use time::format_description::well_known::Rfc2822;
 use time::{OffsetDateTime, UtcOffset};
+use url::Url;
 
 /// Command to manage file encryption keys.
 pub struct Key {
     config: Arc<AppConfig>,
+    home: Arc<Home>,
     keymgr: Arc<KeyMgr>,
 }
 
 impl Key {
     pub const NAME: &'static str = "key";
 
-    pub fn new(config: Arc<AppConfig>, keymgr: Arc<KeyMgr>) -> Self {
-        Self { config, keymgr }
+    /// Environment variable to read the passphrase of a `.warpkey` file.
+    const PASSPHRASE_VAR: &'static str = "WARP_KEY_PASSPHRASE";
+
+    pub fn new(config: Arc<AppConfig>, home: Arc<Home>, keymgr: Arc<KeyMgr>) -> Self {
+        Self {
+            config,
+            home,
+            keymgr,
+        }
     }
 
     fn exec_new(&self, args: &ArgMatches) -> ExitCode {
@@ -49,19 +73,568 @@ impl Key {
         let mut table = tabled::builder::Builder::new();
         let local = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
 
-        table.push_record(["ID", "Created Date"]);
+        table.push_record(["ID", "Created Date", "Stores"]);
 
         self.keymgr.for_each_key(|key| {
             let id = key.id();
             let created = OffsetDateTime::from(key.created()).to_offset(local);
+            let stores: Vec<&str> = key.stores().collect();
 
-            table.push_record([id.to_string(), created.format(&Rfc2822).unwrap()]);
+            table.push_record([
+                id.to_string(),
+                created.format(&Rfc2822).unwrap(),
+                stores.join(", "),
+            ]);
         });
 
         println!("{}", table.build());
 
         ExitCode::SUCCESS
     }
+
+    fn exec_export(&self, args: &ArgMatches) -> ExitCode {
+        // Load the key.
+        let id: &KeyId = args.get_one("id").unwrap();
+        let key = match self.keymgr.material(id) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                eprintln!("Unknown key '{id}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to load key {}: {}.", id, e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Print the phrase.
+        if args.get_flag("phrase") {
+            eprintln!("Write down the following words in order and keep them in a safe place:");
+            println!("{}", key_to_phrase(&key.key).as_str());
+            return ExitCode::SUCCESS;
+        }
+
+        // Encrypt the key.
+        let path: &PathBuf = args.get_one("output").unwrap();
+        let pass = match read_passphrase(
+            Self::PASSPHRASE_VAR,
+            &format!("Passphrase to protect {}: ", path.display()),
+            true,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to read the passphrase: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let backup = match KeyBackup::new(&key, &pass) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to encrypt key {}: {}.", id, e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Write the file.
+        let mut opts = OpenOptions::new();
+
+        opts.write(true).create_new(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+
+        let mut file = match opts.open(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                eprintln!("{} already exists.", path.display());
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to create {}: {}.", path.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        if let Err(e) = file.write_all(&backup.to_bytes()) {
+            eprintln!("Failed to write {}: {}.", path.display(), e.display());
+            drop(file);
+            std::fs::remove_file(path).unwrap();
+            return ExitCode::FAILURE;
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    fn exec_import(&self, args: &ArgMatches) -> ExitCode {
+        // Read the file.
+        let path: &PathBuf = args.get_one("file").unwrap();
+        let store: &String = args
+            .get_one("store")
+            .unwrap_or(&self.config.key.default_store);
+        let backup = match std::fs::read(path) {
+            Ok(v) => match KeyBackup::from_bytes(&v) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}.", path.display(), e.display());
+                    return ExitCode::FAILURE;
+                }
+            },
+            Err(e) => {
+                eprintln!("Failed to read {}: {}.", path.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let id = backup.id();
+
+        if self
+            .keymgr
+            .get(id)
+            .is_some_and(|k| k.stores().any(|s| s == store))
+        {
+            eprintln!("Key {id} is already in '{store}'.");
+            return ExitCode::FAILURE;
+        }
+
+        // Decrypt the key.
+        let pass = match read_passphrase(
+            Self::PASSPHRASE_VAR,
+            &format!("Passphrase for {}: ", path.display()),
+            false,
+        ) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to read the passphrase: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let key = match backup.decrypt(&pass) {
+            Ok(v) => v,
+            Err(BackupError::WrongPassphrase) => {
+                eprintln!("Wrong passphrase or {} is corrupted.", path.display());
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to decrypt {}: {}.", path.display(), e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Store the key.
+        match self.keymgr.import(store, &key) {
+            Ok(Some(v)) => println!("{}", v.id()),
+            Ok(None) => {
+                eprintln!("Unknown keystore '{store}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to import key {}: {}.", id, e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    fn exec_recover(&self, args: &ArgMatches) -> ExitCode {
+        // Read the phrase.
+        let expected: Option<&KeyId> = args.get_one("id");
+        let store: &String = args
+            .get_one("store")
+            .unwrap_or(&self.config.key.default_store);
+        let phrase = match read_passphrase("WARP_RECOVERY_PHRASE", "Recovery phrase: ", false) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to read the recovery phrase: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let key = match phrase_to_key(&phrase) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Invalid recovery phrase: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Check the key.
+        let id = KeyId::from_key(&key);
+
+        if let Some(v) = expected {
+            if id != *v {
+                eprintln!("The recovery phrase is for key {id}, not {v}.");
+                return ExitCode::FAILURE;
+            }
+        }
+
+        if self
+            .keymgr
+            .get(&id)
+            .is_some_and(|k| k.stores().any(|s| s == store))
+        {
+            eprintln!("Key {id} is already in '{store}'.");
+            return ExitCode::FAILURE;
+        }
+
+        // Store the key. The phrase does not contain the original KeyData so we use the same
+        // algorithms as a new key, which is what every key has been generated with.
+        let key = KeyMaterial {
+            key,
+            data: KeyData::new(SystemTime::now()),
+        };
+
+        match self.keymgr.import(store, &key) {
+            Ok(Some(v)) => println!("{}", v.id()),
+            Ok(None) => {
+                eprintln!("Unknown keystore '{store}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to recover key {}: {}.", id, e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    fn exec_rm(&self, args: &ArgMatches) -> ExitCode {
+        // Check if the key exists.
+        let id: &KeyId = args.get_one("id").unwrap();
+        let store = args.get_one::<String>("store").map(|v| v.as_str());
+        let key = match self.keymgr.get(id) {
+            Some(v) => v,
+            None => {
+                eprintln!("Unknown key '{id}'.");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        if let Some(s) = store {
+            if !key.stores().any(|v| v == s) {
+                eprintln!("Key {id} is not in '{s}'.");
+                return ExitCode::FAILURE;
+            }
+        }
+
+        // Check which repositories depend on the key. No need to check if the key is still in the
+        // other stores after removed.
+        let dependents = if store.is_none() || key.stores().count() == 1 {
+            match self.dependents(id) {
+                Some(v) => v,
+                None => return ExitCode::FAILURE,
+            }
+        } else {
+            Vec::new()
+        };
+
+        if !dependents.is_empty() && !args.get_flag("force") {
+            eprintln!(
+                "The following repositories cannot be read on this computer without key {id}:"
+            );
+
+            for d in &dependents {
+                eprintln!("  {d}");
+            }
+
+            eprintln!("Use --force to remove it anyway.");
+            return ExitCode::FAILURE;
+        }
+
+        // Confirm.
+        if !args.get_flag("yes") && !Self::confirm(id, store, &dependents) {
+            return ExitCode::FAILURE;
+        }
+
+        // Remove.
+        match self.keymgr.delete(id, store) {
+            Ok(Some(_)) => ExitCode::SUCCESS,
+            Ok(None) => {
+                eprintln!("Unknown key '{id}'.");
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("Failed to remove key {}: {}.", id, e.display());
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    /// Returns the repositories that would become unreadable on this computer without `id`, which
+    /// include the repositories that cannot be checked. Returns [`None`] if failed, in which case
+    /// the error has been printed.
+    fn dependents(&self, id: &KeyId) -> Option<Vec<Dependent>> {
+        // Get the repositories on this computer.
+        let mut registry = match Registry::load(&self.home) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to load the list of repositories: {}.", e.display());
+                return None;
+            }
+        };
+
+        let mut repos = BTreeMap::new();
+        let mut dependents = Vec::new();
+        let mut stale = Vec::new();
+
+        for path in registry.repos() {
+            match Repo::load(path) {
+                Ok(v) => {
+                    let key = (v.server().clone(), v.name().to_owned());
+                    repos.insert(key, Some(v));
+                }
+                Err(RepoLoadError::NotWarpRepo) => stale.push(path.to_owned()),
+                Err(e) => dependents.push(Dependent {
+                    repo: path.display().to_string(),
+                    error: Some(e.display().to_string()),
+                }),
+            }
+        }
+
+        if !stale.is_empty() {
+            for path in stale {
+                registry.remove(&path);
+            }
+
+            if let Err(e) = registry.save() {
+                eprintln!(
+                    "Failed to update the list of repositories: {}.",
+                    e.display()
+                );
+            }
+        }
+
+        // Get the repositories on the configured server.
+        let mut clients = BTreeMap::new();
+        let server = &self.config.default_server;
+        let client = match connect(&self.keymgr, server) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to connect to {}: {}.", server, e.display());
+                return None;
+            }
+        };
+
+        if client.is_authenticated() {
+            match client.list_repos() {
+                Ok(v) => {
+                    for r in v {
+                        repos.entry((server.clone(), r.name)).or_insert(None);
+                    }
+                }
+                Err(e) => dependents.push(Dependent {
+                    repo: format!("all repositories on {server}"),
+                    error: Some(e.display().to_string()),
+                }),
+            }
+        }
+
+        clients.insert(server.clone(), client);
+
+        // Check each repository.
+        for ((server, name), local) in repos {
+            let client = match clients.entry(server.clone()) {
+                Entry::Vacant(e) => match connect(&self.keymgr, &server) {
+                    Ok(v) => e.insert(v),
+                    Err(e) => {
+                        eprintln!("Failed to connect to {}: {}.", server, e.display());
+                        return None;
+                    }
+                },
+                Entry::Occupied(e) => e.into_mut(),
+            };
+
+            let path = local.as_ref().map(|v| v.path().to_owned());
+            let error = match Self::is_dependent(&self.keymgr, client, &name, local.as_ref(), id) {
+                Ok(false) => continue,
+                Ok(true) => None,
+                Err(e) => Some(e),
+            };
+
+            dependents.push(Dependent {
+                repo: match path {
+                    Some(p) => format!("{} on {} ({})", name, server, p.display()),
+                    None => format!("{name} on {server}"),
+                },
+                error,
+            });
+        }
+
+        Some(dependents)
+    }
+
+    fn is_dependent(
+        keymgr: &KeyMgr,
+        client: &Client,
+        name: &str,
+        local: Option<&Repo>,
+        id: &KeyId,
+    ) -> Result<bool, String> {
+        match fetch_keyring(client, name) {
+            Ok(Some((keyring, _))) => {
+                Ok(keyring.contains(id)
+                    && !keyring.keys().any(|k| k != id && keymgr.get(k).is_some()))
+            }
+            Ok(None) => match local {
+                Some(v) => Ok(v.key() == id),
+                None => Err(String::from(
+                    "the repository was created before the keyring existed",
+                )),
+            },
+            Err(e) => Err(e.display().to_string()),
+        }
+    }
+
+    fn confirm(id: &KeyId, store: Option<&str>, dependents: &[Dependent]) -> bool {
+        // Check if we can prompt.
+        let mut stdin = std::io::stdin().lock();
+
+        if !stdin.is_terminal() {
+            eprintln!("Use --yes to remove the key without a confirmation.");
+            return false;
+        }
+
+        // Prompt.
+        if !dependents.is_empty() {
+            eprintln!("The following repositories will become unreadable on this computer:");
+
+            for d in dependents {
+                eprintln!("  {d}");
+            }
+        }
+
+        match store {
+            Some(s) => {
+                eprint!("Remove key {id} from '{s}' permanently? This cannot be undone [y/N]: ")
+            }
+            None => eprint!("Remove key {id} permanently? This cannot be undone [y/N]: "),
+        }
+
+        std::io::stderr().flush().unwrap();
+
+        let mut line = String::new();
+
+        match stdin.read_line(&mut line) {
+            Ok(_) => matches!(line.trim(), "y" | "Y" | "yes"),
+            Err(e) => {
+                eprintln!("Failed to read the confirmation: {}.", e.display());
+                false
+            }
+        }
+    }
+
+    fn exec_rotate(&self, args: &ArgMatches) -> ExitCode {
+        // Connect to the server.
+        let repo: &String = args.get_one("repo").unwrap();
+        let to: &KeyId = args.get_one("to").unwrap();
+        let from: Option<Vec<KeyId>> = args.get_many("from").map(|v| v.cloned().collect());
+        let server = args
+            .get_one::<Url>("server")
+            .unwrap_or(&self.config.default_server);
+        let client = match connect(&self.keymgr, server) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to connect to {}: {}.", server, e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Check if the repository exists so a typo does not look like a missing keyring.
+        match client.get_repo(repo) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                eprintln!("Repository {repo} does not exist on {server}.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to get {} from {}: {}.", repo, server, e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+
+        // Rotate.
+        let summary = match rotate(
+            &self.home,
+            &self.keymgr,
+            &client,
+            server,
+            repo,
+            to,
+            from.as_deref(),
+        ) {
+            Ok(v) => v,
+            Err(RotateError::UnknownKey(id)) => {
+                eprintln!("Unknown key '{id}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::RetireTargetKey(id)) => {
+                eprintln!("Key {id} cannot be specified with both '--to' and '--from'.");
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::InProgress(id, from)) => {
+                let from: Vec<String> = from.iter().map(|k| format!("--from {k}")).collect();
+
+                eprintln!("A rotation of {repo} to key {id} was interrupted, run this command with '--to {id} {}' to finish it first.", from.join(" "));
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::NoKeyring) => {
+                eprintln!("Repository {repo} has no keyring, run 'warp repo key add {to}' inside the repository to create it first.");
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::UnlockFailed(UnlockError::NoAuthorizedKey)) => {
+                eprintln!("None of the keys that authorized to access {repo} is available on this computer.");
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::RetiredKey(id)) => {
+                eprintln!("Key {id} has been retired from {repo} and cannot be used again.");
+                return ExitCode::FAILURE;
+            }
+            Err(RotateError::UploadManifestFailed(ClientError::Conflict)) => {
+                eprintln!("The repository has been changed by another computer during the rotation, run this command again to continue.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to rotate the key of {}: {}.", repo, e.display());
+                eprintln!("Run this command again to continue the rotation.");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        eprintln!(
+            "Re-encrypted {} object(s) and removed {} object(s) that encrypted with the old key.",
+            summary.objects, summary.deleted
+        );
+
+        for id in summary.retired {
+            eprintln!("Key {id} has been retired from {repo}.");
+        }
+
+        for id in summary.unavailable {
+            eprintln!("Key {id} is not available on this computer and can no longer access {repo}, import it to this computer then run 'warp repo key add {id}' inside the repository to allow it again.");
+        }
+
+        println!("{}", summary.key);
+
+        ExitCode::SUCCESS
+    }
+}
+
+/// Repository that found by [`Key::dependents()`].
+struct Dependent {
+    repo: String,
+    error: Option<String>,
+}
+
+impl Display for Dependent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            Some(e) => write!(f, "{} (couldn't check: {})", self.repo, e),
+            None => f.write_str(&self.repo),
+        }
+    }
 }
 
 impl super::Command for Key {
@@ -86,12 +659,155 @@ impl super::Command for Key {
                 ),
             )
             .subcommand(Command::new("ls").about("List all available keys"))
+            .subcommand(
+                Command::new("export")
+                    .about("Write a key to a file encrypted with a passphrase or as a recovery phrase")
+                    .arg(
+                        Arg::new("id")
+                            .help("ID of the key to export")
+                            .value_name("ID")
+                            .value_parser(|v: &str| v.parse::<KeyId>())
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("output")
+                            .help("Path to write the key (e.g. key.warpkey)")
+                            .long("output")
+                            .short('o')
+                            .value_name("FILE")
+                            .value_parser(value_parser!(PathBuf))
+                            .required_unless_present("phrase"),
+                    )
+                    .arg(
+                        Arg::new("phrase")
+                            .help("Print the key as a list of words to write down on paper")
+                            .long("phrase")
+                            .action(ArgAction::SetTrue)
+                            .conflicts_with("output"),
+                    ),
+            )
+            .subcommand(
+                Command::new("import")
+                    .about("Import a key from a file that was written by 'export'")
+                    .arg(
+                        Arg::new("file")
+                            .help("Path to the exported key")
+                            .value_name("FILE")
+                            .value_parser(value_parser!(PathBuf))
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("store")
+                            .help(format!(
+                                "Key store to import to (default to '{}')",
+                                self.config.key.default_store
+                            ))
+                            .long("store")
+                            .value_name("ID")
+                            .value_parser(NonEmptyStringValueParser::new()),
+                    ),
+            )
+            .subcommand(
+                Command::new("recover")
+                    .about("Rebuild a key from a phrase that was printed by 'export --phrase'")
+                    .arg(
+                        Arg::new("id")
+                            .help("Expected ID of the key")
+                            .long("id")
+                            .value_name("ID")
+                            .value_parser(|v: &str| v.parse::<KeyId>()),
+                    )
+                    .arg(
+                        Arg::new("store")
+                            .help(format!(
+                                "Key store to put the key (default to '{}')",
+                                self.config.key.default_store
+                            ))
+                            .long("store")
+                            .value_name("ID")
+                            .value_parser(NonEmptyStringValueParser::new()),
+                    ),
+            )
+            .subcommand(
+                Command::new("rm")
+                    .about("Remove a key permanently")
+                    .arg(
+                        Arg::new("id")
+                            .help("ID of the key to remove")
+                            .value_name("ID")
+                            .value_parser(|v: &str| v.parse::<KeyId>())
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("store")
+                            .help("Remove only the copy in this keystore (default to all keystores)")
+                            .long("store")
+                            .value_name("ID")
+                            .value_parser(NonEmptyStringValueParser::new()),
+                    )
+                    .arg(
+                        Arg::new("force")
+                            .help("Remove the key even if some repositories cannot be read without it")
+                            .long("force")
+                            .action(ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("yes")
+                            .help("Do not ask for a confirmation")
+                            .long("yes")
+                            .short('y')
+                            .action(ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("rotate")
+                    .about("Re-encrypt a repository on the server with a new key and retire the specified keys")
+                    .arg(
+                        Arg::new("repo")
+                            .help("Name of the repository on the server")
+                            .long("repo")
+                            .value_name("NAME")
+                            .value_parser(NonEmptyStringValueParser::new())
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("to")
+                            .help("ID of the key to wrap the new data key")
+                            .long("to")
+                            .value_name("ID")
+                            .value_parser(|v: &str| v.parse::<KeyId>())
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("from")
+                            .help("ID of the key to retire from the repository (default to all authorized keys other than '--to')")
+                            .long("from")
+                            .value_name("ID")
+                            .value_parser(|v: &str| v.parse::<KeyId>())
+                            .action(ArgAction::Append),
+                    )
+                    .arg(
+                        Arg::new("server")
+                            .help(format!(
+                                "URL of the server (default to {})",
+                                self.config.default_server
+                            ))
+                            .long("server")
+                            .value_name("URL")
+                            .value_parser(parse_url),
+                    ),
+            )
     }
 
     fn exec(&self, args: &ArgMatches) -> ExitCode {
         match args.subcommand().unwrap() {
             ("new", args) => self.exec_new(args),
             ("ls", args) => self.exec_ls(args),
+            ("export", args) => self.exec_export(args),
+            ("import", args) => self.exec_import(args),
+            ("recover", args) => self.exec_recover(args),
+            ("rm", args) => self.exec_rm(args),
+            ("rotate", args) => self.exec_rotate(args),
             _ => unreachable!(),
         }
     }
@@ -1,33 +1,173 @@
-use crate::key::KeyMgr;
-use clap::{ArgMatches, Command};
+use crate::config::{set_keystore, AppConfig};
+use crate::home::Home;
+use crate::key::{KeyMgr, KeystoreRegistry};
+use clap::builder::NonEmptyStringValueParser;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
 use std::process::ExitCode;
 use std::sync::Arc;
 
 /// Command to manage file encryption keystores.
 pub struct Keystore {
+    config: Arc<AppConfig>,
+    home: Arc<Home>,
     keymgr: Arc<KeyMgr>,
 }
 
 impl Keystore {
     pub const NAME: &'static str = "keystore";
 
-    pub fn new(keymgr: Arc<KeyMgr>) -> Self {
-        Self { keymgr }
+    pub fn new(config: Arc<AppConfig>, home: Arc<Home>, keymgr: Arc<KeyMgr>) -> Self {
+        Self {
+            config,
+            home,
+            keymgr,
+        }
     }
 
     fn ls(&self) -> ExitCode {
         let mut t = tabled::builder::Builder::new();
 
-        t.push_record(["ID"]);
+        t.push_record(["ID", "Type", "Status"]);
+
+        for (id, c) in &self.config.key.effective_stores() {
+            let status = if !c.enabled {
+                "disabled"
+            } else if self.keymgr.stores().any(|s| s.id() == id) {
+                "enabled"
+            } else {
+                "invalid"
+            };
 
-        for s in self.keymgr.stores() {
-            t.push_record([s.id()]);
+            t.push_record([id.as_str(), c.ty.as_str(), status]);
         }
 
         println!("{}", t.build());
 
         ExitCode::SUCCESS
     }
+
+    fn enable(&self, args: &ArgMatches) -> ExitCode {
+        // Get current configurations. A store that is not configured will use its ID as the type
+        // by default so the built-in stores can be enabled by its type.
+        let id: &String = args.get_one("id").unwrap();
+        let ty: Option<&String> = args.get_one("type");
+        let effective = self.config.key.effective_stores();
+        let mut store = match self.configured(id, &effective) {
+            Some(v) => v,
+            None => crate::config::Keystore::new(ty.unwrap_or(id)),
+        };
+
+        store.enabled = true;
+
+        if let Some(v) = ty {
+            store.ty.clone_from(v);
+        }
+
+        for o in args.get_many::<String>("option").into_iter().flatten() {
+            let (k, v) = o.split_once('=').unwrap();
+            let v = serde_yaml::from_str(v).unwrap_or_else(|_| Value::String(v.to_owned()));
+
+            store.options.insert(k.into(), v);
+        }
+
+        // Check if the configurations is valid. A plugin on PATH is checked with the program that
+        // was found.
+        let registry = KeystoreRegistry::default();
+        let mut options = store.options.clone();
+
+        if let Some(c) = effective.get(id).and_then(|s| s.options.get("command")) {
+            if store.ty == KeyMgr::PLUGIN_STORE && !options.contains_key("command") {
+                options.insert("command".into(), c.clone());
+            }
+        }
+
+        if let Err(e) = registry.create(id, &store.ty, &self.home, &options) {
+            eprintln!(
+                "Invalid configuration for keystore '{}': {}.",
+                id,
+                e.display()
+            );
+            return ExitCode::FAILURE;
+        }
+
+        // Update configurations.
+        let path = self.home.config();
+
+        if let Err(e) = set_keystore(&path, &self.config.key, id, &store) {
+            eprintln!("Failed to update {}: {}.", path.display(), e.display());
+            return ExitCode::FAILURE;
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    fn disable(&self, args: &ArgMatches) -> ExitCode {
+        // Get current configurations.
+        let id: &String = args.get_one("id").unwrap();
+        let mut store = match self.configured(id, &self.config.key.effective_stores()) {
+            Some(v) => v,
+            None => {
+                eprintln!("Unknown keystore '{id}'.");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let path = self.home.config();
+
+        if *id == self.config.key.default_store {
+            eprintln!(
+                "Keystore '{}' is the default store, change key.default_store in {} first.",
+                id,
+                path.display()
+            );
+
+            return ExitCode::FAILURE;
+        }
+
+        // Update configurations.
+        store.enabled = false;
+
+        if let Err(e) = set_keystore(&path, &self.config.key, id, &store) {
+            eprintln!("Failed to update {}: {}.", path.display(), e.display());
+            return ExitCode::FAILURE;
+        }
+
+        // Keys in the store are not removed so tell the user they still exists.
+        let mut keys = 0;
+
+        self.keymgr.for_each_key(|k| {
+            if k.stores().all(|s| s == id) {
+                keys += 1;
+            }
+        });
+
+        if keys != 0 {
+            eprintln!(
+                "{keys} key(s) that are only in '{id}' will be available again when it is re-enabled."
+            );
+        }
+
+        ExitCode::SUCCESS
+    }
+
+    /// Returns the configurations of `id` as it is in the configuration file so the `command` that
+    /// was filled from `PATH` is not written back. A plugin that is on `PATH` but not configured
+    /// gets a new configuration.
+    fn configured(
+        &self,
+        id: &str,
+        effective: &BTreeMap<String, crate::config::Keystore>,
+    ) -> Option<crate::config::Keystore> {
+        match self.config.key.stores.get(id) {
+            Some(v) => Some(v.clone()),
+            None => effective
+                .get(id)
+                .map(|v| crate::config::Keystore::new(v.ty.as_str())),
+        }
+    }
 }
 
 impl super::Command for Keystore {
@@ -39,12 +179,55 @@ impl super::Command for Keystore {
         Command::new(Self::NAME)
             .about("Manage file encryption keystores")
             .subcommand_required(true)
-            .subcommand(Command::new("ls").about("List all enabled keystores"))
+            .subcommand(Command::new("ls").about("List all configured keystores"))
+            .subcommand(
+                Command::new("enable")
+                    .about("Enable a keystore or add a new one")
+                    .arg(
+                        Arg::new("id")
+                            .help("ID of the keystore")
+                            .value_name("ID")
+                            .value_parser(NonEmptyStringValueParser::new())
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("type")
+                            .help("Type of the keystore (default to ID for a new keystore)")
+                            .long("type")
+                            .value_name("TYPE")
+                            .value_parser(NonEmptyStringValueParser::new()),
+                    )
+                    .arg(
+                        Arg::new("option")
+                            .help("Type-specific option (e.g. path=/mnt/keys for 'file' type)")
+                            .long("option")
+                            .short('o')
+                            .value_name("KEY=VALUE")
+                            .action(ArgAction::Append)
+                            .value_parser(|v: &str| match v.split_once('=') {
+                                Some((k, _)) if !k.is_empty() => Ok(v.to_owned()),
+                                _ => Err("the value must be in KEY=VALUE form"),
+                            }),
+                    ),
+            )
+            .subcommand(
+                Command::new("disable")
+                    .about("Disable a keystore without removing its keys")
+                    .arg(
+                        Arg::new("id")
+                            .help("ID of the keystore")
+                            .value_name("ID")
+                            .value_parser(NonEmptyStringValueParser::new())
+                            .required(true),
+                    ),
+            )
     }
 
     fn exec(&self, args: &ArgMatches) -> ExitCode {
         match args.subcommand().unwrap() {
             ("ls", _) => self.ls(),
+            ("enable", args) => self.enable(args),
+            ("disable", args) => self.disable(args),
             _ => unreachable!(),
         }
     }
@@ -0,0 +1,172 @@
+use crate::auth::Credentials;
+use crate::config::AppConfig;
+use crate::key::KeyMgr;
+use crate::server::{parse_url, Client, ClientError};
+use clap::builder::NonEmptyStringValueParser;
+use clap::{Arg, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Command to login to a Warp server.
+pub struct Login {
+    config: Arc<AppConfig>,
+    keymgr: Arc<KeyMgr>,
+}
+
+impl Login {
+    pub const NAME: &'static str = "login";
+
+    pub fn new(config: Arc<AppConfig>, keymgr: Arc<KeyMgr>) -> Self {
+        Self { config, keymgr }
+    }
+
+    fn username() -> Option<String> {
+        let mut stdin = std::io::stdin().lock();
+
+        if !stdin.is_terminal() {
+            eprintln!("No terminal to prompt for the username, use --user to specify it.");
+            return None;
+        }
+
+        loop {
+            let mut line = String::new();
+
+            eprint!("Username: ");
+            std::io::stderr().flush().unwrap();
+
+            match stdin.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Failed to read the username: {}.", e.display());
+                    return None;
+                }
+            }
+
+            match line.trim() {
+                "" => continue,
+                v => return Some(v.to_owned()),
+            }
+        }
+    }
+
+    /// Get password from `WARP_PASSWORD` or prompt the user if it is not set.
+    fn password() -> Result<Zeroizing<String>, std::io::Error> {
+        match std::env::var_os("WARP_PASSWORD") {
+            Some(v) => v.into_string().map(Zeroizing::new).map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "WARP_PASSWORD is not a valid UTF-8",
+                )
+            }),
+            None => rpassword::prompt_password("Password: ").map(Zeroizing::new),
+        }
+    }
+}
+
+impl super::Command for Login {
+    fn is_matched(&self, name: &str) -> bool {
+        name == Self::NAME
+    }
+
+    fn definition(&self) -> Command {
+        Command::new(Self::NAME)
+            .about("Login to a Warp server")
+            .arg(
+                Arg::new("server")
+                    .help(format!(
+                        "URL of the server to login (default to {})",
+                        self.config.default_server
+                    ))
+                    .long("server")
+                    .value_name("URL")
+                    .value_parser(parse_url),
+            )
+            .arg(
+                Arg::new("user")
+                    .help("Name of the account (default to prompt)")
+                    .long("user")
+                    .value_name("NAME")
+                    .value_parser(NonEmptyStringValueParser::new()),
+            )
+            .arg(
+                Arg::new("store")
+                    .help(format!(
+                        "Key store to keep the credentials (default to '{}')",
+                        self.config.key.default_store
+                    ))
+                    .long("store")
+                    .value_name("ID")
+                    .value_parser(NonEmptyStringValueParser::new()),
+            )
+    }
+
+    fn exec(&self, args: &ArgMatches) -> ExitCode {
+        // Get the credentials.
+        let server = args
+            .get_one::<Url>("server")
+            .unwrap_or(&self.config.default_server);
+        let username = match args.get_one::<String>("user") {
+            Some(v) => v.clone(),
+            None => match Self::username() {
+                Some(v) => v,
+                None => return ExitCode::FAILURE,
+            },
+        };
+
+        let password = match Self::password() {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to get the password: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Login.
+        let client = match Client::new(server.clone()) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Invalid server {server}: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+        let session = match client.login(&username, &password) {
+            Ok(v) => v,
+            Err(ClientError::Unauthorized) => {
+                eprintln!("Invalid username or password.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to login to {server}: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Store the refresh token.
+        let store: &String = args
+            .get_one("store")
+            .unwrap_or(&self.config.key.default_store);
+        let credentials = Credentials {
+            account: session.account,
+            refresh_token: session.refresh_token,
+        };
+
+        if let Err(e) = credentials.save(&self.keymgr, store, server) {
+            eprintln!("Failed to store the credentials: {}.", e.display());
+
+            if let Err(e) = client.logout(&credentials.refresh_token) {
+                eprintln!("Failed to end the session: {}.", e.display());
+            }
+
+            return ExitCode::FAILURE;
+        }
+
+        eprintln!("Logged in to {} as {}.", server, credentials.account);
+
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,83 @@
+use crate::auth::Credentials;
+use crate::config::AppConfig;
+use crate::key::KeyMgr;
+use crate::server::{parse_url, Client};
+use clap::{Arg, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::process::ExitCode;
+use std::sync::Arc;
+use url::Url;
+
+/// Command to logout from a Warp server.
+pub struct Logout {
+    config: Arc<AppConfig>,
+    keymgr: Arc<KeyMgr>,
+}
+
+impl Logout {
+    pub const NAME: &'static str = "logout";
+
+    pub fn new(config: Arc<AppConfig>, keymgr: Arc<KeyMgr>) -> Self {
+        Self { config, keymgr }
+    }
+}
+
+impl super::Command for Logout {
+    fn is_matched(&self, name: &str) -> bool {
+        name == Self::NAME
+    }
+
+    fn definition(&self) -> Command {
+        Command::new(Self::NAME)
+            .about("Logout from a Warp server")
+            .arg(
+                Arg::new("server")
+                    .help(format!(
+                        "URL of the server to logout (default to {})",
+                        self.config.default_server
+                    ))
+                    .long("server")
+                    .value_name("URL")
+                    .value_parser(parse_url),
+            )
+    }
+
+    fn exec(&self, args: &ArgMatches) -> ExitCode {
+        // Load the credentials.
+        let server = args
+            .get_one::<Url>("server")
+            .unwrap_or(&self.config.default_server);
+        let credentials = match Credentials::load(&self.keymgr, server) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                eprintln!("Not logged in to {server}.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to load the credentials: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // End the session on the server. We still need to remove the local credentials if this
+        // failed otherwise the user will not be able to logout when the server is unreachable.
+        match Client::new(server.clone()) {
+            Ok(client) => {
+                if let Err(e) = client.logout(&credentials.refresh_token) {
+                    eprintln!("Failed to end the session on {server}: {}.", e.display());
+                }
+            }
+            Err(e) => eprintln!("Failed to end the session on {server}: {}.", e.display()),
+        }
+
+        // Remove the credentials.
+        if let Err(e) = Credentials::delete(&self.keymgr, server) {
+            eprintln!("Failed to remove the credentials: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+
+        eprintln!("Logged out from {server}.");
+
+        ExitCode::SUCCESS
+    }
+}
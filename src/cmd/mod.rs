@@ -1,11 +1,23 @@
 pub use self::init::*;
 pub use self::key::*;
 pub use self::keystore::*;
+pub use self::login::*;
+pub use self::logout::*;
+pub use self::repo::*;
+pub use self::whoami::*;
+use crate::repo::RepoLoadError;
+use dirs::home_dir;
+use erdp::ErrorDisplay;
+use std::path::PathBuf;
 use std::process::ExitCode;
 
 mod init;
 mod key;
 mod keystore;
+mod login;
+mod logout;
+mod repo;
+mod whoami;
 
 /// A single command passed from a command line argument.
 pub trait Command {
@@ -13,3 +25,52 @@ pub trait Command {
     fn definition(&self) -> clap::Command;
     fn exec(&self, args: &clap::ArgMatches) -> ExitCode;
 }
+
+/// Load the repository from `WARP_DIR` or the one that contains the current directory. Returns
+/// [`None`] if the repository couldn't be loaded, in which case the error has been printed.
+pub fn find_repo() -> Option<crate::repo::Repo> {
+    // Use WARP_DIR if specified.
+    if let Some(v) = std::env::var_os("WARP_DIR") {
+        let path = PathBuf::from(v);
+
+        return match crate::repo::Repo::load(&path) {
+            Ok(v) => Some(v),
+            Err(RepoLoadError::NotWarpRepo) => {
+                eprintln!(
+                    "{} (from WARP_DIR) is not a Warp repository.",
+                    path.display()
+                );
+                None
+            }
+            Err(e) => {
+                eprintln!("Failed to load {}: {}.", path.display(), e.display());
+                None
+            }
+        };
+    }
+
+    // Find from the current directory.
+    let path = match std::env::current_dir() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Failed to get current directory: {}.", e.display());
+            return None;
+        }
+    };
+
+    match crate::repo::Repo::find(&path, home_dir().as_deref()) {
+        Ok(v) => Some(v),
+        Err(RepoLoadError::NotWarpRepo) => {
+            eprintln!("{} is not inside a Warp repository, invoke Warp with '{} --help' to see how to setup a new repository.", path.display(), Init::NAME);
+            None
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to load a repository for {}: {}.",
+                path.display(),
+                e.display()
+            );
+            None
+        }
+    }
+}
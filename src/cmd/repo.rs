@@ -0,0 +1,281 @@
+use super::find_repo;
+use crate::auth::connect;
+use crate::key::{KeyId, KeyMgr};
+use crate::repo::{DataKey, Keyring};
+use crate::server::{Client, ClientError, Revision};
+use crate::sync::{fetch_keyring, unlock, UnlockError};
+use clap::{Arg, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+/// Command to manage the repository in the current directory.
+pub struct Repo {
+    keymgr: Arc<KeyMgr>,
+}
+
+impl Repo {
+    pub const NAME: &'static str = "repo";
+
+    pub fn new(keymgr: Arc<KeyMgr>) -> Self {
+        Self { keymgr }
+    }
+
+    fn key_ls(&self) -> ExitCode {
+        // Get the keyring.
+        let Opened { repo, keyring, .. } = match self.open() {
+            Some(v) => v,
+            None => return ExitCode::FAILURE,
+        };
+
+        let keys: Vec<(&KeyId, &str)> = match &keyring {
+            Some((v, _)) => v
+                .keys()
+                .map(|k| (k, "authorized"))
+                .chain(v.retired().map(|k| (k, "retired")))
+                .collect(),
+            None => vec![(repo.key(), "authorized")],
+        };
+
+        // List the keys.
+        let mut t = tabled::builder::Builder::new();
+
+        t.push_record(["ID", "Status", "Available"]);
+
+        for (k, status) in keys {
+            let available = match self.keymgr.get(k) {
+                Some(_) => "yes",
+                None => "no",
+            };
+
+            t.push_record([k.to_string(), status.to_owned(), available.to_owned()]);
+        }
+
+        println!("{}", t.build());
+
+        ExitCode::SUCCESS
+    }
+
+    fn key_add(&self, args: &ArgMatches) -> ExitCode {
+        // Load the key to authorize.
+        let id: &KeyId = args.get_one("id").unwrap();
+        let key = match self.keymgr.material(id) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                eprintln!("Unknown key '{id}'.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to load key {}: {}.", id, e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Get the data key.
+        let Opened {
+            repo,
+            client,
+            keyring,
+        } = match self.open() {
+            Some(v) => v,
+            None => return ExitCode::FAILURE,
+        };
+
+        let data = match unlock(&repo, &self.keymgr, keyring.as_ref().map(|v| &v.0)) {
+            Ok(v) => v,
+            Err(UnlockError::NoAuthorizedKey) => {
+                eprintln!("None of the keys that authorized to access this repository is available on this computer.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to unlock the repository: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let (mut keyring, revision) = match keyring {
+            Some((k, r)) => (k, Some(r)),
+            None => match Keyring::legacy(repo.name(), &data) {
+                Ok(v) => (v, None),
+                Err(e) => {
+                    eprintln!("Failed to create a keyring: {}.", e.display());
+                    return ExitCode::FAILURE;
+                }
+            },
+        };
+
+        // Authorize the key.
+        if keyring.is_retired(id) {
+            eprintln!(
+                "Key {id} has been retired from this repository and cannot be authorized again."
+            );
+            return ExitCode::FAILURE;
+        }
+
+        match keyring.add(&data, &key) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("Key {id} is already authorized to access this repository.");
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("Failed to wrap the data key: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        }
+
+        Self::save(&client, repo.name(), &keyring, &data, revision.as_ref())
+    }
+
+    fn key_rm(&self, args: &ArgMatches) -> ExitCode {
+        // Get the keyring.
+        let id: &KeyId = args.get_one("id").unwrap();
+        let Opened {
+            repo,
+            client,
+            keyring,
+        } = match self.open() {
+            Some(v) => v,
+            None => return ExitCode::FAILURE,
+        };
+
+        // The files are encrypted directly with the key if the repository was created before the
+        // keyring existed and has never been rotated.
+        if id == repo.key() && !keyring.as_ref().is_some_and(|v| v.0.replaces(id)) {
+            eprintln!("Key {id} is used to encrypt the files in this repository directly so it cannot be revoked without re-encrypting the files.");
+            return ExitCode::FAILURE;
+        }
+
+        let (mut keyring, revision) = match keyring {
+            Some(v) => v,
+            None => {
+                eprintln!("Key {id} is not authorized to access this repository.");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // The keyring need to be authenticated with the data key after it is modified.
+        let data = match unlock(&repo, &self.keymgr, Some(&keyring)) {
+            Ok(v) => v,
+            Err(UnlockError::NoAuthorizedKey) => {
+                eprintln!("None of the keys that authorized to access this repository is available on this computer.");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to unlock the repository: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        // Revoke the key.
+        if keyring.keys().count() == 1 && keyring.contains(id) {
+            eprintln!("Key {id} is the only key that can access this repository.");
+            return ExitCode::FAILURE;
+        }
+
+        if !keyring.remove(id) {
+            eprintln!("Key {id} is not authorized to access this repository.");
+            return ExitCode::FAILURE;
+        }
+
+        Self::save(&client, repo.name(), &keyring, &data, Some(&revision))
+    }
+
+    /// Load the repository and its keyring. Returns [`None`] if failed, in which case the error
+    /// has been printed.
+    fn open(&self) -> Option<Opened> {
+        let repo = find_repo()?;
+        let client = match connect(&self.keymgr, repo.server()) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to connect to {}: {}.", repo.server(), e.display());
+                return None;
+            }
+        };
+
+        match fetch_keyring(&client, repo.name()) {
+            Ok(keyring) => Some(Opened {
+                repo,
+                client,
+                keyring,
+            }),
+            Err(e) => {
+                eprintln!("Failed to get the keyring: {}.", e.display());
+                None
+            }
+        }
+    }
+
+    fn save(
+        client: &Client,
+        repo: &str,
+        keyring: &Keyring,
+        data: &DataKey,
+        base: Option<&Revision>,
+    ) -> ExitCode {
+        match client.put_keyring(repo, &keyring.to_bytes(data), base) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(ClientError::Conflict) => {
+                eprintln!("Failed to update the keyring: the keyring has been changed by another computer, please try again.");
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("Failed to update the keyring: {}.", e.display());
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+/// Repository that loaded by [`Repo::open()`].
+struct Opened {
+    repo: crate::repo::Repo,
+    client: Client,
+    keyring: Option<(Keyring, Revision)>,
+}
+
+impl super::Command for Repo {
+    fn is_matched(&self, name: &str) -> bool {
+        name == Self::NAME
+    }
+
+    fn definition(&self) -> Command {
+        let id = Arg::new("id")
+            .help("ID of the key")
+            .value_name("ID")
+            .value_parser(|v: &str| v.parse::<KeyId>())
+            .required(true);
+
+        Command::new(Self::NAME)
+            .about("Manage the repository that contains the current directory")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("key")
+                    .about("Manage the keys that authorized to access the repository")
+                    .subcommand_required(true)
+                    .subcommand(Command::new("ls").about("List all authorized keys"))
+                    .subcommand(
+                        Command::new("add")
+                            .about("Authorize a key to access the repository")
+                            .arg(id.clone()),
+                    )
+                    .subcommand(
+                        Command::new("rm")
+                            .about("Revoke the access of a key (files are not re-encrypted, use 'warp key rotate' for that)")
+                            .arg(id),
+                    ),
+            )
+    }
+
+    fn exec(&self, args: &ArgMatches) -> ExitCode {
+        match args.subcommand().unwrap() {
+            ("key", args) => match args.subcommand().unwrap() {
+                ("ls", _) => self.key_ls(),
+                ("add", args) => self.key_add(args),
+                ("rm", args) => self.key_rm(args),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::Login;
+use crate::auth::{connect, Credentials};
+use crate::config::AppConfig;
+use crate::key::KeyMgr;
+use crate::server::{parse_url, ClientError};
+use clap::{Arg, ArgMatches, Command};
+use erdp::ErrorDisplay;
+use std::process::ExitCode;
+use std::sync::Arc;
+use url::Url;
+
+/// Command to show the account on a Warp server.
+pub struct Whoami {
+    config: Arc<AppConfig>,
+    keymgr: Arc<KeyMgr>,
+}
+
+impl Whoami {
+    pub const NAME: &'static str = "whoami";
+
+    pub fn new(config: Arc<AppConfig>, keymgr: Arc<KeyMgr>) -> Self {
+        Self { config, keymgr }
+    }
+}
+
+impl super::Command for Whoami {
+    fn is_matched(&self, name: &str) -> bool {
+        name == Self::NAME
+    }
+
+    fn definition(&self) -> Command {
+        Command::new(Self::NAME)
+            .about("Show the account that currently logged in")
+            .arg(
+                Arg::new("server")
+                    .help(format!(
+                        "URL of the server to check (default to {})",
+                        self.config.default_server
+                    ))
+                    .long("server")
+                    .value_name("URL")
+                    .value_parser(parse_url),
+            )
+    }
+
+    fn exec(&self, args: &ArgMatches) -> ExitCode {
+        // Check if we have the credentials. WARP_TOKEN does not need to login.
+        let server = args
+            .get_one::<Url>("server")
+            .unwrap_or(&self.config.default_server);
+
+        if std::env::var_os("WARP_TOKEN").is_none() {
+            match Credentials::load(&self.keymgr, server) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    eprintln!("Not logged in to {server}.");
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("Failed to load the credentials: {}.", e.display());
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        // Ask the server.
+        let client = match connect(&self.keymgr, server) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("Failed to connect to {server}: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let account = match client.whoami() {
+            Ok(v) => v,
+            Err(ClientError::Unauthorized) => {
+                eprintln!(
+                    "The session on {server} has expired or been revoked, invoke Warp with '{}' to login again.",
+                    Login::NAME
+                );
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Failed to get the account from {server}: {}.", e.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        println!("{account} on {server}");
+
+        ExitCode::SUCCESS
+    }
+}
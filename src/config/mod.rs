@@ -1,5 +1,9 @@
-use crate::key::KeyMgr;
-use serde::Deserialize;
+use crate::key::{find_plugins, KeyMgr};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
 use url::Url;
 
 /// Application configurations.
@@ -24,12 +28,152 @@ impl Default for AppConfig {
 #[serde(default)]
 pub struct Key {
     pub default_store: String,
+    pub stores: BTreeMap<String, Keystore>,
+    /// ID of the stores to load the key from first when the key is in multiple stores. The stores
+    /// that are not in this list are used after these in order of their ID.
+    pub priority: Vec<String>,
 }
 
 impl Default for Key {
     fn default() -> Self {
+        let stores = [KeyMgr::DEFAULT_STORE, KeyMgr::FILE_STORE]
+            .into_iter()
+            .map(|t| (t.to_owned(), Keystore::new(t)))
+            .collect();
+
         Self {
             default_store: String::from(KeyMgr::DEFAULT_STORE),
+            stores,
+            priority: Vec::new(),
         }
     }
 }
+
+impl Key {
+    /// Returns [`Key::stores`] plus the keystore plugins on `PATH` that are not configured, which
+    /// are enabled by default.
+    ///
+    /// `PATH` is scanned only once and the `command` option of each plugin store without it is set
+    /// to the program that was found.
+    pub fn effective_stores(&self) -> BTreeMap<String, Keystore> {
+        let mut stores = self.stores.clone();
+
+        for (name, path) in find_plugins() {
+            let store = stores
+                .entry(name)
+                .or_insert_with(|| Keystore::new(KeyMgr::PLUGIN_STORE));
+
+            if store.ty != KeyMgr::PLUGIN_STORE || store.options.contains_key("command") {
+                continue;
+            }
+
+            if let Ok(v) = serde_yaml::to_value(path) {
+                store.options.insert("command".into(), v);
+            }
+        }
+
+        stores
+    }
+}
+
+/// Configurations for a keystore in [`Key::stores`].
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(
+        default = "Keystore::default_enabled",
+        skip_serializing_if = "Keystore::is_enabled"
+    )]
+    pub enabled: bool,
+    /// Type-specific options.
+    #[serde(flatten)]
+    pub options: Mapping,
+}
+
+impl Keystore {
+    pub fn new(ty: impl Into<String>) -> Self {
+        Self {
+            ty: ty.into(),
+            enabled: true,
+            options: Mapping::new(),
+        }
+    }
+
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn is_enabled(v: &bool) -> bool {
+        *v
+    }
+}
+
+/// Set `key.stores.<id>` in the configuration file at `path` to `store`.
+///
+/// Other settings in the file keep their values but the file is re-serialized, so any comments and
+/// formatting in it are lost. If the file does not have `key.stores` it will be populated from
+/// `current` first so the other stores remain enabled.
+pub fn set_keystore(
+    path: &Path,
+    current: &Key,
+    id: &str,
+    store: &Keystore,
+) -> Result<(), ConfigError> {
+    // Load the file.
+    let mut root = match std::fs::read_to_string(path) {
+        Ok(v) => match serde_yaml::from_str::<Value>(&v) {
+            Ok(Value::Null) => Mapping::new(),
+            Ok(Value::Mapping(v)) => v,
+            Ok(_) => return Err(ConfigError::NotMapping(path.to_owned(), "the root")),
+            Err(e) => return Err(ConfigError::ParseFailed(path.to_owned(), e)),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Mapping::new(),
+        Err(e) => return Err(ConfigError::ReadFailed(path.to_owned(), e)),
+    };
+
+    // Get key.stores.
+    let key = match root
+        .entry("key".into())
+        .or_insert_with(|| Value::Mapping(Mapping::new()))
+    {
+        Value::Mapping(v) => v,
+        _ => return Err(ConfigError::NotMapping(path.to_owned(), "key")),
+    };
+
+    let stores = match key
+        .entry("stores".into())
+        .or_insert_with(|| serde_yaml::to_value(&current.stores).unwrap())
+    {
+        Value::Mapping(v) => v,
+        _ => return Err(ConfigError::NotMapping(path.to_owned(), "key.stores")),
+    };
+
+    stores.insert(id.into(), serde_yaml::to_value(store).unwrap());
+
+    // Write the file.
+    let tmp = path.with_extension("tmp");
+    let data = serde_yaml::to_string(&root).unwrap();
+
+    if let Err(e) = std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, path)) {
+        return Err(ConfigError::WriteFailed(path.to_owned(), e));
+    }
+
+    Ok(())
+}
+
+/// Represents an error when the configuration file cannot be updated.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("couldn't read {0}")]
+    ReadFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't parse {0}")]
+    ParseFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("{1} of {0} is not a mapping")]
+    NotMapping(PathBuf, &'static str),
+
+    #[error("couldn't write {0}")]
+    WriteFailed(PathBuf, #[source] std::io::Error),
+}
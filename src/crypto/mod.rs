@@ -0,0 +1,402 @@
+pub use self::stream::*;
+use crate::key::{Encryption, KeyData, KeyDerivation, KeyId, Mac};
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use ctr::Ctr128BE;
+use getrandom::getrandom;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac as _};
+use sha3::Sha3_256;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+mod stream;
+
+/// Encrypt and decrypt data with a key using the algorithms specified in its [`KeyData`].
+///
+/// Each encrypted data has the following layout:
+///
+/// | Offset | Size | Description |
+/// |--------|------|-------------|
+/// | 0      | 1    | Format version. |
+/// | 1      | 1    | ID of [`KeyDerivation`]. |
+/// | 2      | 1    | ID of [`Encryption`]. |
+/// | 3      | 1    | ID of [`Mac`]. |
+/// | 4      | 32   | Salt to derive the subkeys. |
+/// | 36     | -    | Ciphertext. |
+/// | -      | -    | MAC of everything before it. |
+pub struct Cipher {
+    id: KeyId,
+    key: Zeroizing<[u8; 16]>,
+    kdf: KeyDerivation,
+    enc: Encryption,
+    mac: Mac,
+}
+
+impl Cipher {
+    pub const VERSION: u8 = 1;
+
+    const SALT_LEN: usize = 32;
+    const HEADER_LEN: usize = 4 + Self::SALT_LEN;
+
+    /// Returns [`CipherError::MacRequired`] if `data` has no MAC since the data encrypted without
+    /// it cannot be authenticated.
+    pub fn new(key: &[u8; 16], data: &KeyData) -> Result<Self, CipherError> {
+        let mac = data.mac.ok_or(CipherError::MacRequired)?;
+
+        Ok(Self {
+            id: KeyId::from_key(key),
+            key: Zeroizing::new(*key),
+            kdf: data.kdf,
+            enc: data.enc,
+            mac,
+        })
+    }
+
+    pub fn id(&self) -> &KeyId {
+        &self.id
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        // Generate a salt.
+        let mut salt = [0u8; Self::SALT_LEN];
+
+        if let Err(e) = getrandom(&mut salt) {
+            return Err(EncryptError::GenerateSaltFailed(e));
+        }
+
+        // Write header.
+        let mut buf = Vec::with_capacity(Self::HEADER_LEN + data.len() + self.mac_len());
+
+        buf.push(Self::VERSION);
+        buf.push(self.kdf.id());
+        buf.push(self.enc.id());
+        buf.push(self.mac.id());
+        buf.extend_from_slice(&salt);
+
+        // Encrypt.
+        let keys = self.derive(&salt, b"warp-data");
+
+        buf.extend_from_slice(data);
+        keys.apply_keystream(0, &mut buf[Self::HEADER_LEN..]);
+
+        // Append MAC.
+        let mac = keys.mac(&[&buf]);
+
+        buf.extend_from_slice(&mac);
+
+        Ok(buf)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        // Check header.
+        let mac_len = self.mac_len();
+
+        if data.len() < Self::HEADER_LEN + mac_len {
+            return Err(DecryptError::Truncated);
+        }
+
+        if data[0] != Self::VERSION {
+            return Err(DecryptError::UnsupportedVersion(data[0]));
+        }
+
+        if data[1] != self.kdf.id() || data[2] != self.enc.id() || data[3] != self.mac.id() {
+            return Err(DecryptError::AlgorithmMismatched);
+        }
+
+        // Verify MAC.
+        let (data, tag) = data.split_at(data.len() - mac_len);
+        let keys = self.derive(&data[4..Self::HEADER_LEN], b"warp-data");
+
+        if !keys.verify(&[data], tag) {
+            return Err(DecryptError::AuthenticationFailed);
+        }
+
+        // Decrypt.
+        let mut buf = data[Self::HEADER_LEN..].to_vec();
+
+        keys.apply_keystream(0, &mut buf);
+
+        Ok(buf)
+    }
+
+    /// Compute an ID for the content with `hash`, which is a SHA3-256 of the content.
+    ///
+    /// The ID is a keyed hash so it is not possible to tell which content it was computed from
+    /// without the key.
+    pub fn object_id(&self, hash: &[u8; 32]) -> [u8; 32] {
+        let mut mac = self.keyed_hash(b"warp-object-id");
+
+        mac.update(hash);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Compute a tag to authenticate the content of a keyring that contains this key.
+    ///
+    /// Like [`Cipher::object_id()`] this uses a dedicated subkey so the tag cannot be confused with
+    /// the MAC of the encrypted data.
+    pub fn keyring_tag(&self, body: &[u8]) -> [u8; 32] {
+        let mut mac = self.keyed_hash(b"warp-keyring");
+
+        mac.update(body);
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Returns `true` if `tag` was computed by [`Cipher::keyring_tag()`] for `body`.
+    pub fn verify_keyring_tag(&self, body: &[u8], tag: &[u8; 32]) -> bool {
+        let mut mac = self.keyed_hash(b"warp-keyring");
+
+        mac.update(body);
+        mac.verify_slice(tag).is_ok()
+    }
+
+    /// Create a keyed hash with a subkey for `info`.
+    fn keyed_hash(&self, info: &[u8]) -> Hmac<Sha3_256> {
+        let mut key = Zeroizing::new([0u8; 32]);
+
+        match self.kdf {
+            KeyDerivation::HkdfSha3256 => Hkdf::<Sha3_256>::new(None, self.key.as_ref())
+                .expand(info, key.as_mut())
+                .unwrap(),
+        }
+
+        Hmac::<Sha3_256>::new_from_slice(key.as_ref()).unwrap()
+    }
+
+    fn mac_len(&self) -> usize {
+        match self.mac {
+            Mac::HmacSha3256 => 32,
+        }
+    }
+
+    /// Derive subkeys from the master key.
+    fn derive(&self, salt: &[u8], info: &[u8]) -> Subkeys {
+        let mut enc = Zeroizing::new([0u8; 16]);
+        let mut iv = [0u8; 16];
+        let mut mac = Zeroizing::new([0u8; 32]);
+
+        match self.kdf {
+            KeyDerivation::HkdfSha3256 => {
+                let hkdf = Hkdf::<Sha3_256>::new(Some(salt), self.key.as_ref());
+                let mut okm = Zeroizing::new([0u8; 64]);
+
+                hkdf.expand(info, okm.as_mut()).unwrap();
+
+                enc.copy_from_slice(&okm[..16]);
+                iv.copy_from_slice(&okm[16..32]);
+                mac.copy_from_slice(&okm[32..]);
+            }
+        }
+
+        Subkeys {
+            enc: self.enc,
+            mac: self.mac,
+            enc_key: enc,
+            iv,
+            mac_key: mac,
+        }
+    }
+}
+
+/// Keys derived from the master key for a single encryption.
+struct Subkeys {
+    enc: Encryption,
+    mac: Mac,
+    enc_key: Zeroizing<[u8; 16]>,
+    iv: [u8; 16],
+    mac_key: Zeroizing<[u8; 32]>,
+}
+
+impl Subkeys {
+    /// `pos` is the position of `buf` in the keystream.
+    fn apply_keystream(&self, pos: u64, buf: &mut [u8]) {
+        match self.enc {
+            Encryption::AesCtr128 => {
+                let key = self.enc_key.as_ref().into();
+                let mut cipher = Ctr128BE::<Aes128>::new(key, (&self.iv).into());
+
+                cipher.seek(pos);
+                cipher.apply_keystream(buf);
+            }
+        }
+    }
+
+    fn mac(&self, data: &[&[u8]]) -> Vec<u8> {
+        match self.mac {
+            Mac::HmacSha3256 => {
+                let mut mac = Hmac::<Sha3_256>::new_from_slice(self.mac_key.as_ref()).unwrap();
+
+                for d in data {
+                    mac.update(d);
+                }
+
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn verify(&self, data: &[&[u8]], tag: &[u8]) -> bool {
+        match self.mac {
+            Mac::HmacSha3256 => {
+                let mut mac = Hmac::<Sha3_256>::new_from_slice(self.mac_key.as_ref()).unwrap();
+
+                for d in data {
+                    mac.update(d);
+                }
+
+                mac.verify_slice(tag).is_ok()
+            }
+        }
+    }
+}
+
+/// Represents an error when [`Cipher::new()`] fails.
+#[derive(Debug, Error)]
+pub enum CipherError {
+    #[error("key without a MAC is not supported")]
+    MacRequired,
+}
+
+/// Represents an error when [`Cipher::encrypt()`] or [`EncryptWriter`] fails.
+#[derive(Debug, Error)]
+pub enum EncryptError {
+    #[error("the key is not supported")]
+    UnsupportedKey(#[from] CipherError),
+
+    #[error("couldn't generate a salt")]
+    GenerateSaltFailed(#[source] getrandom::Error),
+
+    #[error("couldn't write the encrypted data")]
+    WriteFailed(#[source] std::io::Error),
+}
+
+/// Represents an error when [`Cipher::decrypt()`] or [`DecryptReader`] fails.
+#[derive(Debug, Error)]
+pub enum DecryptError {
+    #[error("the key is not supported")]
+    UnsupportedKey(#[from] CipherError),
+
+    #[error("couldn't read the encrypted data")]
+    ReadFailed(#[source] std::io::Error),
+
+    #[error("data is truncated")]
+    Truncated,
+
+    #[error("format version {0} is not supported")]
+    UnsupportedVersion(u8),
+
+    #[error("data was encrypted with different algorithms")]
+    AlgorithmMismatched,
+
+    #[error("data is not a Warp stream")]
+    NotWarpStream,
+
+    #[error("data was encrypted with a different key")]
+    KeyMismatched,
+
+    #[error("chunk size {0} is not valid")]
+    InvalidChunkSize(u32),
+
+    #[error("data has been modified or encrypted with a different key")]
+    AuthenticationFailed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn cipher(key: u8) -> Cipher {
+        Cipher::new(&[key; 16], &KeyData::new(SystemTime::now())).unwrap()
+    }
+
+    fn flip(data: &[u8], i: usize) -> Vec<u8> {
+        let mut data = data.to_vec();
+
+        data[i] ^= 1;
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = cipher(1);
+        let data = cipher.encrypt(b"hello").unwrap();
+
+        assert_eq!(data.len(), Cipher::HEADER_LEN + 5 + 32);
+        assert_eq!(cipher.decrypt(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn mac_required() {
+        let mut data = KeyData::new(SystemTime::now());
+
+        data.mac = None;
+
+        assert!(matches!(
+            Cipher::new(&[1; 16], &data),
+            Err(CipherError::MacRequired)
+        ));
+    }
+
+    #[test]
+    fn tampered_header() {
+        let cipher = cipher(1);
+        let data = cipher.encrypt(b"hello").unwrap();
+
+        assert!(matches!(
+            cipher.decrypt(&flip(&data, 0)),
+            Err(DecryptError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            cipher.decrypt(&flip(&data, 3)),
+            Err(DecryptError::AlgorithmMismatched)
+        ));
+        assert!(matches!(
+            cipher.decrypt(&flip(&data, 4)),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn tampered_ciphertext() {
+        let cipher = cipher(1);
+        let data = cipher.encrypt(b"hello").unwrap();
+
+        assert!(matches!(
+            cipher.decrypt(&flip(&data, Cipher::HEADER_LEN)),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn tampered_tag() {
+        let cipher = cipher(1);
+        let data = cipher.encrypt(b"hello").unwrap();
+
+        assert!(matches!(
+            cipher.decrypt(&flip(&data, data.len() - 1)),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let cipher = cipher(1);
+        let data = cipher.encrypt(b"").unwrap();
+
+        assert!(matches!(
+            cipher.decrypt(&data[..(data.len() - 1)]),
+            Err(DecryptError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let data = cipher(1).encrypt(b"hello").unwrap();
+
+        assert!(matches!(
+            cipher(2).decrypt(&data),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+}
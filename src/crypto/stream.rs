@@ -0,0 +1,447 @@
+use super::{Cipher, DecryptError, EncryptError, Subkeys};
+use getrandom::getrandom;
+use std::cmp::min;
+use std::io::{Read, Write};
+
+/// Magic of an encrypted stream.
+const MAGIC: &[u8; 4] = b"WARP";
+
+/// Size of the stream header.
+///
+/// | Offset | Size | Description |
+/// |--------|------|-------------|
+/// | 0      | 4    | Magic (`WARP`). |
+/// | 4      | 1    | Format version. |
+/// | 5      | 16   | ID of the key. |
+/// | 21     | 1    | ID of [`KeyDerivation`](crate::key::KeyDerivation). |
+/// | 22     | 1    | ID of [`Encryption`](crate::key::Encryption). |
+/// | 23     | 1    | ID of [`Mac`](crate::key::Mac). |
+/// | 24     | 4    | Chunk size in little-endian. |
+/// | 28     | 32   | Salt to derive the subkeys. |
+const HEADER_LEN: usize = 60;
+
+/// Info for the subkeys of a stream.
+const INFO: &[u8] = b"warp-stream";
+
+/// Maximum chunk size the reader accept.
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Implementation of [`Write`] to encrypt a stream with [`Cipher`].
+///
+/// The header is followed by the chunks of ciphertext with a fixed size except the last one, which
+/// may be smaller or even empty. Each chunk is followed by its MAC, which covers the header, the
+/// chunk index, a flag indicating the last chunk and the ciphertext. The keystream position of each
+/// chunk is its offset in the plaintext.
+///
+/// [`EncryptWriter::finish()`] must be called to write the last chunk, otherwise the stream will be
+/// rejected as truncated.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    keys: Subkeys,
+    header: [u8; HEADER_LEN],
+    chunk: usize,
+    buf: Vec<u8>,
+    index: u64,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new(cipher: &Cipher, mut inner: W) -> Result<Self, EncryptError> {
+        // Generate a salt.
+        let mut salt = [0u8; Cipher::SALT_LEN];
+
+        if let Err(e) = getrandom(&mut salt) {
+            return Err(EncryptError::GenerateSaltFailed(e));
+        }
+
+        // Write header.
+        let chunk = Self::DEFAULT_CHUNK_SIZE;
+        let mut header = [0u8; HEADER_LEN];
+
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = Cipher::VERSION;
+        header[5..21].copy_from_slice(cipher.id.as_ref());
+        header[21] = cipher.kdf.id();
+        header[22] = cipher.enc.id();
+        header[23] = cipher.mac.id();
+        header[24..28].copy_from_slice(&u32::try_from(chunk).unwrap().to_le_bytes());
+        header[28..].copy_from_slice(&salt);
+
+        inner
+            .write_all(&header)
+            .map_err(EncryptError::WriteFailed)?;
+
+        Ok(Self {
+            inner,
+            keys: cipher.derive(&salt, INFO),
+            header,
+            chunk,
+            buf: Vec::with_capacity(chunk + cipher.mac_len()),
+            index: 0,
+        })
+    }
+
+    /// Write the last chunk and return the underlying writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.write_chunk(true)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn write_chunk(&mut self, last: bool) -> std::io::Result<()> {
+        let pos = self.index * u64::try_from(self.chunk).unwrap();
+        let flag = [u8::from(last)];
+
+        self.keys.apply_keystream(pos, &mut self.buf);
+
+        let mac = self
+            .keys
+            .mac(&[&self.header, &self.index.to_be_bytes(), &flag, &self.buf]);
+
+        self.buf.extend_from_slice(&mac);
+
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        self.index += 1;
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // We can only write a full chunk when we know there are more data after it, otherwise it
+        // should be the last chunk.
+        if self.buf.len() == self.chunk && !buf.is_empty() {
+            self.write_chunk(false)?;
+        }
+
+        let len = min(self.chunk - self.buf.len(), buf.len());
+
+        self.buf.extend_from_slice(&buf[..len]);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Implementation of [`Read`] to decrypt a stream written by [`EncryptWriter`].
+///
+/// Any modification, truncation, reordering or swapping of the chunks will cause the read to fail
+/// with [`std::io::ErrorKind::InvalidData`] wrapping a [`DecryptError`].
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    keys: Subkeys,
+    header: [u8; HEADER_LEN],
+    chunk: usize,
+    mac: usize,
+    buf: Vec<u8>,
+    len: usize,
+    next: usize,
+    end: usize,
+    index: u64,
+    last: bool,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(cipher: &Cipher, mut inner: R) -> Result<Self, DecryptError> {
+        // Read header.
+        let mut header = [0u8; HEADER_LEN];
+
+        if let Err(e) = inner.read_exact(&mut header) {
+            return Err(if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                DecryptError::Truncated
+            } else {
+                DecryptError::ReadFailed(e)
+            });
+        }
+
+        // Check header.
+        if &header[..4] != MAGIC {
+            return Err(DecryptError::NotWarpStream);
+        } else if header[4] != Cipher::VERSION {
+            return Err(DecryptError::UnsupportedVersion(header[4]));
+        } else if header[5..21] != *cipher.id.as_ref() {
+            return Err(DecryptError::KeyMismatched);
+        } else if header[21] != cipher.kdf.id()
+            || header[22] != cipher.enc.id()
+            || header[23] != cipher.mac.id()
+        {
+            return Err(DecryptError::AlgorithmMismatched);
+        }
+
+        let chunk = u32::from_le_bytes(header[24..28].try_into().unwrap());
+
+        if chunk == 0 || chunk > MAX_CHUNK_SIZE {
+            return Err(DecryptError::InvalidChunkSize(chunk));
+        }
+
+        // We need one more byte to see if there are more chunks after the current one.
+        let chunk: usize = chunk.try_into().unwrap();
+        let mac = cipher.mac_len();
+
+        Ok(Self {
+            inner,
+            keys: cipher.derive(&header[28..], INFO),
+            header,
+            chunk,
+            mac,
+            buf: vec![0; chunk + mac + 1],
+            len: 0,
+            next: 0,
+            end: 0,
+            index: 0,
+            last: false,
+        })
+    }
+
+    fn read_chunk(&mut self) -> Result<(), DecryptError> {
+        // Move the lookahead byte from the previous chunk.
+        let full = self.chunk + self.mac;
+
+        if self.len > full {
+            self.buf[0] = self.buf[full];
+            self.len = 1;
+        } else {
+            self.len = 0;
+        }
+
+        // Fill the buffer.
+        while self.len < self.buf.len() {
+            match self.inner.read(&mut self.buf[self.len..]) {
+                Ok(0) => break,
+                Ok(v) => self.len += v,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(DecryptError::ReadFailed(e)),
+            }
+        }
+
+        // Get the chunk.
+        let last = self.len <= full;
+        let len = if last { self.len } else { full };
+
+        if len < self.mac {
+            return Err(DecryptError::Truncated);
+        }
+
+        let (data, tag) = self.buf[..len].split_at_mut(len - self.mac);
+        let flag = [u8::from(last)];
+
+        if !self
+            .keys
+            .verify(&[&self.header, &self.index.to_be_bytes(), &flag, data], tag)
+        {
+            return Err(DecryptError::AuthenticationFailed);
+        }
+
+        // Decrypt.
+        let pos = self.index * u64::try_from(self.chunk).unwrap();
+
+        self.keys.apply_keystream(pos, data);
+        self.index += 1;
+        self.last = last;
+        self.next = 0;
+        self.end = data.len();
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Read next chunk if we have consumed the current one.
+        while self.next == self.end {
+            if self.last {
+                return Ok(0);
+            }
+
+            if let Err(e) = self.read_chunk() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+            }
+        }
+
+        // Copy the plaintext.
+        let len = min(self.end - self.next, buf.len());
+
+        buf[..len].copy_from_slice(&self.buf[self.next..(self.next + len)]);
+        self.next += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::KeyData;
+    use std::time::SystemTime;
+
+    const CHUNK: usize = EncryptWriter::<Vec<u8>>::DEFAULT_CHUNK_SIZE;
+
+    /// Size of each chunk on the wire.
+    const FULL: usize = CHUNK + 32;
+
+    fn cipher() -> Cipher {
+        Cipher::new(&[1; 16], &KeyData::new(SystemTime::now())).unwrap()
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn encrypt(cipher: &Cipher, data: &[u8]) -> Vec<u8> {
+        let mut w = EncryptWriter::new(cipher, Vec::new()).unwrap();
+
+        w.write_all(data).unwrap();
+        w.finish().unwrap()
+    }
+
+    fn decrypt(cipher: &Cipher, data: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        let mut r = DecryptReader::new(cipher, data)?;
+        let mut buf = Vec::new();
+
+        match r.read_to_end(&mut buf) {
+            Ok(_) => Ok(buf),
+            Err(e) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+                Err(*e.into_inner().unwrap().downcast().unwrap())
+            }
+        }
+    }
+
+    /// Offset of chunk `i` in the stream.
+    fn chunk(i: usize) -> usize {
+        HEADER_LEN + i * FULL
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = cipher();
+
+        for len in [
+            0,
+            1,
+            CHUNK - 1,
+            CHUNK,
+            CHUNK + 1,
+            CHUNK * 2,
+            CHUNK * 2 + 100,
+        ] {
+            let data = sample(len);
+
+            assert_eq!(decrypt(&cipher, &encrypt(&cipher, &data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn dropped_last_chunk() {
+        let cipher = cipher();
+        let data = encrypt(&cipher, &sample(CHUNK * 2 + 100));
+
+        assert_eq!(data.len(), chunk(2) + 100 + 32);
+        assert!(matches!(
+            decrypt(&cipher, &data[..chunk(2)]),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+
+        // The last chunk is a full chunk when the data is a multiple of the chunk size.
+        let data = encrypt(&cipher, &sample(CHUNK * 2));
+
+        assert_eq!(data.len(), chunk(2));
+        assert!(matches!(
+            decrypt(&cipher, &data[..chunk(1)]),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn swapped_chunks() {
+        let cipher = cipher();
+        let mut data = encrypt(&cipher, &sample(CHUNK * 2 + 100));
+        let first = data[chunk(0)..chunk(1)].to_vec();
+
+        data.copy_within(chunk(1)..chunk(2), chunk(0));
+        data[chunk(1)..chunk(2)].copy_from_slice(&first);
+
+        assert!(matches!(
+            decrypt(&cipher, &data),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn chunk_from_other_stream() {
+        let cipher = cipher();
+        let plain = sample(CHUNK * 2 + 100);
+        let mut data = encrypt(&cipher, &plain);
+        let other = encrypt(&cipher, &plain);
+
+        data[chunk(1)..chunk(2)].copy_from_slice(&other[chunk(1)..chunk(2)]);
+
+        assert!(matches!(
+            decrypt(&cipher, &data),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn tampered_header() {
+        let cipher = cipher();
+        let data = encrypt(&cipher, &sample(100));
+
+        for (i, expected) in [
+            (0, "NotWarpStream"),
+            (4, "UnsupportedVersion"),
+            (5, "KeyMismatched"),
+            (23, "AlgorithmMismatched"),
+            (27, "InvalidChunkSize"),
+            (24, "AuthenticationFailed"),
+            (HEADER_LEN - 1, "AuthenticationFailed"),
+        ] {
+            let mut data = data.clone();
+
+            data[i] ^= 1;
+
+            let e = decrypt(&cipher, &data).unwrap_err();
+
+            assert!(format!("{e:?}").starts_with(expected), "{i}: {e:?}");
+        }
+    }
+
+    #[test]
+    fn cleared_last_flag() {
+        let cipher = cipher();
+        let mut w = EncryptWriter::new(&cipher, Vec::new()).unwrap();
+
+        // Write the last chunk as if more chunks will follow.
+        w.write_all(&sample(100)).unwrap();
+        w.write_chunk(false).unwrap();
+
+        assert!(matches!(
+            decrypt(&cipher, &w.inner),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+
+    #[test]
+    fn trailing_data() {
+        let cipher = cipher();
+        let mut data = encrypt(&cipher, &sample(100));
+
+        data.push(0);
+
+        assert!(matches!(
+            decrypt(&cipher, &data),
+            Err(DecryptError::AuthenticationFailed)
+        ));
+    }
+}
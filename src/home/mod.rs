@@ -29,10 +29,24 @@ impl Home {
         self.0.join("config.yml")
     }
 
-    #[cfg(target_os = "windows")]
+    /// Path to the global ignore file.
+    pub fn ignore(&self) -> PathBuf {
+        self.0.join("ignore")
+    }
+
     pub fn keys(&self) -> PathBuf {
         self.0.join("keys")
     }
+
+    /// Path to the list of repositories on this computer.
+    pub fn repos(&self) -> PathBuf {
+        self.0.join("repos.yml")
+    }
+
+    /// Path to the directory to store the journal of key rotations that still in progress.
+    pub fn rotations(&self) -> PathBuf {
+        self.0.join("rotations")
+    }
 }
 
 /// Represents an error when [`Home::new()`] fails.
@@ -0,0 +1,160 @@
+use super::{KeyData, KeyId, KeyMaterial, PassphraseKdf};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Content of a `.warpkey` file, which is a key encrypted with a passphrase to move the key to
+/// another computer or keep it as a backup.
+///
+/// The ID of the key is stored unencrypted so it can be checked before asking for the passphrase.
+#[derive(Serialize, Deserialize)]
+pub struct KeyBackup {
+    id: KeyId,
+    kdf: PassphraseKdf,
+    nonce: [u8; 12],
+    key: Vec<u8>,
+}
+
+impl KeyBackup {
+    const MAGIC: &'static [u8; 8] = b"WARPKEY\x01";
+
+    /// Encrypt `key` with a key derived from `pass`.
+    pub fn new(key: &KeyMaterial, pass: &str) -> Result<Self, BackupError> {
+        // Derive the key to encrypt the key.
+        let mut salt = [0u8; 16];
+
+        getrandom(&mut salt).map_err(BackupError::GenerateSaltFailed)?;
+
+        let kdf = PassphraseKdf::new(salt);
+        let kek = kdf.derive(pass).map_err(BackupError::DeriveKeyFailed)?;
+
+        // Encrypt the key.
+        let mut nonce = [0u8; 12];
+
+        getrandom(&mut nonce).map_err(BackupError::GenerateNonceFailed)?;
+
+        let id = KeyId::from_key(&key.key);
+        let mut payload = BackupPayload {
+            key: *key.key,
+            data: key.data.clone(),
+        };
+
+        let msg = Zeroizing::new(postcard::to_stdvec(&payload).unwrap());
+
+        payload.key.zeroize();
+
+        let key = Aes256Gcm::new(kek.as_ref().into())
+            .encrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: &msg,
+                    aad: &Self::aad(&id),
+                },
+            )
+            .unwrap();
+
+        Ok(Self {
+            id,
+            kdf,
+            nonce,
+            key,
+        })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, BackupError> {
+        match data.strip_prefix(Self::MAGIC) {
+            Some(v) => postcard::from_bytes(v).map_err(BackupError::InvalidBackup),
+            None => Err(BackupError::NotKeyBackup),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Self::MAGIC.to_vec();
+        data.extend(postcard::to_stdvec(self).unwrap());
+        data
+    }
+
+    /// ID of the key as recorded when the backup was created.
+    pub fn id(&self) -> &KeyId {
+        &self.id
+    }
+
+    /// Decrypt the key with `pass`. The ID of the decrypted key is verified against
+    /// [`KeyBackup::id()`] so a backup of the other key is rejected.
+    pub fn decrypt(&self, pass: &str) -> Result<KeyMaterial, BackupError> {
+        // Decrypt the key.
+        let kek = self
+            .kdf
+            .derive(pass)
+            .map_err(BackupError::DeriveKeyFailed)?;
+        let payload = Aes256Gcm::new(kek.as_ref().into())
+            .decrypt(
+                (&self.nonce).into(),
+                Payload {
+                    msg: &self.key,
+                    aad: &Self::aad(&self.id),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| BackupError::WrongPassphrase)?;
+        let mut payload: BackupPayload =
+            postcard::from_bytes(&payload).map_err(|_| BackupError::InvalidKey)?;
+        let key = Zeroizing::new(payload.key);
+
+        payload.key.zeroize();
+
+        // Verify the key.
+        if KeyId::from_key(&key) != self.id {
+            return Err(BackupError::KeyMismatched);
+        }
+
+        Ok(KeyMaterial {
+            key,
+            data: payload.data,
+        })
+    }
+
+    fn aad(id: &KeyId) -> Vec<u8> {
+        let mut aad = Self::MAGIC.to_vec();
+        aad.extend_from_slice(id.as_ref());
+        aad
+    }
+}
+
+/// Encrypted part of [`KeyBackup`].
+#[derive(Serialize, Deserialize)]
+struct BackupPayload {
+    key: [u8; 16],
+    data: KeyData,
+}
+
+/// Represents an error when [`KeyBackup`] fails.
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("couldn't generate a salt")]
+    GenerateSaltFailed(#[source] getrandom::Error),
+
+    #[error("couldn't generate a nonce")]
+    GenerateNonceFailed(#[source] getrandom::Error),
+
+    #[error("couldn't derive a key from the passphrase")]
+    DeriveKeyFailed(#[source] argon2::Error),
+
+    #[error("the data is not a Warp key")]
+    NotKeyBackup,
+
+    #[error("the key file is corrupted")]
+    InvalidBackup(#[source] postcard::Error),
+
+    #[error("wrong passphrase or the key file is corrupted")]
+    WrongPassphrase,
+
+    #[error("the key file contains an invalid key")]
+    InvalidKey,
+
+    #[error("the key in the file does not match its ID")]
+    KeyMismatched,
+}
@@ -1,6 +1,16 @@
-use self::store::{DefaultStore, Keystore};
+pub use self::backup::*;
+pub use self::passphrase::*;
+pub use self::phrase::*;
+pub use self::store::{find_plugins, Keystore, KeystoreConfigError, KeystoreRegistry};
+use crate::config;
 use crate::home::Home;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
 use hex::FromHexError;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake128;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
@@ -9,78 +19,305 @@ use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
 use thiserror::Error;
+use zeroize::Zeroizing;
 
+mod backup;
+mod passphrase;
+mod phrase;
 mod store;
 
 /// Manage file encryption keys.
+///
+/// The same key can be in multiple stores (e.g. a keychain and a backup on a removable drive). The
+/// secret part of the key will be loaded from the store with the highest priority that has it.
 pub struct KeyMgr {
-    stores: HashMap<&'static str, Arc<dyn Keystore>>,
+    stores: Vec<Arc<dyn Keystore>>,
     keys: RwLock<HashMap<KeyId, Arc<Key>>>,
 }
 
 impl KeyMgr {
     pub const DEFAULT_STORE: &'static str = "default";
+    pub const FILE_STORE: &'static str = "file";
+    pub const PASS_STORE: &'static str = "pass";
+    pub const PLUGIN_STORE: &'static str = "plugin";
 
-    pub fn new(home: &Arc<Home>) -> Result<Self, KeyMgrError> {
-        let mut stores = HashMap::<&'static str, Arc<dyn Keystore>>::new();
-        let mut keys = HashMap::new();
+    pub fn new(home: &Arc<Home>, config: &config::Key) -> (Self, Vec<(String, KeystoreLoadError)>) {
+        Self::with_registry(home, config, &KeystoreRegistry::default())
+    }
 
-        // Initialize default store.
-        let store = Arc::new(DefaultStore::new(home));
+    /// Create a [`KeyMgr`] with the stores that are enabled in `config`, including the plugins on
+    /// `PATH` that are not configured. The type of each store must be in `registry`.
+    ///
+    /// A store that cannot be created from its configurations or cannot list its keys does not
+    /// fail the whole [`KeyMgr`] so it still possible to fix or disable it. These stores are
+    /// returned along with the reason.
+    pub fn with_registry(
+        home: &Arc<Home>,
+        config: &config::Key,
+        registry: &KeystoreRegistry,
+    ) -> (Self, Vec<(String, KeystoreLoadError)>) {
+        // Sort the stores by priority. The stores that are not in the priority list come last.
+        let stores = config.effective_stores();
+        let mut enabled: Vec<_> = stores.iter().filter(|(_, c)| c.enabled).collect();
 
-        for e in store.list() {
-            let k = e.map_err(|e| KeyMgrError::ListKeyFailed(store.id(), e))?;
+        enabled.sort_by_key(|(id, _)| {
+            config
+                .priority
+                .iter()
+                .position(|p| p == *id)
+                .unwrap_or(usize::MAX)
+        });
 
-            assert!(keys.insert(k.id().clone(), Arc::new(k)).is_none());
-        }
+        // Initialize stores.
+        let mut mgr = Self {
+            stores: Vec::new(),
+            keys: RwLock::default(),
+        };
+        let mut failed = Vec::new();
+
+        for (id, c) in enabled {
+            // Create the store.
+            let store = match registry.create(id, &c.ty, home, &c.options) {
+                Ok(v) => v,
+                Err(e) => {
+                    failed.push((id.clone(), KeystoreLoadError::InvalidConfig(e)));
+                    continue;
+                }
+            };
 
-        assert!(stores.insert(store.id(), store).is_none());
+            // Load keys. The keys are not added if the listing fails halfway so nothing refer to
+            // the store.
+            match store.list().collect::<Result<Vec<_>, _>>() {
+                Ok(v) => {
+                    for k in v {
+                        mgr.insert(k);
+                    }
 
-        Ok(Self {
-            stores,
-            keys: RwLock::new(keys),
-        })
+                    mgr.stores.push(store);
+                }
+                Err(e) => failed.push((id.clone(), KeystoreLoadError::ListKeyFailed(e))),
+            }
+        }
+
+        (mgr, failed)
     }
 
     pub fn has_keys(&self) -> bool {
         !self.keys.read().unwrap().is_empty()
     }
 
+    /// Returns the stores in the order of priority.
     pub fn stores(&self) -> impl FusedIterator<Item = &dyn Keystore> {
-        self.stores.values().map(|s| s.as_ref())
+        self.stores.iter().map(|s| s.as_ref())
     }
 
     pub fn generate(&self, store: &str) -> Result<Option<Arc<Key>>, Box<dyn Error>> {
         // Get target store.
-        let store = match self.stores.get(store) {
-            Some(v) => v.clone(),
+        let store = match self.store(store) {
+            Some(v) => v,
             None => return Ok(None),
         };
 
         // Generate.
-        let key = Arc::new(store.generate()?);
+        let key = store.generate()?;
+
+        Ok(Some(self.insert(key)))
+    }
+
+    /// Store an existing key to `store`, which can be used to copy a key to another store. Returns
+    /// [`None`] if `store` does not exists.
+    pub fn import(
+        &self,
+        store: &str,
+        key: &KeyMaterial,
+    ) -> Result<Option<Arc<Key>>, Box<dyn Error>> {
+        // Get target store.
+        let store = match self.store(store) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        // Import.
+        let key = store.import(key)?;
 
-        assert!(self
-            .keys
-            .write()
-            .unwrap()
-            .insert(key.id().clone(), key.clone())
-            .is_none());
+        Ok(Some(self.insert(key)))
+    }
+
+    pub fn get(&self, id: &KeyId) -> Option<Arc<Key>> {
+        self.keys.read().unwrap().get(id).cloned()
+    }
+
+    /// Load the secret part of the key from the first store that has it. Returns [`None`] if the
+    /// key does not exists.
+    pub fn material(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        let key = match self.get(id) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        for s in key.stores() {
+            if let Some(v) = self.store(s).unwrap().get(id)? {
+                return Ok(Some(v));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Remove the key permanently from `store` or from all stores if [`None`]. Returns [`None`] if
+    /// the key does not exists in the specified store.
+    pub fn delete(
+        &self,
+        id: &KeyId,
+        store: Option<&str>,
+    ) -> Result<Option<Arc<Key>>, Box<dyn Error>> {
+        // Get the stores to remove from.
+        let key = match self.get(id) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let targets: Vec<&str> = match store {
+            Some(v) if key.stores().any(|s| s == v) => vec![v],
+            Some(_) => return Ok(None),
+            None => key.stores().collect(),
+        };
+
+        // Remove the key from each store. We need to update the key after each store so the key
+        // reflect the actual state if some of them failed.
+        for s in targets {
+            self.store(s).unwrap().delete(id)?;
+
+            let mut keys = self.keys.write().unwrap();
+            let stores: Vec<Arc<str>> = keys[id]
+                .stores
+                .iter()
+                .filter(|&v| v.as_ref() != s)
+                .cloned()
+                .collect();
+
+            if stores.is_empty() {
+                keys.remove(id);
+            } else {
+                let key = Key {
+                    id: id.clone(),
+                    created: key.created,
+                    stores,
+                };
+
+                keys.insert(id.clone(), Arc::new(key));
+            }
+        }
 
         Ok(Some(key))
     }
 
+    /// Store a secret that is not a key to `store`. Returns `false` if `store` does not exists.
+    pub fn set_secret(
+        &self,
+        store: &str,
+        name: &str,
+        value: &[u8],
+    ) -> Result<bool, Box<dyn Error>> {
+        let store = match self.store(store) {
+            Some(v) => v,
+            None => return Ok(false),
+        };
+
+        store.set_secret(name, value)?;
+
+        Ok(true)
+    }
+
+    /// Load a secret from the first store that has it. Returns [`None`] if no store has the
+    /// secret.
+    pub fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        for s in &self.stores {
+            if let Some(v) = s.get_secret(name)? {
+                return Ok(Some(v));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Remove a secret from all stores. Returns `false` if no store has the secret.
+    pub fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        let mut found = false;
+
+        for s in &self.stores {
+            found |= s.delete_secret(name)?;
+        }
+
+        Ok(found)
+    }
+
     pub fn for_each_key(&self, mut f: impl FnMut(&Arc<Key>)) {
         for k in self.keys.read().unwrap().values() {
             f(k);
         }
     }
+
+    fn store(&self, id: &str) -> Option<&Arc<dyn Keystore>> {
+        self.stores.iter().find(|s| s.id() == id)
+    }
+
+    /// Add `key` that was loaded from a store. If the key is already known the store will be added
+    /// to its locations.
+    fn insert(&self, key: Key) -> Arc<Key> {
+        let mut keys = self.keys.write().unwrap();
+        let key = match keys.get(&key.id) {
+            Some(v) => {
+                let mut stores = v.stores.clone();
+
+                for s in key.stores {
+                    if !stores.contains(&s) {
+                        stores.push(s);
+                    }
+                }
+
+                stores.sort_by_key(|s| self.stores.iter().position(|v| v.id() == s.as_ref()));
+
+                Key {
+                    id: key.id,
+                    created: v.created.min(key.created),
+                    stores,
+                }
+            }
+            None => key,
+        };
+
+        let key = Arc::new(key);
+
+        keys.insert(key.id.clone(), key.clone());
+
+        key
+    }
 }
 
 /// Unique identifier of a [`Key`].
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct KeyId([u8; 16]);
 
+impl KeyId {
+    /// Compute the ID of `key`.
+    pub fn from_key(key: &[u8; 16]) -> Self {
+        // Get a key check value.
+        let mut kcv = [0u8; 16];
+
+        Aes128::new(key.into()).encrypt_block((&mut kcv).into());
+
+        // Get key ID.
+        let mut hasher = Shake128::default();
+        let mut id = [0u8; 16];
+
+        hasher.update(&kcv);
+        hasher.finalize_xof().read(&mut id);
+
+        Self(id)
+    }
+}
+
 impl FromStr for KeyId {
     type Err = FromHexError;
 
@@ -100,17 +337,38 @@ impl AsRef<[u8; 16]> for KeyId {
 impl Display for KeyId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for b in self.0 {
-            write!(f, "{b:x}")?;
+            write!(f, "{b:02x}")?;
         }
 
         Ok(())
     }
 }
 
+impl Serialize for KeyId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'a> Deserialize<'a> for KeyId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        let v = String::deserialize(deserializer)?;
+
+        v.parse().map_err(D::Error::custom)
+    }
+}
+
 /// Key to encrypt/decrypt files in a repository.
 pub struct Key {
     id: KeyId,
     created: SystemTime,
+    stores: Vec<Arc<str>>,
 }
 
 impl Key {
@@ -118,14 +376,94 @@ impl Key {
         &self.id
     }
 
+    /// Returns ID of the stores that keep this key in the order of priority.
+    pub fn stores(&self) -> impl Iterator<Item = &str> {
+        self.stores.iter().map(|s| s.as_ref())
+    }
+
     pub fn created(&self) -> SystemTime {
         self.created
     }
 }
 
-/// Represents an error when [`KeyMgr`] fails to initialize.
+/// Secret part of a [`Key`].
+pub struct KeyMaterial {
+    pub key: Zeroizing<[u8; 16]>,
+    pub data: KeyData,
+}
+
+/// Per-key data stored unencrypted with the key.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct KeyData {
+    pub kdf: KeyDerivation,
+    pub enc: Encryption,
+    pub mac: Option<Mac>,
+    pub created: SystemTime,
+}
+
+impl KeyData {
+    /// Create a [`KeyData`] with the algorithms for a new key.
+    pub fn new(created: SystemTime) -> Self {
+        Self {
+            kdf: KeyDerivation::HkdfSha3256,
+            enc: Encryption::AesCtr128,
+            mac: Some(Mac::HmacSha3256),
+            created,
+        }
+    }
+}
+
+/// Key derivation algorithm of the key.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyDerivation {
+    HkdfSha3256,
+}
+
+impl KeyDerivation {
+    /// Unique identifier of the algorithm to store in an encrypted data.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::HkdfSha3256 => 1,
+        }
+    }
+}
+
+/// Encryption algorithm of the key.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encryption {
+    AesCtr128,
+}
+
+impl Encryption {
+    /// Unique identifier of the algorithm to store in an encrypted data.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::AesCtr128 => 1,
+        }
+    }
+}
+
+/// Message authentication code of the key.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mac {
+    HmacSha3256,
+}
+
+impl Mac {
+    /// Unique identifier of the algorithm to store in an encrypted data.
+    pub fn id(self) -> u8 {
+        match self {
+            Self::HmacSha3256 => 1,
+        }
+    }
+}
+
+/// Represents an error when [`KeyMgr`] fails to load an enabled store.
 #[derive(Debug, Error)]
-pub enum KeyMgrError {
-    #[error("couldn't list keys from '{0}' store")]
-    ListKeyFailed(&'static str, #[source] Box<dyn Error>),
+pub enum KeystoreLoadError {
+    #[error("invalid configuration")]
+    InvalidConfig(#[source] KeystoreConfigError),
+
+    #[error("couldn't list the keys")]
+    ListKeyFailed(#[source] Box<dyn Error>),
 }
@@ -0,0 +1,79 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use std::ops::DerefMut;
+use zeroize::Zeroizing;
+
+/// Algorithm to derive a key from a passphrase.
+#[derive(Serialize, Deserialize)]
+pub enum PassphraseKdf {
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        salt: [u8; 16],
+    },
+}
+
+impl PassphraseKdf {
+    /// Create a [`PassphraseKdf`] with the default parameters for a new key.
+    pub fn new(salt: [u8; 16]) -> Self {
+        Self::Argon2id {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+            salt,
+        }
+    }
+
+    pub fn derive(&self, pass: &str) -> Result<Zeroizing<[u8; 32]>, argon2::Error> {
+        match self {
+            Self::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+                salt,
+            } => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(32))?;
+                let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+                let mut key = Zeroizing::new([0u8; 32]);
+
+                argon2.hash_password_into(pass.as_bytes(), salt, key.deref_mut())?;
+
+                Ok(key)
+            }
+        }
+    }
+}
+
+/// Get passphrase from the environment variable `var` or prompt the user with `prompt` if it is not
+/// set.
+pub fn read_passphrase(
+    var: &str,
+    prompt: &str,
+    confirm: bool,
+) -> Result<Zeroizing<String>, std::io::Error> {
+    if let Some(v) = std::env::var_os(var) {
+        return v.into_string().map(Zeroizing::new).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{var} is not a valid UTF-8"),
+            )
+        });
+    }
+
+    loop {
+        let pass = Zeroizing::new(rpassword::prompt_password(prompt)?);
+
+        if !confirm {
+            return Ok(pass);
+        }
+
+        let again = Zeroizing::new(rpassword::prompt_password("Confirm passphrase: ")?);
+
+        if pass == again {
+            return Ok(pass);
+        }
+
+        eprintln!("Passphrases do not match, please try again.");
+    }
+}
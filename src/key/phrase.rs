@@ -0,0 +1,93 @@
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// English word list from [BIP39](https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki).
+const WORDS: &str = include_str!("english.txt");
+
+/// Number of words in a recovery phrase of 16-byte key.
+const PHRASE_LEN: usize = 12;
+
+/// Encode `key` as a recovery phrase the same way as a 128-bit BIP39 mnemonic, which is 12 words
+/// where the last word contains a 4-bit checksum.
+///
+/// The phrase contains only the key itself so [`super::KeyData`] need to be recreated on recovery.
+pub fn key_to_phrase(key: &[u8; 16]) -> Zeroizing<String> {
+    // Append the checksum.
+    let words: Vec<&str> = WORDS.lines().collect();
+    let mut data = Zeroizing::new([0u8; 17]);
+
+    data[..16].copy_from_slice(key);
+    data[16] = Sha256::digest(key)[0] & 0xf0;
+
+    // Map each 11 bits to a word.
+    let mut phrase = Zeroizing::new(String::new());
+
+    for i in 0..PHRASE_LEN {
+        let mut index = 0;
+
+        for b in (i * 11)..((i + 1) * 11) {
+            index = (index << 1) | usize::from((data[b / 8] >> (7 - b % 8)) & 1);
+        }
+
+        if i != 0 {
+            phrase.push(' ');
+        }
+
+        phrase.push_str(words[index]);
+    }
+
+    phrase
+}
+
+/// Decode a phrase that was produced by [`key_to_phrase()`]. Words can be separated by any
+/// whitespaces and are case-insensitive.
+pub fn phrase_to_key(phrase: &str) -> Result<Zeroizing<[u8; 16]>, PhraseError> {
+    // Check the number of words.
+    let input = Zeroizing::new(phrase.to_lowercase());
+    let count = input.split_whitespace().count();
+
+    if count != PHRASE_LEN {
+        return Err(PhraseError::InvalidLength(count));
+    }
+
+    // Map each word back to 11 bits.
+    let words: Vec<&str> = WORDS.lines().collect();
+    let mut data = Zeroizing::new([0u8; 17]);
+
+    for (i, w) in input.split_whitespace().enumerate() {
+        let mut index = match words.binary_search(&w) {
+            Ok(v) => v,
+            Err(_) => return Err(PhraseError::UnknownWord(i + 1)),
+        };
+
+        for b in ((i * 11)..((i + 1) * 11)).rev() {
+            data[b / 8] |= ((index & 1) as u8) << (7 - b % 8);
+            index >>= 1;
+        }
+    }
+
+    // Verify the checksum.
+    let mut key = Zeroizing::new([0u8; 16]);
+
+    key.copy_from_slice(&data[..16]);
+
+    if Sha256::digest(key.as_ref())[0] & 0xf0 != data[16] {
+        return Err(PhraseError::ChecksumMismatched);
+    }
+
+    Ok(key)
+}
+
+/// Represents an error when [`phrase_to_key()`] fails.
+#[derive(Debug, Error)]
+pub enum PhraseError {
+    #[error("the phrase has {0} words instead of {PHRASE_LEN}")]
+    InvalidLength(usize),
+
+    #[error("word #{0} is not in the word list")]
+    UnknownWord(usize),
+
+    #[error("the phrase has an incorrect checksum")]
+    ChecksumMismatched,
+}
@@ -1,14 +1,343 @@
-use crate::key::Key;
+use crate::key::{Key, KeyData, KeyId, KeyMaterial};
+use secret_service::blocking::SecretService;
+use secret_service::EncryptionType;
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::Arc;
+use thiserror::Error;
+use zeroize::Zeroizing;
 
-/// Iterator to list all keys from libsecret.
-#[derive(Default)]
-pub struct KeyList {}
+/// Value of `xdg:schema` attribute for the items that represent a key.
+pub const SCHEMA: &str = "sh.warpgate.Warp.Key";
+
+/// Attribute name for the ID of the key.
+pub const ATTR_ID: &str = "id";
+
+/// Attribute name for the hex-encoded [`KeyData`].
+pub const ATTR_DATA: &str = "data";
+
+/// Value of `xdg:schema` attribute for the items that represent a secret other than a key.
+pub const SECRET_SCHEMA: &str = "sh.warpgate.Warp.Secret";
+
+/// Attribute name for the name of the secret.
+pub const ATTR_NAME: &str = "name";
+
+/// Iterator to list all keys from the Secret Service.
+pub struct KeyList {
+    store: Arc<str>,
+    items: Option<std::vec::IntoIter<Result<Key, ListError>>>,
+}
+
+impl KeyList {
+    pub fn new(store: Arc<str>) -> Self {
+        Self { store, items: None }
+    }
+
+    fn load(&self) -> Result<Vec<Result<Key, ListError>>, ListError> {
+        // Connect to the service. No provider means no keys since nothing can be stored there.
+        let ss = match SecretService::connect(EncryptionType::Dh) {
+            Ok(v) => v,
+            Err(secret_service::Error::Unavailable) => return Ok(Vec::new()),
+            Err(e) => return Err(ListError::ConnectFailed(e)),
+        };
+
+        // Search for our items.
+        let items = ss
+            .search_items(HashMap::from([("xdg:schema", SCHEMA)]))
+            .map_err(ListError::SearchFailed)?;
+
+        // Load the keys. The attributes are readable without unlocking the items so we don't prompt
+        // the user on every listing, get() will unlock the item when the key is actually needed.
+        let keys = items
+            .unlocked
+            .iter()
+            .chain(&items.locked)
+            .map(|i| {
+                let attrs = i.get_attributes().map_err(ListError::GetAttributesFailed)?;
+                let id = attrs.get(ATTR_ID).ok_or(ListError::NoKeyId)?;
+                let id = id.parse().map_err(|_| ListError::InvalidKeyId)?;
+                let data = attrs.get(ATTR_DATA).ok_or(ListError::NoKeyData)?;
+                let data = hex::decode(data).map_err(|_| ListError::InvalidKeyData(None))?;
+                let data: KeyData =
+                    postcard::from_bytes(&data).map_err(|e| ListError::InvalidKeyData(Some(e)))?;
+
+                Ok(Key {
+                    id,
+                    created: data.created,
+                    stores: vec![self.store.clone()],
+                })
+            })
+            .collect();
+
+        Ok(keys)
+    }
+}
 
 impl Iterator for KeyList {
     type Item = Result<Key, Box<dyn Error>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        let items = match &mut self.items {
+            Some(v) => v,
+            None => match self.load() {
+                Ok(v) => self.items.insert(v.into_iter()),
+                Err(e) => {
+                    self.items = Some(Vec::new().into_iter());
+                    return Some(Err(Box::new(e)));
+                }
+            },
+        };
+
+        items.next().map(|r| r.map_err(|e| e.into()))
     }
 }
+
+/// Load the key from the Secret Service.
+pub fn get(id: &KeyId) -> Result<Option<KeyMaterial>, GetError> {
+    // Connect to the service.
+    let ss = match SecretService::connect(EncryptionType::Dh) {
+        Ok(v) => v,
+        Err(secret_service::Error::Unavailable) => return Ok(None),
+        Err(e) => return Err(GetError::ConnectFailed(e)),
+    };
+
+    // Search for the key.
+    let id = id.to_string();
+    let items = ss
+        .search_items(HashMap::from([
+            ("xdg:schema", SCHEMA),
+            (ATTR_ID, id.as_str()),
+        ]))
+        .map_err(GetError::SearchFailed)?;
+    let item = match items.unlocked.first().or(items.locked.first()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    item.ensure_unlocked().map_err(GetError::UnlockFailed)?;
+
+    // Get data.
+    let attrs = item
+        .get_attributes()
+        .map_err(GetError::GetAttributesFailed)?;
+    let data = attrs.get(ATTR_DATA).ok_or(GetError::NoKeyData)?;
+    let data = hex::decode(data).map_err(|_| GetError::InvalidKeyData(None))?;
+    let data = postcard::from_bytes(&data).map_err(|e| GetError::InvalidKeyData(Some(e)))?;
+
+    // Get key.
+    let secret = Zeroizing::new(item.get_secret().map_err(GetError::GetSecretFailed)?);
+    let key = match <[u8; 16]>::try_from(secret.as_slice()) {
+        Ok(v) => Zeroizing::new(v),
+        Err(_) => return Err(GetError::InvalidKey),
+    };
+
+    Ok(Some(KeyMaterial { key, data }))
+}
+
+/// Remove the key from the Secret Service.
+pub fn delete(id: &KeyId) -> Result<bool, DeleteError> {
+    // Connect to the service.
+    let ss = match SecretService::connect(EncryptionType::Dh) {
+        Ok(v) => v,
+        Err(secret_service::Error::Unavailable) => return Ok(false),
+        Err(e) => return Err(DeleteError::ConnectFailed(e)),
+    };
+
+    // Search for the key.
+    let id = id.to_string();
+    let items = ss
+        .search_items(HashMap::from([
+            ("xdg:schema", SCHEMA),
+            (ATTR_ID, id.as_str()),
+        ]))
+        .map_err(DeleteError::SearchFailed)?;
+    let mut found = false;
+
+    for item in items.unlocked.iter().chain(&items.locked) {
+        item.delete().map_err(DeleteError::DeleteFailed)?;
+        found = true;
+    }
+
+    Ok(found)
+}
+
+/// Store the secret to the default collection of the Secret Service.
+pub fn set_secret(name: &str, value: &[u8]) -> Result<(), SecretError> {
+    // Get the default collection.
+    let ss = SecretService::connect(EncryptionType::Dh).map_err(SecretError::ConnectFailed)?;
+    let collection = match ss.get_default_collection() {
+        Ok(v) => v,
+        Err(secret_service::Error::NoResult) => ss
+            .create_collection("Default keyring", "default")
+            .map_err(SecretError::CreateCollectionFailed)?,
+        Err(e) => return Err(SecretError::GetCollectionFailed(e)),
+    };
+
+    collection
+        .ensure_unlocked()
+        .map_err(SecretError::UnlockFailed)?;
+
+    // Store the secret.
+    let attrs = HashMap::from([("xdg:schema", SECRET_SCHEMA), (ATTR_NAME, name)]);
+
+    collection
+        .create_item(
+            &format!("Warp Secret ({name})"),
+            attrs,
+            value,
+            true,
+            "application/octet-stream",
+        )
+        .map_err(SecretError::StoreFailed)?;
+
+    Ok(())
+}
+
+/// Load the secret from the Secret Service.
+pub fn get_secret(name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+    // Connect to the service.
+    let ss = match SecretService::connect(EncryptionType::Dh) {
+        Ok(v) => v,
+        Err(secret_service::Error::Unavailable) => return Ok(None),
+        Err(e) => return Err(SecretError::ConnectFailed(e)),
+    };
+
+    // Search for the secret.
+    let items = ss
+        .search_items(HashMap::from([
+            ("xdg:schema", SECRET_SCHEMA),
+            (ATTR_NAME, name),
+        ]))
+        .map_err(SecretError::SearchFailed)?;
+    let item = match items.unlocked.first().or(items.locked.first()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    item.ensure_unlocked().map_err(SecretError::UnlockFailed)?;
+
+    // Get the secret.
+    let secret = item.get_secret().map_err(SecretError::GetSecretFailed)?;
+
+    Ok(Some(Zeroizing::new(secret)))
+}
+
+/// Remove the secret from the Secret Service.
+pub fn delete_secret(name: &str) -> Result<bool, SecretError> {
+    // Connect to the service.
+    let ss = match SecretService::connect(EncryptionType::Dh) {
+        Ok(v) => v,
+        Err(secret_service::Error::Unavailable) => return Ok(false),
+        Err(e) => return Err(SecretError::ConnectFailed(e)),
+    };
+
+    // Search for the secret.
+    let items = ss
+        .search_items(HashMap::from([
+            ("xdg:schema", SECRET_SCHEMA),
+            (ATTR_NAME, name),
+        ]))
+        .map_err(SecretError::SearchFailed)?;
+    let mut found = false;
+
+    for item in items.unlocked.iter().chain(&items.locked) {
+        item.delete().map_err(SecretError::DeleteFailed)?;
+        found = true;
+    }
+
+    Ok(found)
+}
+
+/// Represents an error when [`KeyList::next()`] fails.
+#[derive(Debug, Error)]
+enum ListError {
+    #[error("couldn't connect to the Secret Service")]
+    ConnectFailed(#[source] secret_service::Error),
+
+    #[error("couldn't search for the keys")]
+    SearchFailed(#[source] secret_service::Error),
+
+    #[error("couldn't get attributes of the item")]
+    GetAttributesFailed(#[source] secret_service::Error),
+
+    #[error("no '{ATTR_ID}' attribute on the item")]
+    NoKeyId,
+
+    #[error("'{ATTR_ID}' attribute has invalid value")]
+    InvalidKeyId,
+
+    #[error("no '{ATTR_DATA}' attribute on the item")]
+    NoKeyData,
+
+    #[error("'{ATTR_DATA}' attribute has invalid value")]
+    InvalidKeyData(#[source] Option<postcard::Error>),
+}
+
+/// Represents an error when [`get()`] fails.
+#[derive(Debug, Error)]
+pub enum GetError {
+    #[error("couldn't connect to the Secret Service")]
+    ConnectFailed(#[source] secret_service::Error),
+
+    #[error("couldn't search for the key")]
+    SearchFailed(#[source] secret_service::Error),
+
+    #[error("couldn't unlock the key")]
+    UnlockFailed(#[source] secret_service::Error),
+
+    #[error("couldn't get attributes of the item")]
+    GetAttributesFailed(#[source] secret_service::Error),
+
+    #[error("no '{ATTR_DATA}' attribute on the item")]
+    NoKeyData,
+
+    #[error("'{ATTR_DATA}' attribute has invalid value")]
+    InvalidKeyData(#[source] Option<postcard::Error>),
+
+    #[error("couldn't get the secret of the item")]
+    GetSecretFailed(#[source] secret_service::Error),
+
+    #[error("the secret of the item is not a valid key")]
+    InvalidKey,
+}
+
+/// Represents an error when [`delete()`] fails.
+#[derive(Debug, Error)]
+pub enum DeleteError {
+    #[error("couldn't connect to the Secret Service")]
+    ConnectFailed(#[source] secret_service::Error),
+
+    #[error("couldn't search for the key")]
+    SearchFailed(#[source] secret_service::Error),
+
+    #[error("couldn't delete the key")]
+    DeleteFailed(#[source] secret_service::Error),
+}
+
+/// Represents an error when [`set_secret()`], [`get_secret()`] or [`delete_secret()`] fails.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("couldn't connect to the Secret Service")]
+    ConnectFailed(#[source] secret_service::Error),
+
+    #[error("couldn't get the default collection")]
+    GetCollectionFailed(#[source] secret_service::Error),
+
+    #[error("couldn't create the default collection")]
+    CreateCollectionFailed(#[source] secret_service::Error),
+
+    #[error("couldn't search for the secret")]
+    SearchFailed(#[source] secret_service::Error),
+
+    #[error("couldn't unlock the secret")]
+    UnlockFailed(#[source] secret_service::Error),
+
+    #[error("couldn't store the secret")]
+    StoreFailed(#[source] secret_service::Error),
+
+    #[error("couldn't get the secret of the item")]
+    GetSecretFailed(#[source] secret_service::Error),
+
+    #[error("couldn't delete the secret")]
+    DeleteFailed(#[source] secret_service::Error),
+}
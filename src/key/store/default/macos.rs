@@ -1,5 +1,4 @@
-use super::KeyData;
-use crate::key::Key;
+use crate::key::{Key, KeyData, KeyId, KeyMaterial};
 use core_foundation::array::CFArray;
 use core_foundation::base::{CFIndex, CFType, TCFType, ToVoid};
 use core_foundation::data::CFData;
@@ -10,23 +9,39 @@ use core_foundation::{declare_TCFType, impl_TCFType};
 use security_framework_sys::access_control::SecAccessControlGetTypeID;
 use security_framework_sys::base::{errSecItemNotFound, SecAccessControlRef};
 use security_framework_sys::item::{
-    kSecAttrAccount, kSecAttrService, kSecClass, kSecClassGenericPassword, kSecMatchLimit,
-    kSecMatchLimitAll, kSecReturnAttributes,
+    kSecAttrAccount, kSecAttrLabel, kSecAttrService, kSecClass, kSecClassGenericPassword,
+    kSecMatchLimit, kSecMatchLimitAll, kSecMatchLimitOne, kSecReturnAttributes, kSecReturnData,
+    kSecValueData,
 };
-use security_framework_sys::keychain_item::SecItemCopyMatching;
+use security_framework_sys::keychain_item::{SecItemAdd, SecItemCopyMatching, SecItemDelete};
 use std::error::Error;
-use std::ptr::null;
+use std::ptr::{null, null_mut};
+use std::sync::Arc;
 use thiserror::Error;
+use zeroize::Zeroizing;
 
 pub const KEYCHAIN_SERVICE: &str = "default-keystore";
 
+/// Value of `kSecAttrService` for the items that represent a secret other than a key.
+pub const SECRET_SERVICE: &str = "default-keystore-secret";
+
 /// Iterator to list all keys in the macOS keychain.
-#[derive(Default)]
 pub struct KeyList {
+    store: Arc<str>,
     items: Option<CFArray>,
     next: CFIndex,
 }
 
+impl KeyList {
+    pub fn new(store: Arc<str>) -> Self {
+        Self {
+            store,
+            items: None,
+            next: 0,
+        }
+    }
+}
+
 impl Iterator for KeyList {
     type Item = Result<Key, Box<dyn Error>>;
 
@@ -110,10 +125,183 @@ impl Iterator for KeyList {
         Some(Ok(Key {
             id,
             created: data.created,
+            stores: vec![self.store.clone()],
         }))
     }
 }
 
+/// Load the key from the keychain.
+pub fn get(id: &KeyId) -> Result<Option<KeyMaterial>, GetError> {
+    // Setup query.
+    let mut query = CFMutableDictionary::new();
+    let service = CFString::from_static_string(KEYCHAIN_SERVICE);
+    let account = CFString::new(&id.to_string());
+
+    unsafe { query.set(kSecMatchLimit.to_void(), kSecMatchLimitOne.to_void()) };
+    unsafe { query.set(kSecClass.to_void(), kSecClassGenericPassword.to_void()) };
+    unsafe { query.set(kSecAttrService.to_void(), service.to_void()) };
+    unsafe { query.set(kSecAttrAccount.to_void(), account.to_void()) };
+    unsafe { query.set(kSecReturnAttributes.to_void(), kCFBooleanTrue.to_void()) };
+    unsafe { query.set(kSecReturnData.to_void(), kCFBooleanTrue.to_void()) };
+
+    unsafe {
+        query.set(
+            kSecAttrSynchronizable.to_void(),
+            kSecAttrSynchronizableAny.to_void(),
+        )
+    };
+
+    unsafe {
+        query.set(
+            kSecUseDataProtectionKeychain.to_void(),
+            kCFBooleanTrue.to_void(),
+        )
+    };
+
+    // Execute the query.
+    let mut item = null();
+
+    #[allow(non_upper_case_globals)]
+    match unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut item) } {
+        0 => {}
+        errSecItemNotFound => return Ok(None),
+        v => return Err(GetError::GetKeyFailed(v)),
+    }
+
+    let item = unsafe { CFType::wrap_under_create_rule(item) };
+    let attrs: CFDictionary = item.downcast_into().unwrap();
+
+    // Get data.
+    let data: CFData = match unsafe { attrs.find(kSecAttrGeneric.to_void()) } {
+        Some(v) => unsafe { CFType::wrap_under_get_rule(*v).downcast_into().unwrap() },
+        None => return Err(GetError::NoKeyData),
+    };
+
+    let data = postcard::from_bytes(&data).map_err(GetError::InvalidKeyData)?;
+
+    // Get key.
+    let key: CFData = match unsafe { attrs.find(kSecValueData.to_void()) } {
+        Some(v) => unsafe { CFType::wrap_under_get_rule(*v).downcast_into().unwrap() },
+        None => return Err(GetError::NoKey),
+    };
+
+    let key = match <[u8; 16]>::try_from(key.bytes()) {
+        Ok(v) => Zeroizing::new(v),
+        Err(_) => return Err(GetError::InvalidKey),
+    };
+
+    Ok(Some(KeyMaterial { key, data }))
+}
+
+/// Remove the key from the keychain.
+pub fn delete(id: &KeyId) -> Result<bool, DeleteError> {
+    // Setup query.
+    let mut query = CFMutableDictionary::new();
+    let service = CFString::from_static_string(KEYCHAIN_SERVICE);
+    let account = CFString::new(&id.to_string());
+
+    unsafe { query.set(kSecClass.to_void(), kSecClassGenericPassword.to_void()) };
+    unsafe { query.set(kSecAttrService.to_void(), service.to_void()) };
+    unsafe { query.set(kSecAttrAccount.to_void(), account.to_void()) };
+
+    unsafe {
+        query.set(
+            kSecAttrSynchronizable.to_void(),
+            kSecAttrSynchronizableAny.to_void(),
+        )
+    };
+
+    unsafe {
+        query.set(
+            kSecUseDataProtectionKeychain.to_void(),
+            kCFBooleanTrue.to_void(),
+        )
+    };
+
+    // Execute the query.
+    #[allow(non_upper_case_globals)]
+    match unsafe { SecItemDelete(query.as_concrete_TypeRef()) } {
+        0 => Ok(true),
+        errSecItemNotFound => Ok(false),
+        v => Err(DeleteError::DeleteFailed(v)),
+    }
+}
+
+/// Store the secret to the keychain, replacing the existing one.
+pub fn set_secret(name: &str, value: &[u8]) -> Result<(), SecretError> {
+    // Remove the existing item.
+    delete_secret(name)?;
+
+    // Setup attributes.
+    let mut attrs = secret_query(name);
+    let value = CFData::from_buffer(value);
+    let label = CFString::new(&format!("Warp Secret ({name})"));
+
+    unsafe { attrs.set(kSecValueData.to_void(), value.to_void()) };
+    unsafe { attrs.set(kSecAttrLabel.to_void(), label.to_void()) };
+
+    // Add to keychain.
+    match unsafe { SecItemAdd(attrs.as_concrete_TypeRef(), null_mut()) } {
+        0 => Ok(()),
+        v => Err(SecretError::StoreFailed(v)),
+    }
+}
+
+/// Load the secret from the keychain.
+pub fn get_secret(name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, SecretError> {
+    // Setup query.
+    let mut query = secret_query(name);
+
+    unsafe { query.set(kSecMatchLimit.to_void(), kSecMatchLimitOne.to_void()) };
+    unsafe { query.set(kSecReturnData.to_void(), kCFBooleanTrue.to_void()) };
+
+    // Execute the query.
+    let mut item = null();
+
+    #[allow(non_upper_case_globals)]
+    match unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut item) } {
+        0 => {}
+        errSecItemNotFound => return Ok(None),
+        v => return Err(SecretError::GetFailed(v)),
+    }
+
+    let item = unsafe { CFType::wrap_under_create_rule(item) };
+    let data: CFData = item.downcast_into().unwrap();
+
+    Ok(Some(Zeroizing::new(data.bytes().to_vec())))
+}
+
+/// Remove the secret from the keychain.
+pub fn delete_secret(name: &str) -> Result<bool, SecretError> {
+    let query = secret_query(name);
+
+    #[allow(non_upper_case_globals)]
+    match unsafe { SecItemDelete(query.as_concrete_TypeRef()) } {
+        0 => Ok(true),
+        errSecItemNotFound => Ok(false),
+        v => Err(SecretError::DeleteFailed(v)),
+    }
+}
+
+fn secret_query(name: &str) -> CFMutableDictionary {
+    let mut query = CFMutableDictionary::new();
+    let service = CFString::from_static_string(SECRET_SERVICE);
+    let account = CFString::new(name);
+
+    unsafe { query.set(kSecClass.to_void(), kSecClassGenericPassword.to_void()) };
+    unsafe { query.set(kSecAttrService.to_void(), service.to_void()) };
+    unsafe { query.set(kSecAttrAccount.to_void(), account.to_void()) };
+
+    unsafe {
+        query.set(
+            kSecUseDataProtectionKeychain.to_void(),
+            kCFBooleanTrue.to_void(),
+        )
+    };
+
+    query
+}
+
 declare_TCFType! { SecAccessControl, SecAccessControlRef }
 
 impl_TCFType!(
@@ -141,6 +329,45 @@ enum ListError {
     InvalidKeyData(#[source] postcard::Error),
 }
 
+/// Represents an error when [`get()`] fails.
+#[derive(Debug, Error)]
+pub enum GetError {
+    #[error("couldn't get the keychain item (code: {0})")]
+    GetKeyFailed(core_foundation::base::OSStatus),
+
+    #[error("no kSecAttrGeneric on the item")]
+    NoKeyData,
+
+    #[error("kSecAttrGeneric has invalid value")]
+    InvalidKeyData(#[source] postcard::Error),
+
+    #[error("no kSecValueData on the item")]
+    NoKey,
+
+    #[error("kSecValueData is not a valid key")]
+    InvalidKey,
+}
+
+/// Represents an error when [`delete()`] fails.
+#[derive(Debug, Error)]
+pub enum DeleteError {
+    #[error("couldn't delete the keychain item (code: {0})")]
+    DeleteFailed(core_foundation::base::OSStatus),
+}
+
+/// Represents an error when [`set_secret()`], [`get_secret()`] or [`delete_secret()`] fails.
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("couldn't store the keychain item (code: {0})")]
+    StoreFailed(core_foundation::base::OSStatus),
+
+    #[error("couldn't get the keychain item (code: {0})")]
+    GetFailed(core_foundation::base::OSStatus),
+
+    #[error("couldn't delete the keychain item (code: {0})")]
+    DeleteFailed(core_foundation::base::OSStatus),
+}
+
 extern "C" {
     pub static kSecAttrGeneric: CFStringRef;
     pub static kSecAttrSynchronizable: CFStringRef;
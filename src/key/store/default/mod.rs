@@ -6,13 +6,9 @@ use self::macos::KeyList;
 use self::windows::KeyList;
 use super::Keystore;
 use crate::home::Home;
-use crate::key::{Key, KeyId, KeyMgr};
-use aes::cipher::{BlockEncrypt, KeyInit};
-use aes::Aes128;
+use crate::key::{Key, KeyData, KeyId, KeyMaterial};
 use getrandom::getrandom;
-use serde::{Deserialize, Serialize};
-use sha3::digest::{ExtendableOutput, Update, XofReader};
-use sha3::Shake128;
+use serde::Deserialize;
 use std::error::Error;
 use std::ops::DerefMut;
 use std::sync::Arc;
@@ -29,18 +25,61 @@ mod windows;
 
 /// Implementation of [`Keystore`] using native key store of the OS.
 pub struct DefaultStore {
+    id: Arc<str>,
     #[allow(dead_code)]
     home: Arc<Home>,
 }
 
 impl DefaultStore {
-    pub fn new(home: &Arc<Home>) -> Self {
-        Self { home: home.clone() }
+    pub fn new(id: &str, home: &Arc<Home>, _: DefaultOptions) -> Self {
+        Self {
+            id: id.into(),
+            home: home.clone(),
+        }
     }
 
     #[cfg(target_os = "linux")]
-    fn store(&self, _: &KeyId, _: &[u8], _: &KeyData) -> Result<(), GenerateError> {
-        todo!()
+    fn store(&self, id: &KeyId, key: &[u8], data: &KeyData) -> Result<(), GenerateError> {
+        use self::linux::{ATTR_DATA, ATTR_ID, SCHEMA};
+        use secret_service::blocking::SecretService;
+        use secret_service::{EncryptionType, Error};
+        use std::collections::HashMap;
+
+        // Get the default collection.
+        let ss =
+            SecretService::connect(EncryptionType::Dh).map_err(GenerateError::StoreKeyFailed)?;
+        let collection = match ss.get_default_collection() {
+            Ok(v) => v,
+            Err(Error::NoResult) => ss
+                .create_collection("Default keyring", "default")
+                .map_err(GenerateError::StoreKeyFailed)?,
+            Err(e) => return Err(GenerateError::StoreKeyFailed(e)),
+        };
+
+        collection
+            .ensure_unlocked()
+            .map_err(GenerateError::StoreKeyFailed)?;
+
+        // Store the key.
+        let id = id.to_string();
+        let data = hex::encode(postcard::to_stdvec(data).unwrap());
+        let attrs = HashMap::from([
+            ("xdg:schema", SCHEMA),
+            (ATTR_ID, id.as_str()),
+            (ATTR_DATA, data.as_str()),
+        ]);
+
+        collection
+            .create_item(
+                "Warp File Key",
+                attrs,
+                key,
+                false,
+                "application/octet-stream",
+            )
+            .map_err(GenerateError::StoreKeyFailed)?;
+
+        Ok(())
     }
 
     #[cfg(target_os = "macos")]
@@ -160,37 +199,23 @@ impl DefaultStore {
 
         Ok(())
     }
-
-    fn get_id(key: &[u8; 16]) -> KeyId {
-        // Get a key check value.
-        let mut kcv = [0u8; 16];
-
-        Aes128::new(key.into()).encrypt_block((&mut kcv).into());
-
-        // Get key ID.
-        let mut hasher = Shake128::default();
-        let mut id = [0u8; 16];
-
-        hasher.update(&kcv);
-        hasher.finalize_xof().read(&mut id);
-
-        KeyId(id)
-    }
 }
 
+/// Options of [`DefaultStore`] in `key.stores` of the configurations.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultOptions {}
+
 impl Keystore for DefaultStore {
-    fn id(&self) -> &'static str {
-        KeyMgr::DEFAULT_STORE
+    fn id(&self) -> &str {
+        &self.id
     }
 
-    fn list(self: &Arc<Self>) -> impl Iterator<Item = Result<Key, Box<dyn Error>>>
-    where
-        Self: Sized,
-    {
-        KeyList::default()
+    fn list(&self) -> Box<dyn Iterator<Item = Result<Key, Box<dyn Error>>> + '_> {
+        Box::new(KeyList::new(self.id.clone()))
     }
 
-    fn generate(self: Arc<Self>) -> Result<Key, Box<dyn Error>> {
+    fn generate(&self) -> Result<Key, Box<dyn Error>> {
         // Generate a new key.
         let mut key = Zeroizing::new([0u8; 16]);
 
@@ -199,48 +224,104 @@ impl Keystore for DefaultStore {
         }
 
         // Store the key.
-        let id = Self::get_id(&key);
-        let data = KeyData {
-            kdf: KeyDerivation::HkdfSha3256,
-            enc: Encryption::AesCtr128,
-            mac: Some(Mac::HmacSha3256),
-            created: SystemTime::now(),
-        };
+        let id = KeyId::from_key(&key);
+        let data = KeyData::new(SystemTime::now());
 
         self.store(&id, key.as_ref(), &data)?;
 
         Ok(Key {
             id,
             created: data.created,
+            stores: vec![self.id.clone()],
         })
     }
-}
 
-/// Per-key data stored unencrypted with the key.
-#[derive(Serialize, Deserialize)]
-struct KeyData {
-    kdf: KeyDerivation,
-    enc: Encryption,
-    mac: Option<Mac>,
-    created: SystemTime,
-}
+    fn import(&self, key: &KeyMaterial) -> Result<Key, Box<dyn Error>> {
+        let id = KeyId::from_key(&key.key);
 
-/// Key derivation algorithm of the key.
-#[derive(Serialize, Deserialize)]
-enum KeyDerivation {
-    HkdfSha3256,
-}
+        self.store(&id, key.key.as_ref(), &key.data)?;
 
-/// Encryption algorithm of the key.
-#[derive(Serialize, Deserialize)]
-enum Encryption {
-    AesCtr128,
-}
+        Ok(Key {
+            id,
+            created: key.data.created,
+            stores: vec![self.id.clone()],
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        Ok(self::linux::get(id)?)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        Ok(self::macos::get(id)?)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn get(&self, _: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        Err(Box::new(UnsupportedError::GetKey))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>> {
+        Ok(self::linux::delete(id)?)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>> {
+        Ok(self::macos::delete(id)?)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn delete(&self, _: &KeyId) -> Result<bool, Box<dyn Error>> {
+        Err(Box::new(UnsupportedError::DeleteKey))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_secret(&self, name: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        Ok(self::linux::set_secret(name, value)?)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn set_secret(&self, name: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        Ok(self::macos::set_secret(name, value)?)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn set_secret(&self, _: &str, _: &[u8]) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(UnsupportedError::SetSecret))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        Ok(self::linux::get_secret(name)?)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        Ok(self::macos::get_secret(name)?)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn get_secret(&self, _: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        Err(Box::new(UnsupportedError::GetSecret))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self::linux::delete_secret(name)?)
+    }
 
-/// Message authentication code of the key.
-#[derive(Serialize, Deserialize)]
-enum Mac {
-    HmacSha3256,
+    #[cfg(target_os = "macos")]
+    fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self::macos::delete_secret(name)?)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn delete_secret(&self, _: &str) -> Result<bool, Box<dyn Error>> {
+        Err(Box::new(UnsupportedError::DeleteSecret))
+    }
 }
 
 /// Represents an error when [`DefaultStore::new()`] fails.
@@ -249,6 +330,10 @@ enum GenerateError {
     #[error("couldn't generate a new key")]
     GenerateKeyFailed(#[source] getrandom::Error),
 
+    #[cfg(target_os = "linux")]
+    #[error("couldn't store the generated key to the Secret Service")]
+    StoreKeyFailed(#[source] secret_service::Error),
+
     #[cfg(target_os = "macos")]
     #[error("couldn't store the generated key to a keychain (code: {0})")]
     StoreKeyFailed(core_foundation::base::OSStatus),
@@ -269,3 +354,24 @@ enum GenerateError {
     #[error("couldn't write {0}")]
     WriteFileFailed(std::path::PathBuf, #[source] std::io::Error),
 }
+
+/// Represents an error when an operation of [`DefaultStore`] is not implemented on the current
+/// platform.
+#[cfg(target_os = "windows")]
+#[derive(Debug, Error)]
+enum UnsupportedError {
+    #[error("reading a key is not supported on this platform yet")]
+    GetKey,
+
+    #[error("removing a key is not supported on this platform yet")]
+    DeleteKey,
+
+    #[error("storing a secret is not supported on this platform yet")]
+    SetSecret,
+
+    #[error("reading a secret is not supported on this platform yet")]
+    GetSecret,
+
+    #[error("removing a secret is not supported on this platform yet")]
+    DeleteSecret,
+}
@@ -1,10 +1,16 @@
 use crate::key::Key;
 use std::error::Error;
+use std::sync::Arc;
 
 /// Iterator to list all keys in a Warp home.
-#[derive(Default)]
 pub struct KeyList {}
 
+impl KeyList {
+    pub fn new(_: Arc<str>) -> Self {
+        Self {}
+    }
+}
+
 impl Iterator for KeyList {
     type Item = Result<Key, Box<dyn Error>>;
 
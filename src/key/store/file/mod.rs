@@ -0,0 +1,582 @@
+use super::Keystore;
+use crate::home::Home;
+use crate::key::{read_passphrase, Key, KeyData, KeyId, KeyMaterial, PassphraseKdf};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{create_dir_all, read_dir, remove_file, rename, File, OpenOptions, ReadDir};
+use std::io::{Read, Write};
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Implementation of [`Keystore`] that keep keys in files encrypted with a passphrase.
+pub struct FileStore {
+    id: Arc<str>,
+    path: PathBuf,
+}
+
+impl FileStore {
+    /// Keys are stored in `~/.warp/keys/<id>` unless `path` option is specified.
+    pub fn new(id: &str, home: &Arc<Home>, options: FileOptions) -> Self {
+        Self {
+            id: id.into(),
+            path: options.path.unwrap_or_else(|| home.keys().join(id)),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns a path of the file to store the secret named `name`. The secrets are stored outside
+    /// [`Self::path()`] so they don't get listed as keys.
+    fn secret(&self, name: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push("-secrets");
+
+        let mut path = PathBuf::from(path);
+        path.push(hex::encode(name));
+        path
+    }
+
+    fn store(&self, id: &KeyId, key: &[u8; 16], data: KeyData) -> Result<(), GenerateError> {
+        // Derive the key to encrypt the key.
+        let pass = passphrase(true).map_err(GenerateError::GetPassphraseFailed)?;
+        let mut salt = [0u8; 16];
+
+        if let Err(e) = getrandom(&mut salt) {
+            return Err(GenerateError::GenerateSaltFailed(e));
+        }
+
+        let kdf = PassphraseKdf::new(salt);
+
+        let kek = kdf.derive(&pass).unwrap();
+
+        // Encrypt the key.
+        let mut nonce = [0u8; 12];
+
+        if let Err(e) = getrandom(&mut nonce) {
+            return Err(GenerateError::GenerateNonceFailed(e));
+        }
+
+        let aad = KeyFile::aad(id, &data);
+        let key = Aes256Gcm::new(kek.as_ref().into())
+            .encrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: key,
+                    aad: &aad,
+                },
+            )
+            .unwrap();
+
+        // Ensure the directory to store the key are exists.
+        let mut path = self.path();
+
+        if let Err(e) = create_dir_all(&path) {
+            return Err(GenerateError::CreateDirectoryFailed(path, e));
+        }
+
+        path.push(id.to_string());
+
+        // Write the key.
+        let file = KeyFile {
+            data,
+            kdf,
+            nonce,
+            key,
+        };
+
+        if path.exists() {
+            let e = std::io::Error::from(std::io::ErrorKind::AlreadyExists);
+            return Err(GenerateError::CreateFileFailed(path, e));
+        }
+
+        // Write to a temporary file then move it to the final path so a crash in the middle does
+        // not leave a truncated key.
+        let tmp = path.with_extension("tmp");
+        let mut opts = OpenOptions::new();
+
+        opts.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+
+        let mut fp = match opts.open(&tmp) {
+            Ok(v) => v,
+            Err(e) => return Err(GenerateError::CreateFileFailed(tmp, e)),
+        };
+
+        if let Err(e) = fp
+            .write_all(&postcard::to_stdvec(&file).unwrap())
+            .and_then(|_| fp.sync_all())
+        {
+            drop(fp);
+            remove_file(&tmp).ok();
+            return Err(GenerateError::WriteFileFailed(tmp, e));
+        }
+
+        drop(fp);
+
+        if let Err(e) = rename(&tmp, &path) {
+            remove_file(&tmp).ok();
+            return Err(GenerateError::WriteFileFailed(path, e));
+        }
+
+        Ok(())
+    }
+}
+
+/// Options of [`FileStore`] in `key.stores` of the configurations.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileOptions {
+    path: Option<PathBuf>,
+}
+
+impl Keystore for FileStore {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn list(&self) -> Box<dyn Iterator<Item = Result<Key, Box<dyn Error>>> + '_> {
+        Box::new(KeyList {
+            store: self.id.clone(),
+            path: self.path(),
+            dir: None,
+            done: false,
+        })
+    }
+
+    fn generate(&self) -> Result<Key, Box<dyn Error>> {
+        // Generate a new key.
+        let mut key = Zeroizing::new([0u8; 16]);
+
+        if let Err(e) = getrandom(key.deref_mut()) {
+            return Err(Box::new(GenerateError::GenerateKeyFailed(e)));
+        }
+
+        // Store the key.
+        let id = KeyId::from_key(&key);
+        let created = SystemTime::now();
+
+        self.store(&id, &key, KeyData::new(created))?;
+
+        Ok(Key {
+            id,
+            created,
+            stores: vec![self.id.clone()],
+        })
+    }
+
+    fn import(&self, key: &KeyMaterial) -> Result<Key, Box<dyn Error>> {
+        let id = KeyId::from_key(&key.key);
+
+        self.store(&id, &key.key, key.data.clone())?;
+
+        Ok(Key {
+            id,
+            created: key.data.created,
+            stores: vec![self.id.clone()],
+        })
+    }
+
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        // Read the file.
+        let path = self.path().join(id.to_string());
+        let mut data = Vec::new();
+
+        match File::open(&path) {
+            Ok(mut v) => {
+                if let Err(e) = v.read_to_end(&mut data) {
+                    return Err(Box::new(GetError::ReadFileFailed(path, e)));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Box::new(GetError::ReadFileFailed(path, e))),
+        }
+
+        let file: KeyFile = match postcard::from_bytes(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(GetError::InvalidKeyFile(path, e))),
+        };
+
+        // Derive the key to decrypt the key.
+        let pass = passphrase(false).map_err(GetError::GetPassphraseFailed)?;
+        let kek = match file.kdf.derive(&pass) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(GetError::DeriveKeyFailed(path, e))),
+        };
+
+        // Decrypt the key.
+        let aad = KeyFile::aad(id, &file.data);
+        let key = Aes256Gcm::new(kek.as_ref().into())
+            .decrypt(
+                (&file.nonce).into(),
+                Payload {
+                    msg: &file.key,
+                    aad: &aad,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| GetError::WrongPassphrase)?;
+        let key = match <[u8; 16]>::try_from(key.as_slice()) {
+            Ok(v) => Zeroizing::new(v),
+            Err(_) => return Err(Box::new(GetError::InvalidKey(path))),
+        };
+
+        Ok(Some(KeyMaterial {
+            key,
+            data: file.data,
+        }))
+    }
+
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>> {
+        let path = self.path().join(id.to_string());
+
+        match remove_file(&path) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Box::new(DeleteError::DeleteFileFailed(path, e))),
+        }
+    }
+
+    fn set_secret(&self, name: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        // Derive the key to encrypt the secret.
+        let pass = passphrase(true).map_err(SecretError::GetPassphraseFailed)?;
+        let mut salt = [0u8; 16];
+
+        getrandom(&mut salt).map_err(SecretError::GenerateSaltFailed)?;
+
+        let kdf = PassphraseKdf::new(salt);
+
+        let kek = kdf.derive(&pass).unwrap();
+
+        // Encrypt the secret.
+        let mut nonce = [0u8; 12];
+
+        getrandom(&mut nonce).map_err(SecretError::GenerateNonceFailed)?;
+
+        let value = Aes256Gcm::new(kek.as_ref().into())
+            .encrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: value,
+                    aad: name.as_bytes(),
+                },
+            )
+            .unwrap();
+
+        // Ensure the directory to store the secret are exists.
+        let path = self.secret(name);
+        let dir = path.parent().unwrap();
+
+        if let Err(e) = create_dir_all(dir) {
+            return Err(Box::new(SecretError::CreateDirectoryFailed(
+                dir.to_owned(),
+                e,
+            )));
+        }
+
+        // Write to a temporary file then replace the old one.
+        let file = SecretFile { kdf, nonce, value };
+        let tmp = path.with_extension("tmp");
+        let mut opts = OpenOptions::new();
+
+        opts.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+
+        let mut fp = match opts.open(&tmp) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(SecretError::CreateFileFailed(tmp, e))),
+        };
+
+        if let Err(e) = fp.write_all(&postcard::to_stdvec(&file).unwrap()) {
+            drop(fp);
+            remove_file(&tmp).unwrap();
+            return Err(Box::new(SecretError::WriteFileFailed(tmp, e)));
+        }
+
+        drop(fp);
+
+        if let Err(e) = rename(&tmp, &path) {
+            return Err(Box::new(SecretError::WriteFileFailed(path, e)));
+        }
+
+        Ok(())
+    }
+
+    fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        // Read the file. We need to check if the file exists before asking for the passphrase.
+        let path = self.secret(name);
+        let data = match std::fs::read(&path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Box::new(SecretError::ReadFileFailed(path, e))),
+        };
+
+        let file: SecretFile = match postcard::from_bytes(&data) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(SecretError::InvalidSecretFile(path, e))),
+        };
+
+        // Derive the key to decrypt the secret.
+        let pass = passphrase(false).map_err(SecretError::GetPassphraseFailed)?;
+        let kek = match file.kdf.derive(&pass) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(SecretError::DeriveKeyFailed(path, e))),
+        };
+
+        // Decrypt the secret.
+        let value = Aes256Gcm::new(kek.as_ref().into())
+            .decrypt(
+                (&file.nonce).into(),
+                Payload {
+                    msg: &file.value,
+                    aad: name.as_bytes(),
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| SecretError::WrongPassphrase)?;
+
+        Ok(Some(value))
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        let path = self.secret(name);
+
+        match remove_file(&path) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Box::new(SecretError::DeleteFileFailed(path, e))),
+        }
+    }
+}
+
+/// Iterator to list all keys in the file store.
+struct KeyList {
+    store: Arc<str>,
+    path: PathBuf,
+    dir: Option<ReadDir>,
+    done: bool,
+}
+
+impl KeyList {
+    /// Returns [`None`] if `path` is not a key (e.g. `.DS_Store` or a temporary file).
+    fn load(store: &Arc<str>, path: &Path) -> Result<Option<Key>, ListError> {
+        // Get key ID.
+        let id = match path.file_name().unwrap().to_str().map(|v| v.parse()) {
+            Some(Ok(v)) => v,
+            _ => return Ok(None),
+        };
+
+        // Read the file.
+        let mut data = Vec::new();
+
+        File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut data))
+            .map_err(|e| ListError::ReadFileFailed(path.to_owned(), e))?;
+
+        let file: KeyFile = postcard::from_bytes(&data)
+            .map_err(|e| ListError::InvalidKeyFile(path.to_owned(), e))?;
+
+        Ok(Some(Key {
+            id,
+            created: file.data.created,
+            stores: vec![store.clone()],
+        }))
+    }
+}
+
+impl Iterator for KeyList {
+    type Item = Result<Key, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // Open the directory.
+        let dir = match &mut self.dir {
+            Some(v) => v,
+            None => match read_dir(&self.path) {
+                Ok(v) => self.dir.insert(v),
+                Err(e) => {
+                    self.done = true;
+
+                    return if e.kind() == std::io::ErrorKind::NotFound {
+                        None
+                    } else {
+                        Some(Err(Box::new(ListError::ReadDirectoryFailed(
+                            self.path.clone(),
+                            e,
+                        ))))
+                    };
+                }
+            },
+        };
+
+        // Get next key.
+        loop {
+            let item = match dir.next()? {
+                Ok(v) => v,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(Box::new(ListError::ReadDirectoryFailed(
+                        self.path.clone(),
+                        e,
+                    ))));
+                }
+            };
+
+            match Self::load(&self.store, &item.path()) {
+                Ok(Some(v)) => return Some(Ok(v)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+/// Content of a key file.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    data: KeyData,
+    kdf: PassphraseKdf,
+    nonce: [u8; 12],
+    key: Vec<u8>,
+}
+
+impl KeyFile {
+    /// Get the data to authenticate together with the encrypted key.
+    fn aad(id: &KeyId, data: &KeyData) -> Vec<u8> {
+        let mut aad = id.as_ref().to_vec();
+        aad.extend(postcard::to_stdvec(data).unwrap());
+        aad
+    }
+}
+
+/// Content of a secret file.
+#[derive(Serialize, Deserialize)]
+struct SecretFile {
+    kdf: PassphraseKdf,
+    nonce: [u8; 12],
+    value: Vec<u8>,
+}
+
+/// Get passphrase from `WARP_PASSPHRASE` or prompt the user if it is not set.
+fn passphrase(confirm: bool) -> Result<Zeroizing<String>, std::io::Error> {
+    read_passphrase("WARP_PASSPHRASE", "Passphrase for file keystore: ", confirm)
+}
+
+/// Represents an error when [`KeyList::next()`] fails.
+#[derive(Debug, Error)]
+enum ListError {
+    #[error("couldn't read {0}")]
+    ReadDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid key file")]
+    InvalidKeyFile(PathBuf, #[source] postcard::Error),
+}
+
+/// Represents an error when [`FileStore::get()`] fails.
+#[derive(Debug, Error)]
+enum GetError {
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid key file")]
+    InvalidKeyFile(PathBuf, #[source] postcard::Error),
+
+    #[error("couldn't get the passphrase")]
+    GetPassphraseFailed(#[source] std::io::Error),
+
+    #[error("couldn't derive a key from the passphrase for {0}")]
+    DeriveKeyFailed(PathBuf, #[source] argon2::Error),
+
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("{0} contains an invalid key")]
+    InvalidKey(PathBuf),
+}
+
+/// Represents an error when [`FileStore::delete()`] fails.
+#[derive(Debug, Error)]
+enum DeleteError {
+    #[error("couldn't remove {0}")]
+    DeleteFileFailed(PathBuf, #[source] std::io::Error),
+}
+
+/// Represents an error when [`FileStore::generate()`] fails.
+#[derive(Debug, Error)]
+enum GenerateError {
+    #[error("couldn't generate a new key")]
+    GenerateKeyFailed(#[source] getrandom::Error),
+
+    #[error("couldn't get the passphrase")]
+    GetPassphraseFailed(#[source] std::io::Error),
+
+    #[error("couldn't generate a salt")]
+    GenerateSaltFailed(#[source] getrandom::Error),
+
+    #[error("couldn't generate a nonce")]
+    GenerateNonceFailed(#[source] getrandom::Error),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+}
+
+/// Represents an error when [`FileStore`] fails to manage a secret.
+#[derive(Debug, Error)]
+enum SecretError {
+    #[error("couldn't get the passphrase")]
+    GetPassphraseFailed(#[source] std::io::Error),
+
+    #[error("couldn't generate a salt")]
+    GenerateSaltFailed(#[source] getrandom::Error),
+
+    #[error("couldn't generate a nonce")]
+    GenerateNonceFailed(#[source] getrandom::Error),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid secret file")]
+    InvalidSecretFile(PathBuf, #[source] postcard::Error),
+
+    #[error("couldn't derive a key from the passphrase for {0}")]
+    DeriveKeyFailed(PathBuf, #[source] argon2::Error),
+
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+
+    #[error("couldn't delete {0}")]
+    DeleteFileFailed(PathBuf, #[source] std::io::Error),
+}
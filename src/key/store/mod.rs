@@ -1,17 +1,138 @@
 pub use self::default::*;
-use super::Key;
+pub use self::file::*;
+pub use self::pass::*;
+pub use self::plugin::*;
+use super::{Key, KeyId, KeyMaterial};
+use crate::home::Home;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
 use std::error::Error;
 use std::sync::Arc;
+use thiserror::Error;
+use zeroize::Zeroizing;
 
 mod default;
+mod file;
+mod pass;
+mod plugin;
 
 /// Storage to keep encryption keys.
+///
+/// This trait is object safe so [`super::KeyMgr`] can work with any store that was created from
+/// [`KeystoreRegistry`].
 pub trait Keystore: Send + Sync {
-    fn id(&self) -> &'static str;
+    /// ID of the store as specified in `key.stores` of the configurations.
+    fn id(&self) -> &str;
 
-    fn list(self: &Arc<Self>) -> impl Iterator<Item = Result<Key, Box<dyn Error>>>
-    where
-        Self: Sized;
+    fn list(&self) -> Box<dyn Iterator<Item = Result<Key, Box<dyn Error>>> + '_>;
 
-    fn generate(self: Arc<Self>) -> Result<Key, Box<dyn Error>>;
+    fn generate(&self) -> Result<Key, Box<dyn Error>>;
+
+    /// Store an existing key (e.g. from a backup).
+    fn import(&self, key: &KeyMaterial) -> Result<Key, Box<dyn Error>>;
+
+    /// Returns [`None`] if the key does not exists.
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>>;
+
+    /// Remove the key permanently. Returns `false` if the key does not exists.
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>>;
+
+    /// Store an arbitrary secret that is not a key (e.g. a credential), replacing the existing one
+    /// with the same `name`.
+    fn set_secret(&self, name: &str, value: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    /// Returns [`None`] if the secret does not exists.
+    fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>>;
+
+    /// Returns `false` if the secret does not exists.
+    fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>>;
+}
+
+/// Function to create a [`Keystore`] with the specified ID and options from `key.stores` of the
+/// configurations.
+pub type KeystoreFactory =
+    fn(&str, &Arc<Home>, &Mapping) -> Result<Arc<dyn Keystore>, KeystoreConfigError>;
+
+/// List of [`Keystore`] types that can be created by [`super::KeyMgr`].
+pub struct KeystoreRegistry {
+    types: Vec<(&'static str, KeystoreFactory)>,
+}
+
+impl KeystoreRegistry {
+    pub fn new() -> Self {
+        Self { types: Vec::new() }
+    }
+
+    /// Returns `false` if `name` is already registered.
+    pub fn register(&mut self, name: &'static str, factory: KeystoreFactory) -> bool {
+        if self.types.iter().any(|(n, _)| *n == name) {
+            return false;
+        }
+
+        self.types.push((name, factory));
+
+        true
+    }
+
+    /// Create a store of type `ty` with `id` and `options`.
+    pub fn create(
+        &self,
+        id: &str,
+        ty: &str,
+        home: &Arc<Home>,
+        options: &Mapping,
+    ) -> Result<Arc<dyn Keystore>, KeystoreConfigError> {
+        match self.types.iter().find(|(n, _)| *n == ty) {
+            Some((_, f)) => f(id, home, options),
+            None => Err(KeystoreConfigError::UnknownType(ty.to_owned())),
+        }
+    }
+}
+
+impl Default for KeystoreRegistry {
+    /// Create a [`KeystoreRegistry`] with all built-in stores.
+    fn default() -> Self {
+        let mut r = Self::new();
+
+        r.register(super::KeyMgr::DEFAULT_STORE, |id, home, options| {
+            Ok(Arc::new(DefaultStore::new(
+                id,
+                home,
+                parse_options(options)?,
+            )))
+        });
+
+        r.register(super::KeyMgr::FILE_STORE, |id, home, options| {
+            Ok(Arc::new(FileStore::new(id, home, parse_options(options)?)))
+        });
+
+        r.register(super::KeyMgr::PASS_STORE, |id, _, options| {
+            Ok(Arc::new(PassStore::new(id, parse_options(options)?)))
+        });
+
+        r.register(super::KeyMgr::PLUGIN_STORE, |id, _, options| {
+            Ok(Arc::new(PluginStore::new(id, parse_options(options)?)?))
+        });
+
+        r
+    }
+}
+
+/// Deserialize the store-specific options from `key.stores` of the configurations.
+fn parse_options<T: DeserializeOwned>(options: &Mapping) -> Result<T, KeystoreConfigError> {
+    serde_yaml::from_value(Value::Mapping(options.clone()))
+        .map_err(KeystoreConfigError::InvalidOptions)
+}
+
+/// Represents an error when a [`Keystore`] cannot be created from its configurations.
+#[derive(Debug, Error)]
+pub enum KeystoreConfigError {
+    #[error("unknown keystore type '{0}'")]
+    UnknownType(String),
+
+    #[error("invalid options")]
+    InvalidOptions(#[source] serde_yaml::Error),
+
+    #[error("couldn't find {0} on PATH")]
+    PluginNotFound(String),
 }
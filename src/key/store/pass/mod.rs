@@ -0,0 +1,449 @@
+use super::Keystore;
+use crate::key::{Key, KeyData, KeyId, KeyMaterial};
+use dirs::home_dir;
+use getrandom::getrandom;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::{create_dir_all, read_dir, remove_file, rename};
+use std::io::Write;
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::SystemTime;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Implementation of [`Keystore`] that keep keys in a password store of
+/// [pass](https://www.passwordstore.org).
+///
+/// Each key is stored as `<prefix>/<id>.gpg` in the same format as `pass insert` so `pass show`
+/// works on it. The entry is encrypted with `gpg` for the recipients in the nearest `.gpg-id` and
+/// the unencrypted [`KeyData`] is stored in `<prefix>/<id>.data`. `GNUPGHOME` is respected since
+/// `gpg` inherits our environment.
+pub struct PassStore {
+    id: Arc<str>,
+    root: PathBuf,
+    path: PathBuf,
+}
+
+impl PassStore {
+    /// Use `$PASSWORD_STORE_DIR` or `~/.password-store` if `path` option is not specified and `warp`
+    /// if `prefix` option is not specified.
+    pub fn new(id: &str, options: PassOptions) -> Self {
+        // Home::new() already make sure the user's home is available.
+        let root = options.path.unwrap_or_else(|| {
+            std::env::var_os("PASSWORD_STORE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home_dir().unwrap().join(".password-store"))
+        });
+
+        let path = root.join(options.prefix.as_deref().unwrap_or("warp"));
+
+        Self {
+            id: id.into(),
+            root,
+            path,
+        }
+    }
+
+    fn key(&self, id: &KeyId) -> PathBuf {
+        self.path.join(format!("{id}.gpg"))
+    }
+
+    fn data(&self, id: &KeyId) -> PathBuf {
+        self.path.join(format!("{id}.data"))
+    }
+
+    /// Secrets are stored in a sub-directory so they don't get listed as keys.
+    fn secret(&self, name: &str) -> PathBuf {
+        self.path
+            .join("secrets")
+            .join(format!("{}.gpg", hex::encode(name)))
+    }
+
+    fn store(&self, id: &KeyId, key: &[u8; 16], data: &KeyData) -> Result<(), StoreError> {
+        // Write the data first so the key is never listed without it.
+        let path = self.data(id);
+
+        if let Err(e) = create_dir_all(&self.path) {
+            return Err(StoreError::CreateDirectoryFailed(self.path.clone(), e));
+        }
+
+        if let Err(e) = std::fs::write(&path, hex::encode(postcard::to_stdvec(data).unwrap())) {
+            return Err(StoreError::WriteFileFailed(path, e));
+        }
+
+        // Write the key in the same format as pass, which is the password on the first line.
+        let mut entry = Zeroizing::new(hex::encode(key));
+
+        entry.push('\n');
+
+        if let Err(e) = self.encrypt(&self.key(id), entry.as_bytes()) {
+            remove_file(&path).ok();
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Returns recipients from the nearest `.gpg-id` of `path` like pass.
+    fn recipients(&self, path: &Path) -> Result<Vec<String>, StoreError> {
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+
+            let file = dir.join(".gpg-id");
+            let data = match std::fs::read_to_string(&file) {
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::ReadFileFailed(file, e)),
+            };
+
+            let ids: Vec<String> = data
+                .lines()
+                .map(|l| l.split('#').next().unwrap().trim())
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_owned())
+                .collect();
+
+            if ids.is_empty() {
+                return Err(StoreError::NoRecipients(file));
+            }
+
+            return Ok(ids);
+        }
+
+        Err(StoreError::NotInitialized(self.root.clone()))
+    }
+
+    fn encrypt(&self, path: &Path, data: &[u8]) -> Result<(), StoreError> {
+        // Ensure the directory to store the entry are exists.
+        let recipients = self.recipients(path)?;
+        let dir = path.parent().unwrap();
+
+        if let Err(e) = create_dir_all(dir) {
+            return Err(StoreError::CreateDirectoryFailed(dir.to_owned(), e));
+        }
+
+        // Encrypt to a temporary file then replace the old one.
+        let tmp = path.with_extension("tmp");
+        let mut gpg = gpg();
+
+        gpg.arg("--encrypt");
+
+        for r in recipients {
+            gpg.arg("-r").arg(r);
+        }
+
+        let mut child = gpg
+            .arg("--output")
+            .arg(&tmp)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(StoreError::StartGpgFailed)?;
+        let mut stdin = child.stdin.take().unwrap();
+        let written = stdin.write_all(data);
+
+        drop(stdin);
+
+        let status = child.wait().map_err(StoreError::StartGpgFailed)?;
+
+        if let Err(e) = written {
+            remove_file(&tmp).ok();
+            return Err(StoreError::WriteGpgFailed(e));
+        } else if !status.success() {
+            remove_file(&tmp).ok();
+            return Err(StoreError::GpgFailed(path.to_owned(), status));
+        }
+
+        if let Err(e) = rename(&tmp, path) {
+            return Err(StoreError::WriteFileFailed(path.to_owned(), e));
+        }
+
+        Ok(())
+    }
+
+    /// Returns [`None`] if `path` does not exists.
+    fn decrypt(&self, path: &Path) -> Result<Option<Zeroizing<Vec<u8>>>, GetError> {
+        // We need to check if the file exists before running gpg since it may ask for a passphrase.
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        // Let gpg use our stdin so pinentry can ask for the passphrase if required.
+        let output = gpg()
+            .arg("--decrypt")
+            .arg(path)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(GetError::StartGpgFailed)?;
+        let data = Zeroizing::new(output.stdout);
+
+        if !output.status.success() {
+            return Err(GetError::GpgFailed(path.to_owned(), output.status));
+        }
+
+        Ok(Some(data))
+    }
+
+    fn load(&self, path: &Path) -> Result<Option<Key>, ListError> {
+        // Skip non-key files (e.g. the data or the other entries the user put in the same folder).
+        let name = match path.file_name().unwrap().to_str() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let id: KeyId = match name.strip_suffix(".gpg").map(|v| v.parse()) {
+            Some(Ok(v)) if path.is_file() => v,
+            _ => return Ok(None),
+        };
+
+        // Read the data.
+        let path = self.data(&id);
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| ListError::ReadFileFailed(path.clone(), e))?;
+        let data = decode_data(&data).ok_or(ListError::InvalidKeyData(path))?;
+
+        Ok(Some(Key {
+            id,
+            created: data.created,
+            stores: vec![self.id.clone()],
+        }))
+    }
+}
+
+/// Options of [`PassStore`] in `key.stores` of the configurations.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassOptions {
+    path: Option<PathBuf>,
+    prefix: Option<String>,
+}
+
+impl Keystore for PassStore {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn list(&self) -> Box<dyn Iterator<Item = Result<Key, Box<dyn Error>>> + '_> {
+        let items = match read_dir(&self.path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Box::new(std::iter::empty())
+            }
+            Err(e) => {
+                let e = ListError::ReadDirectoryFailed(self.path.clone(), e);
+                return Box::new(std::iter::once(Err(e.into())));
+            }
+        };
+
+        Box::new(items.filter_map(|i| {
+            let i = match i {
+                Ok(v) => v,
+                Err(e) => {
+                    let e = ListError::ReadDirectoryFailed(self.path.clone(), e);
+                    return Some(Err(e.into()));
+                }
+            };
+
+            self.load(&i.path()).map_err(|e| e.into()).transpose()
+        }))
+    }
+
+    fn generate(&self) -> Result<Key, Box<dyn Error>> {
+        // Generate a new key.
+        let mut key = Zeroizing::new([0u8; 16]);
+
+        if let Err(e) = getrandom(key.deref_mut()) {
+            return Err(Box::new(StoreError::GenerateKeyFailed(e)));
+        }
+
+        // Store the key.
+        let id = KeyId::from_key(&key);
+        let created = SystemTime::now();
+
+        self.store(&id, &key, &KeyData::new(created))?;
+
+        Ok(Key {
+            id,
+            created,
+            stores: vec![self.id.clone()],
+        })
+    }
+
+    fn import(&self, key: &KeyMaterial) -> Result<Key, Box<dyn Error>> {
+        let id = KeyId::from_key(&key.key);
+
+        self.store(&id, &key.key, &key.data)?;
+
+        Ok(Key {
+            id,
+            created: key.data.created,
+            stores: vec![self.id.clone()],
+        })
+    }
+
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        // Decrypt the entry.
+        let path = self.key(id);
+        let entry = match self.decrypt(&path)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        // Decode the key from the first line.
+        let line = entry.split(|&b| b == b'\n').next().unwrap();
+        let mut key = Zeroizing::new([0u8; 16]);
+
+        if hex::decode_to_slice(line.trim_ascii(), key.as_mut()).is_err() {
+            return Err(Box::new(GetError::InvalidKey(path)));
+        }
+
+        if KeyId::from_key(&key) != *id {
+            return Err(Box::new(GetError::KeyMismatched(path)));
+        }
+
+        // Read the data.
+        let path = self.data(id);
+        let data = match std::fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) => return Err(Box::new(GetError::ReadFileFailed(path, e))),
+        };
+
+        match decode_data(&data) {
+            Some(data) => Ok(Some(KeyMaterial { key, data })),
+            None => Err(Box::new(GetError::InvalidKeyData(path))),
+        }
+    }
+
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>> {
+        let path = self.key(id);
+
+        match remove_file(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(Box::new(DeleteError::DeleteFileFailed(path, e))),
+        }
+
+        // The key is already gone so the data is useless even if we failed to remove it.
+        remove_file(self.data(id)).ok();
+
+        Ok(true)
+    }
+
+    fn set_secret(&self, name: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        Ok(self.encrypt(&self.secret(name), value)?)
+    }
+
+    fn get_secret(&self, name: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        Ok(self.decrypt(&self.secret(name))?)
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        let path = self.secret(name);
+
+        match remove_file(&path) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(Box::new(DeleteError::DeleteFileFailed(path, e))),
+        }
+    }
+}
+
+/// Create a [`Command`] to run `gpg` with the same options as pass.
+fn gpg() -> Command {
+    let mut cmd = Command::new("gpg");
+
+    cmd.args([
+        "--quiet",
+        "--yes",
+        "--compress-algo=none",
+        "--no-encrypt-to",
+        "--batch",
+        "--use-agent",
+    ]);
+
+    cmd
+}
+
+fn decode_data(data: &str) -> Option<KeyData> {
+    hex::decode(data.trim())
+        .ok()
+        .and_then(|v| postcard::from_bytes(&v).ok())
+}
+
+/// Represents an error when [`PassStore::list()`] fails.
+#[derive(Debug, Error)]
+enum ListError {
+    #[error("couldn't read {0}")]
+    ReadDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} contains an invalid key data")]
+    InvalidKeyData(PathBuf),
+}
+
+/// Represents an error when [`PassStore`] fails to store a key or a secret.
+#[derive(Debug, Error)]
+enum StoreError {
+    #[error("couldn't generate a new key")]
+    GenerateKeyFailed(#[source] getrandom::Error),
+
+    #[error("{0} is not initialized (run 'pass init' first)")]
+    NotInitialized(PathBuf),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} does not contains any GPG ID")]
+    NoRecipients(PathBuf),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't start gpg")]
+    StartGpgFailed(#[source] std::io::Error),
+
+    #[error("couldn't write to gpg")]
+    WriteGpgFailed(#[source] std::io::Error),
+
+    #[error("gpg couldn't encrypt {0} ({1})")]
+    GpgFailed(PathBuf, ExitStatus),
+}
+
+/// Represents an error when [`PassStore`] fails to get a key or a secret.
+#[derive(Debug, Error)]
+enum GetError {
+    #[error("couldn't start gpg")]
+    StartGpgFailed(#[source] std::io::Error),
+
+    #[error("gpg couldn't decrypt {0} ({1})")]
+    GpgFailed(PathBuf, ExitStatus),
+
+    #[error("{0} contains an invalid key")]
+    InvalidKey(PathBuf),
+
+    #[error("{0} contains a key that does not match its name")]
+    KeyMismatched(PathBuf),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} contains an invalid key data")]
+    InvalidKeyData(PathBuf),
+}
+
+/// Represents an error when [`PassStore`] fails to delete a key or a secret.
+#[derive(Debug, Error)]
+enum DeleteError {
+    #[error("couldn't remove {0}")]
+    DeleteFileFailed(PathBuf, #[source] std::io::Error),
+}
@@ -0,0 +1,455 @@
+use super::{Keystore, KeystoreConfigError};
+use crate::key::{Key, KeyData, KeyId, KeyMaterial};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Implementation of [`Keystore`] that forward all requests to an external program.
+///
+/// The program is started when the first request is made and it will live until the store is
+/// dropped. Each request is a JSON object on a single line written to the standard input of the
+/// program and the program must write a response as a JSON object on a single line to its
+/// standard output. The standard error is inherited from Warp.
+///
+/// The first request is always `{"request":"hello","version":1}` and the program must respond
+/// with the version of the protocol it speaks (e.g. `{"version":1}`). The other requests are:
+///
+/// | Request | Response |
+/// |---------|----------|
+/// | `{"request":"list"}` | `{"keys":[{"id":"<ID>","data":"<DATA>"}]}` |
+/// | `{"request":"generate","data":"<DATA>"}` | `{"id":"<ID>"}` |
+/// | `{"request":"get","id":"<ID>"}` | `{"material":{"key":"<KEY>","data":"<DATA>"}}` or `{"material":null}` |
+/// | `{"request":"store","id":"<ID>","key":"<KEY>","data":"<DATA>"}` | `{}` |
+/// | `{"request":"delete","id":"<ID>"}` | `{"deleted":true}` or `{"deleted":false}` |
+///
+/// `<KEY>` is a hex-encoded 16-byte key and `<ID>` is a hex-encoded ID of the key, which is the
+/// first 16 bytes of SHAKE128 of a zero block encrypted by the key with AES-128. `<DATA>` is
+/// opaque to the program and must be returned as is. For `generate` the program must create a new
+/// random key then store it together with `<DATA>`.
+///
+/// Any request can be responded with `{"error":"<MESSAGE>"}` to indicate a failure.
+pub struct PluginStore {
+    id: Arc<str>,
+    command: PathBuf,
+    session: Mutex<Option<Session>>,
+}
+
+impl PluginStore {
+    /// Prefix of the program name to discover on `PATH`.
+    pub const PREFIX: &'static str = "warp-keystore-";
+
+    /// Version of the protocol.
+    pub const VERSION: u32 = 1;
+
+    /// The `command` option is filled from `PATH` by
+    /// [`Key::effective_stores()`](crate::config::Key::effective_stores) when the program is
+    /// installed as `warp-keystore-<id>`.
+    pub fn new(id: &str, options: PluginOptions) -> Result<Self, KeystoreConfigError> {
+        let command = match options.command {
+            Some(v) => v,
+            None => {
+                let name = format!("{}{}", Self::PREFIX, id);
+                return Err(KeystoreConfigError::PluginNotFound(name));
+            }
+        };
+
+        Ok(Self {
+            id: id.into(),
+            command,
+            session: Mutex::new(None),
+        })
+    }
+
+    fn request<T: DeserializeOwned>(&self, req: &Request) -> Result<T, PluginError> {
+        // Start the program if this is the first request.
+        let mut session = self.session.lock().unwrap();
+        let s = match session.as_mut() {
+            Some(v) => v,
+            None => session.insert(Session::start(&self.command)?),
+        };
+
+        // Send the request. Discard the program if it fails since it is likely in a bad state.
+        match s.send(req) {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                session.take().unwrap().close();
+                Err(e)
+            }
+        }
+    }
+
+    fn key(&self, id: KeyId, data: &str) -> Result<Key, PluginError> {
+        let data = decode_data(data)?;
+
+        Ok(Key {
+            id,
+            created: data.created,
+            stores: vec![self.id.clone()],
+        })
+    }
+}
+
+impl Drop for PluginStore {
+    fn drop(&mut self) {
+        if let Some(s) = self.session.get_mut().unwrap().take() {
+            s.close();
+        }
+    }
+}
+
+/// Options of [`PluginStore`] in `key.stores` of the configurations.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginOptions {
+    command: Option<PathBuf>,
+}
+
+impl Keystore for PluginStore {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn list(&self) -> Box<dyn Iterator<Item = Result<Key, Box<dyn Error>>> + '_> {
+        let keys = match self.request::<ListResponse>(&Request::List) {
+            Ok(v) => v.keys,
+            Err(e) => return Box::new(std::iter::once(Err(e.into()))),
+        };
+
+        Box::new(
+            keys.into_iter()
+                .map(|k| self.key(k.id, &k.data).map_err(|e| e.into())),
+        )
+    }
+
+    fn generate(&self) -> Result<Key, Box<dyn Error>> {
+        let data = encode_data(&KeyData::new(SystemTime::now()));
+        let res: GenerateResponse = self.request(&Request::Generate { data: &data })?;
+
+        Ok(self.key(res.id, &data)?)
+    }
+
+    fn import(&self, key: &KeyMaterial) -> Result<Key, Box<dyn Error>> {
+        let id = KeyId::from_key(&key.key);
+        let data = encode_data(&key.data);
+        let req = Request::Store {
+            id: &id,
+            key: &Zeroizing::new(hex::encode(key.key.as_ref())),
+            data: &data,
+        };
+
+        self.request::<StoreResponse>(&req)?;
+
+        Ok(self.key(id, &data)?)
+    }
+
+    fn get(&self, id: &KeyId) -> Result<Option<KeyMaterial>, Box<dyn Error>> {
+        // Get the key.
+        let res: GetResponse = self.request(&Request::Get { id })?;
+        let material = match res.material {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        // Decode the key.
+        let mut key = Zeroizing::new([0u8; 16]);
+
+        if hex::decode_to_slice(material.key.as_bytes(), key.as_mut()).is_err() {
+            return Err(Box::new(PluginError::InvalidKey));
+        }
+
+        if KeyId::from_key(&key) != *id {
+            return Err(Box::new(PluginError::KeyMismatched));
+        }
+
+        Ok(Some(KeyMaterial {
+            key,
+            data: decode_data(&material.data)?,
+        }))
+    }
+
+    fn delete(&self, id: &KeyId) -> Result<bool, Box<dyn Error>> {
+        let res: DeleteResponse = self.request(&Request::Delete { id })?;
+
+        Ok(res.deleted)
+    }
+
+    fn set_secret(&self, _: &str, _: &[u8]) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(PluginError::SecretNotSupported))
+    }
+
+    fn get_secret(&self, _: &str) -> Result<Option<Zeroizing<Vec<u8>>>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    fn delete_secret(&self, _: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+}
+
+/// Returns all `warp-keystore-<name>` programs on `PATH` keyed by `<name>`. The first one on
+/// `PATH` will be used if multiple programs have the same name.
+pub fn find_plugins() -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    let path = match std::env::var_os("PATH") {
+        Some(v) => v,
+        None => return plugins,
+    };
+
+    for dir in std::env::split_paths(&path) {
+        let items = match std::fs::read_dir(&dir) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        for item in items.flatten() {
+            let path = item.path();
+            let name = match path.file_name().and_then(|v| v.to_str()) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            #[cfg(windows)]
+            let name = match name.strip_suffix(".exe") {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let name = match name.strip_prefix(PluginStore::PREFIX) {
+                Some(v) if !v.is_empty() => v,
+                _ => continue,
+            };
+
+            if !plugins.contains_key(name) && is_executable(&path) {
+                plugins.insert(name.to_owned(), path);
+            }
+        }
+    }
+
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Encode [`KeyData`] as an opaque string for the program.
+fn encode_data(data: &KeyData) -> String {
+    hex::encode(postcard::to_stdvec(data).unwrap())
+}
+
+fn decode_data(data: &str) -> Result<KeyData, PluginError> {
+    hex::decode(data)
+        .ok()
+        .and_then(|v| postcard::from_bytes(&v).ok())
+        .ok_or(PluginError::InvalidKeyData)
+}
+
+/// A running program of [`PluginStore`].
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Session {
+    fn start(command: &Path) -> Result<Self, PluginError> {
+        // Start the program.
+        let mut child = Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| PluginError::StartFailed(command.to_owned(), e))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut session = Self {
+            child,
+            stdin,
+            stdout,
+        };
+
+        // Check the version.
+        let req = Request::Hello {
+            version: PluginStore::VERSION,
+        };
+
+        match session.send::<HelloResponse>(&req) {
+            Ok(v) if v.version == PluginStore::VERSION => Ok(session),
+            Ok(v) => {
+                session.close();
+                Err(PluginError::UnsupportedVersion(v.version))
+            }
+            Err(e) => {
+                session.close();
+                Err(e)
+            }
+        }
+    }
+
+    fn send<T: DeserializeOwned>(&mut self, req: &Request) -> Result<T, PluginError> {
+        // Write the request.
+        let mut line = Zeroizing::new(serde_json::to_string(req).unwrap());
+
+        line.push('\n');
+
+        self.stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(PluginError::WriteFailed)?;
+
+        // Read the response.
+        let mut line = Zeroizing::new(String::new());
+
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => return Err(PluginError::Exited),
+            Ok(_) => {}
+            Err(e) => return Err(PluginError::ReadFailed(e)),
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(Response::Ok(v)) => Ok(v),
+            Ok(Response::Error { error }) => Err(PluginError::Failed(error)),
+            Err(e) => Err(PluginError::InvalidResponse(e)),
+        }
+    }
+
+    fn close(self) {
+        let Self {
+            mut child,
+            stdin,
+            stdout,
+        } = self;
+
+        // Closing the standard input tell the program to exit.
+        drop(stdin);
+        drop(stdout);
+
+        child.wait().ok();
+    }
+}
+
+/// Request to the program of [`PluginStore`].
+#[derive(Serialize)]
+#[serde(tag = "request", rename_all = "lowercase")]
+enum Request<'a> {
+    Hello {
+        version: u32,
+    },
+    List,
+    Generate {
+        data: &'a str,
+    },
+    Get {
+        id: &'a KeyId,
+    },
+    Store {
+        id: &'a KeyId,
+        key: &'a str,
+        data: &'a str,
+    },
+    Delete {
+        id: &'a KeyId,
+    },
+}
+
+/// Response from the program of [`PluginStore`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Response<T> {
+    Error { error: String },
+    Ok(T),
+}
+
+#[derive(Deserialize)]
+struct HelloResponse {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct ListResponse {
+    keys: Vec<ListedKey>,
+}
+
+#[derive(Deserialize)]
+struct ListedKey {
+    id: KeyId,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    id: KeyId,
+}
+
+#[derive(Deserialize)]
+struct GetResponse {
+    material: Option<PluginMaterial>,
+}
+
+#[derive(Deserialize)]
+struct PluginMaterial {
+    key: Zeroizing<String>,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct StoreResponse {}
+
+#[derive(Deserialize)]
+struct DeleteResponse {
+    deleted: bool,
+}
+
+/// Represents an error when [`PluginStore`] fails.
+#[derive(Debug, Error)]
+enum PluginError {
+    #[error("couldn't start {0}")]
+    StartFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write a request to the plugin")]
+    WriteFailed(#[source] std::io::Error),
+
+    #[error("couldn't read a response from the plugin")]
+    ReadFailed(#[source] std::io::Error),
+
+    #[error("the plugin exited unexpectedly")]
+    Exited,
+
+    #[error("the plugin returned an invalid response")]
+    InvalidResponse(#[source] serde_json::Error),
+
+    #[error("the plugin speaks an unsupported protocol version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("the plugin reported an error: {0}")]
+    Failed(String),
+
+    #[error("the plugin returned an invalid key data")]
+    InvalidKeyData,
+
+    #[error("the plugin returned an invalid key")]
+    InvalidKey,
+
+    #[error("the plugin returned a key that does not match its ID")]
+    KeyMismatched,
+
+    #[error("the plugin does not support storing secrets")]
+    SecretNotSupported,
+}
@@ -1,21 +1,29 @@
 #![allow(clippy::enum_variant_names)]
 
+use crate::auth::connect;
 use crate::cmd::Command;
 use crate::config::AppConfig;
+use crate::crypto::Cipher;
 use crate::home::Home;
-use crate::key::KeyMgr;
-use crate::repo::{Repo, RepoLoadError};
+use crate::key::{KeyMgr, KeystoreLoadError};
+use crate::repo::Repo;
+use crate::server::{Client, ClientError};
+use crate::sync::{fetch_keyring, pull, push, unlock, PullError, PushError, UnlockError};
 use erdp::ErrorDisplay;
 use std::fs::File;
 use std::io::BufReader;
 use std::process::ExitCode;
 use std::sync::Arc;
 
+mod auth;
 mod cmd;
 mod config;
+mod crypto;
 mod home;
 mod key;
 mod repo;
+mod server;
+mod sync;
 
 fn main() -> ExitCode {
     // Get our home directory.
@@ -46,20 +54,47 @@ fn main() -> ExitCode {
 
     // Load file encryption keys.
     let config = Arc::new(config);
-    let keymgr = match KeyMgr::new(&home) {
-        Ok(v) => Arc::new(v),
-        Err(e) => {
-            eprintln!("Failed to load file encryption keys: {}.", e.display());
-            return ExitCode::FAILURE;
+    let (keymgr, failed) = KeyMgr::new(&home, &config.key);
+    let keymgr = Arc::new(keymgr);
+
+    for (id, e) in failed {
+        match e {
+            KeystoreLoadError::InvalidConfig(e) => eprintln!(
+                "Keystore '{}' is not available because its configuration in {} is invalid: {}.",
+                id,
+                path.display(),
+                e.display()
+            ),
+            KeystoreLoadError::ListKeyFailed(e) => eprintln!(
+                "Keystore '{}' is not available because its keys cannot be listed: {}.",
+                id,
+                e.display()
+            ),
         }
-    };
+    }
 
     // Setup commands.
     let mut args = clap::Command::new("warp");
     let commands: Vec<Box<dyn Command>> = vec![
-        Box::new(self::cmd::Init::new(config.clone(), keymgr.clone())),
-        Box::new(self::cmd::Key::new(config.clone(), keymgr.clone())),
-        Box::new(self::cmd::Keystore::new(keymgr.clone())),
+        Box::new(self::cmd::Init::new(
+            config.clone(),
+            home.clone(),
+            keymgr.clone(),
+        )),
+        Box::new(self::cmd::Key::new(
+            config.clone(),
+            home.clone(),
+            keymgr.clone(),
+        )),
+        Box::new(self::cmd::Keystore::new(
+            config.clone(),
+            home.clone(),
+            keymgr.clone(),
+        )),
+        Box::new(self::cmd::Login::new(config.clone(), keymgr.clone())),
+        Box::new(self::cmd::Logout::new(config.clone(), keymgr.clone())),
+        Box::new(self::cmd::Repo::new(keymgr.clone())),
+        Box::new(self::cmd::Whoami::new(config.clone(), keymgr.clone())),
     ];
 
     for cmd in &commands {
@@ -70,7 +105,7 @@ fn main() -> ExitCode {
     let args = args.get_matches();
     let (name, args) = match args.subcommand() {
         Some(v) => v,
-        None => return warp(),
+        None => return warp(&home, &keymgr),
     };
 
     for cmd in commands {
@@ -82,25 +117,104 @@ fn main() -> ExitCode {
     unreachable!()
 }
 
-fn warp() -> ExitCode {
-    // Get path to repository.
-    let path = match std::env::current_dir() {
+fn warp(home: &Home, keymgr: &KeyMgr) -> ExitCode {
+    // Load repository.
+    let mut repo = match self::cmd::find_repo() {
+        Some(v) => v,
+        None => return ExitCode::FAILURE,
+    };
+
+    // The repository may be created before the registry existed.
+    if let Err(e) = repo.register(home) {
+        eprintln!(
+            "Failed to add {} to the list of repositories: {}.",
+            repo.path().display(),
+            e.display()
+        );
+    }
+
+    // Connect to the server.
+    let client = match connect(keymgr, repo.server()) {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("Failed to get current directory: {}.", e.display());
+            eprintln!("Failed to connect to {}: {}.", repo.server(), e.display());
             return ExitCode::FAILURE;
         }
     };
 
-    // Load repository.
-    match Repo::load(&path) {
-        Ok(_) => {}
-        Err(RepoLoadError::NotWarpRepo) => {
-            eprintln!("{} is not a Warp repository, invoke Warp with '{} --help' to see how to setup a new repository.", path.display(), self::cmd::Init::NAME);
+    // Get the data key.
+    let keyring = match fetch_keyring(&client, repo.name()) {
+        Ok(v) => v.map(|v| v.0),
+        Err(e) => {
+            eprintln!("Failed to get the keyring: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let key = match unlock(&repo, keymgr, keyring.as_ref()) {
+        Ok(v) => v,
+        Err(UnlockError::NoAuthorizedKey) => {
+            eprintln!("None of the keys that authorized to access this repository is available on this computer.");
             return ExitCode::FAILURE;
         }
         Err(e) => {
-            eprintln!("Failed to load {}: {}.", path.display(), e.display());
+            eprintln!("Failed to unlock the repository: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Follow the data key if the repository has been re-encrypted by a key rotation.
+    let id = key.id();
+
+    if id != *repo.key() {
+        if let Err(e) = repo.set_key(id) {
+            eprintln!("Failed to update the repository key: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+
+        eprintln!("The repository has been re-encrypted with a new key since the last session.");
+    }
+
+    // Pull the changes from the other computers.
+    let cipher = key.cipher();
+
+    match pull(&repo, home, &client, cipher) {
+        Ok(v) => {
+            if v.changes != 0 {
+                eprintln!(
+                    "Pulled {} change(s) with {} file(s) ({} bytes).",
+                    v.changes, v.files, v.bytes
+                );
+            }
+        }
+        Err(PullError::Conflicted(paths)) => {
+            eprintln!("The following local changes would be overwritten by the remote changes:");
+
+            for p in paths {
+                eprintln!("  {p}");
+            }
+
+            eprintln!("Nothing has been changed. Move or revert these changes then try again.");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("Failed to pull the changes: {}.", e.display());
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Push the changes that was queued by the previous session.
+    match repo.pending() {
+        Ok(Some(_)) => {
+            eprintln!("Pushing the changes from the previous session.");
+
+            if !push_changes(&repo, home, &client, cipher) {
+                eprintln!("The changes will be pushed again when this session ends.");
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("Failed to load the queued changes: {}.", e.display());
             return ExitCode::FAILURE;
         }
     }
@@ -118,15 +232,51 @@ fn warp() -> ExitCode {
     let mut cmd = std::process::Command::new(&shell);
 
     // Launch the shell.
-    if let Err(e) = cmd.status() {
-        eprintln!(
-            "Failed to launch {}: {}.",
-            shell.to_string_lossy(),
-            e.display()
-        );
+    let status = match cmd.status() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!(
+                "Failed to launch {}: {}.",
+                shell.to_string_lossy(),
+                e.display()
+            );
 
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Push the changes.
+    if !push_changes(&repo, home, &client, cipher) {
+        eprintln!("The changes have been queued and will be pushed when the next session starts.");
         return ExitCode::FAILURE;
     }
 
-    ExitCode::SUCCESS
+    // The code is not available if the shell was terminated by a signal.
+    match status.code().and_then(|v| u8::try_from(v).ok()) {
+        Some(v) => ExitCode::from(v),
+        None => ExitCode::FAILURE,
+    }
+}
+
+fn push_changes(repo: &Repo, home: &Home, client: &Client, cipher: &Cipher) -> bool {
+    let summary = match push(repo, home, client, cipher) {
+        Ok(v) => v,
+        Err(PushError::UploadManifestFailed(ClientError::Conflict)) => {
+            eprintln!("Failed to push the changes: the repository has been changed by another computer since the last pull.");
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Failed to push the changes: {}.", e.display());
+            return false;
+        }
+    };
+
+    if summary.changes != 0 {
+        eprintln!(
+            "Pushed {} change(s) with {} file(s) ({} bytes).",
+            summary.changes, summary.files, summary.bytes
+        );
+    }
+
+    true
 }
@@ -0,0 +1,257 @@
+use super::{Entry, EntryType, Manifest};
+use std::collections::{HashMap, HashSet};
+
+/// Compute the changes from `from` to `to`.
+///
+/// All deletions come first with the children before its parent, then the remaining changes
+/// ordered by its path (after the change) so the parent always come before its children. An entry
+/// that change its type (e.g. a file to a directory) is represented as a deletion followed by an
+/// addition so applying the changes in order will never see a file where a directory is expected
+/// or vice versa. The only exception is the source of a rename, which is not part of the deletions
+/// and may be inside a deleted directory. The caller must take care of the rename sources (e.g.
+/// copy or move them out) before applying the deletions since the renames come after them.
+pub fn diff(from: &Manifest, to: &Manifest) -> Vec<Change> {
+    let mut deleted = Vec::new();
+    let mut added = Vec::new();
+    let mut others = Vec::new();
+
+    // Find deleted entries.
+    for (path, old) in from.entries() {
+        match to.get(path) {
+            Some(new) if new.ty == old.ty => {}
+            _ => deleted.push((path, old)),
+        }
+    }
+
+    // Find added and changed entries.
+    for (path, new) in to.entries() {
+        let old = match from.get(path) {
+            Some(v) if v.ty == new.ty => v,
+            _ => {
+                added.push((path, new));
+                continue;
+            }
+        };
+
+        if old.hash != new.hash {
+            others.push(Change::Modified {
+                path: path.to_owned(),
+                entry: new.clone(),
+            });
+        } else if old.mode != new.mode {
+            others.push(Change::ModeChanged {
+                path: path.to_owned(),
+                mode: new.mode,
+            });
+        }
+    }
+
+    // Pair the deleted and added entries with the same content as renames.
+    let mut sources = HashMap::<(EntryType, [u8; 32]), Vec<&str>>::new();
+
+    for (path, old) in &deleted {
+        if let Some(h) = old.hash {
+            sources.entry((old.ty, h)).or_default().push(path);
+        }
+    }
+
+    for v in sources.values_mut() {
+        v.reverse();
+    }
+
+    let mut renamed = Vec::new();
+    let mut moved = HashSet::new();
+
+    added.retain(|&(path, new)| {
+        let src = new
+            .hash
+            .and_then(|h| sources.get_mut(&(new.ty, h)))
+            .and_then(|v| v.pop());
+
+        match src {
+            Some(from) => {
+                moved.insert(from);
+                renamed.push(Change::Renamed {
+                    from: from.to_owned(),
+                    to: path.to_owned(),
+                    entry: new.clone(),
+                });
+
+                false
+            }
+            None => true,
+        }
+    });
+
+    deleted.retain(|(path, _)| !moved.contains(path));
+
+    // Build the result.
+    let mut changes =
+        Vec::with_capacity(deleted.len() + renamed.len() + added.len() + others.len());
+
+    for (path, old) in deleted.into_iter().rev() {
+        changes.push(Change::Deleted {
+            path: path.to_owned(),
+            entry: old.clone(),
+        });
+    }
+
+    let mut rest: Vec<Change> = added
+        .into_iter()
+        .map(|(path, new)| Change::Added {
+            path: path.to_owned(),
+            entry: new.clone(),
+        })
+        .chain(renamed)
+        .chain(others)
+        .collect();
+
+    rest.sort_by(|a, b| a.path().cmp(b.path()));
+    changes.extend(rest);
+
+    changes
+}
+
+/// A single change between two [`Manifest`].
+#[derive(Clone)]
+pub enum Change {
+    Added {
+        path: String,
+        entry: Entry,
+    },
+    Modified {
+        path: String,
+        entry: Entry,
+    },
+    Deleted {
+        path: String,
+        entry: Entry,
+    },
+    Renamed {
+        from: String,
+        to: String,
+        entry: Entry,
+    },
+    ModeChanged {
+        path: String,
+        mode: u32,
+    },
+}
+
+impl Change {
+    /// Returns the path of the entry after the change.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } => path,
+            Self::Modified { path, .. } => path,
+            Self::Deleted { path, .. } => path,
+            Self::Renamed { to, .. } => to,
+            Self::ModeChanged { path, .. } => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn file(hash: u8, mode: u32) -> Entry {
+        Entry {
+            ty: EntryType::File,
+            size: 1,
+            mode,
+            mtime: SystemTime::UNIX_EPOCH,
+            hash: Some([hash; 32]),
+        }
+    }
+
+    fn dir() -> Entry {
+        Entry {
+            ty: EntryType::Directory,
+            size: 0,
+            mode: 0o755,
+            mtime: SystemTime::UNIX_EPOCH,
+            hash: None,
+        }
+    }
+
+    fn manifest<const N: usize>(entries: [(&str, Entry); N]) -> Manifest {
+        entries
+            .into_iter()
+            .map(|(p, e)| (p.to_owned(), e))
+            .collect()
+    }
+
+    /// Format `changes` so it can be compared.
+    fn summary(changes: &[Change]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| match c {
+                Change::Added { path, .. } => format!("A {path}"),
+                Change::Modified { path, .. } => format!("M {path}"),
+                Change::Deleted { path, .. } => format!("D {path}"),
+                Change::Renamed { from, to, entry } => format!("R {from} {to} {:o}", entry.mode),
+                Change::ModeChanged { path, mode } => format!("C {path} {mode:o}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_to_dir() {
+        let from = manifest([("a", file(1, 0o644))]);
+        let to = manifest([("a", dir()), ("a/b", file(2, 0o644))]);
+
+        assert_eq!(summary(&diff(&from, &to)), ["D a", "A a", "A a/b"]);
+    }
+
+    #[test]
+    fn dir_to_file() {
+        let from = manifest([
+            ("a", dir()),
+            ("a/b", file(1, 0o644)),
+            ("a/c", file(2, 0o644)),
+        ]);
+        let to = manifest([("a", file(3, 0o644))]);
+
+        assert_eq!(summary(&diff(&from, &to)), ["D a/c", "D a/b", "D a", "A a"]);
+    }
+
+    #[test]
+    fn rename_with_mode() {
+        let from = manifest([("x", file(1, 0o644))]);
+        let to = manifest([("y", file(1, 0o755))]);
+
+        assert_eq!(summary(&diff(&from, &to)), ["R x y 755"]);
+    }
+
+    #[test]
+    fn rename_out_of_deleted_dir() {
+        let from = manifest([("a", dir()), ("a/b", file(1, 0o644))]);
+        let to = manifest([("c", file(1, 0o644))]);
+
+        assert_eq!(summary(&diff(&from, &to)), ["D a", "R a/b c 644"]);
+    }
+
+    #[test]
+    fn deletion_order() {
+        let from = manifest([
+            ("a", dir()),
+            ("a/b", dir()),
+            ("a/b/c", file(1, 0o644)),
+            ("a/d", file(2, 0o644)),
+            ("e", file(3, 0o644)),
+            ("f", file(4, 0o644)),
+        ]);
+        let to = manifest([
+            ("0", file(5, 0o644)),
+            ("f", file(4, 0o600)),
+            ("g", file(6, 0o644)),
+        ]);
+
+        assert_eq!(
+            summary(&diff(&from, &to)),
+            ["D e", "D a/d", "D a/b/c", "D a/b", "D a", "A 0", "C f 600", "A g"]
+        );
+    }
+}
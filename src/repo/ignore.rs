@@ -0,0 +1,91 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Rules to exclude entries from [`Manifest`](super::Manifest).
+///
+/// The rules are loaded from `.warpignore` (and `.gitignore` if enabled) in each directory plus a
+/// global file, all with the same semantic as `.gitignore`. The rules in a deeper directory take
+/// precedence over its parents and `.warpignore` take precedence over `.gitignore` in the same
+/// directory. The global file has the lowest precedence.
+pub struct Ignore {
+    global: Option<Gitignore>,
+    gitignore: bool,
+    stack: Vec<Gitignore>,
+}
+
+impl Ignore {
+    pub const FILE_NAME: &'static str = ".warpignore";
+
+    /// `global` is a path to the global ignore file, which is not required to exists. The patterns
+    /// in this file are relative to `root`.
+    pub fn new(root: &Path, global: &Path, gitignore: bool) -> Result<Self, IgnoreError> {
+        Ok(Self {
+            global: Self::load(root, global)?,
+            gitignore,
+            stack: Vec::new(),
+        })
+    }
+
+    /// Load the rules from `dir`. Each call must be paired with [`Ignore::leave()`] when all of
+    /// entries in `dir` has been processed.
+    pub fn enter(&mut self, dir: &Path) -> Result<usize, IgnoreError> {
+        let mut n = 0;
+
+        if self.gitignore {
+            if let Some(v) = Self::load(dir, &dir.join(".gitignore"))? {
+                self.stack.push(v);
+                n += 1;
+            }
+        }
+
+        if let Some(v) = Self::load(dir, &dir.join(Self::FILE_NAME))? {
+            self.stack.push(v);
+            n += 1;
+        }
+
+        Ok(n)
+    }
+
+    /// `n` is a value returned from [`Ignore::enter()`].
+    pub fn leave(&mut self, n: usize) {
+        self.stack.truncate(self.stack.len() - n);
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for rules in self.stack.iter().rev().chain(&self.global) {
+            match rules.matched(path, is_dir) {
+                Match::None => {}
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+            }
+        }
+
+        false
+    }
+
+    fn load(root: &Path, file: &Path) -> Result<Option<Gitignore>, IgnoreError> {
+        if !file.is_file() {
+            return Ok(None);
+        }
+
+        let mut builder = GitignoreBuilder::new(root);
+
+        if let Some(e) = builder.add(file) {
+            return Err(IgnoreError::LoadFailed(file.to_owned(), e));
+        }
+
+        match builder.build() {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(IgnoreError::LoadFailed(file.to_owned(), e)),
+        }
+    }
+}
+
+/// Represents an error when [`Ignore`] fails to load the rules.
+#[derive(Debug, Error)]
+pub enum IgnoreError {
+    #[error("couldn't load {0}")]
+    LoadFailed(PathBuf, #[source] ignore::Error),
+}
@@ -0,0 +1,278 @@
+use crate::crypto::{Cipher, CipherError, DecryptError, EncryptError};
+use crate::key::{KeyData, KeyId, KeyMaterial};
+use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Data key of a repository wrapped by each key that authorized to access the repository.
+///
+/// Files in the repository are encrypted with the data key instead of the key from [`KeyMgr`] so
+/// the access can be granted or revoked without re-encrypting the files. The keyring is stored on
+/// the server as-is since nothing in it is usable without one of the authorized keys. Everything
+/// else in the keyring, including the name of the repository, is authenticated with the data key
+/// so the server cannot alter it or give us the keyring of the other repository.
+///
+/// [`KeyMgr`]: crate::key::KeyMgr
+pub struct Keyring {
+    body: Body,
+    tag: Option<[u8; 32]>,
+}
+
+impl Keyring {
+    /// Create a keyring of `repo` for `key` without any authorized keys.
+    pub fn new(repo: impl Into<String>, key: &DataKey) -> Self {
+        Self {
+            body: Body {
+                repo: repo.into(),
+                data: key.data.clone(),
+                keys: Vec::new(),
+                retired: Vec::new(),
+                replaced: Vec::new(),
+            },
+            tag: None,
+        }
+    }
+
+    /// Create a keyring for the repository that was created before the keyring existed. Such
+    /// repository use its key as a data key so `key` will be the only authorized key.
+    pub fn legacy(repo: impl Into<String>, key: &DataKey) -> Result<Self, EncryptError> {
+        let mut keyring = Self::new(repo, key);
+        let material = KeyMaterial {
+            key: key.key.clone(),
+            data: key.data.clone(),
+        };
+
+        keyring.add(key, &material)?;
+
+        Ok(keyring)
+    }
+
+    /// The content will be authenticated by [`Keyring::unwrap()`].
+    pub fn from_bytes(data: &[u8]) -> Result<Self, postcard::Error> {
+        let sealed: Sealed<Body> = postcard::from_bytes(data)?;
+
+        Ok(Self {
+            body: sealed.body,
+            tag: Some(sealed.tag),
+        })
+    }
+
+    /// `key` must be the data key of this keyring.
+    pub fn to_bytes(&self, key: &DataKey) -> Vec<u8> {
+        let sealed = Sealed {
+            body: &self.body,
+            tag: key.cipher.keyring_tag(&self.body.to_bytes()),
+        };
+
+        postcard::to_stdvec(&sealed).unwrap()
+    }
+
+    /// Name of the repository on the server that this keyring belong to.
+    pub fn repo(&self) -> &str {
+        &self.body.repo
+    }
+
+    /// Returns the ID of all authorized keys.
+    pub fn keys(&self) -> impl Iterator<Item = &KeyId> {
+        self.body.keys.iter().map(|k| &k.id)
+    }
+
+    pub fn contains(&self, id: &KeyId) -> bool {
+        self.body.keys.iter().any(|k| k.id == *id)
+    }
+
+    /// Returns the ID of all keys that was retired by a key rotation.
+    pub fn retired(&self) -> impl Iterator<Item = &KeyId> {
+        self.body.retired.iter()
+    }
+
+    pub fn is_retired(&self, id: &KeyId) -> bool {
+        self.body.retired.contains(id)
+    }
+
+    /// Mark `id` as retired. The caller is responsible to remove the key if it is authorized.
+    pub fn retire(&mut self, id: KeyId) {
+        if !self.body.retired.contains(&id) {
+            self.body.retired.push(id);
+            self.tag = None;
+        }
+    }
+
+    /// Returns `true` if `id` is a data key that was replaced by the data key of this keyring.
+    pub fn replaces(&self, id: &KeyId) -> bool {
+        self.body.replaced.contains(id)
+    }
+
+    /// Create a keyring for `key` to replace the data key of this keyring. All retired and
+    /// replaced keys are carried over without any authorized keys.
+    pub fn rotate(&self, key: &DataKey, old: KeyId) -> Self {
+        let mut replaced = self.body.replaced.clone();
+
+        if !replaced.contains(&old) {
+            replaced.push(old);
+        }
+
+        Self {
+            body: Body {
+                repo: self.body.repo.clone(),
+                data: key.data.clone(),
+                keys: Vec::new(),
+                retired: self.body.retired.clone(),
+                replaced,
+            },
+            tag: None,
+        }
+    }
+
+    /// Authorize `key` to access `data`. Returns `false` if `key` is already authorized.
+    pub fn add(&mut self, data: &DataKey, key: &KeyMaterial) -> Result<bool, EncryptError> {
+        let id = KeyId::from_key(&key.key);
+
+        if self.contains(&id) {
+            return Ok(false);
+        }
+
+        let key = Cipher::new(&key.key, &key.data)?.encrypt(data.key.as_ref())?;
+
+        self.body.keys.push(WrappedKey { id, key });
+        self.tag = None;
+
+        Ok(true)
+    }
+
+    /// Revoke the access of the key. Returns `false` if the key is not authorized.
+    pub fn remove(&mut self, id: &KeyId) -> bool {
+        let len = self.body.keys.len();
+
+        self.body.keys.retain(|k| k.id != *id);
+
+        if self.body.keys.len() == len {
+            return false;
+        }
+
+        self.tag = None;
+
+        true
+    }
+
+    /// Get the data key with `key`. Returns [`None`] if `key` is not authorized.
+    ///
+    /// The content of the keyring is verified with the data key if it was loaded with
+    /// [`Keyring::from_bytes()`] and has not been modified since then.
+    pub fn unwrap(&self, key: &KeyMaterial) -> Result<Option<DataKey>, UnwrapError> {
+        // Decrypt the data key.
+        let id = KeyId::from_key(&key.key);
+        let wrapped = match self.body.keys.iter().find(|k| k.id == id) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let data = Cipher::new(&key.key, &key.data)
+            .map_err(DecryptError::from)?
+            .decrypt(&wrapped.key)?;
+        let data = Zeroizing::new(data);
+        let key = match <[u8; 16]>::try_from(data.as_slice()) {
+            Ok(v) => DataKey::new(Zeroizing::new(v), self.body.data.clone())
+                .map_err(UnwrapError::UnsupportedKey)?,
+            Err(_) => return Err(UnwrapError::InvalidKey),
+        };
+
+        // Verify the content.
+        if let Some(tag) = &self.tag {
+            if !key.cipher.verify_keyring_tag(&self.body.to_bytes(), tag) {
+                return Err(UnwrapError::AuthenticationFailed);
+            }
+        }
+
+        Ok(Some(key))
+    }
+}
+
+/// Content of [`Keyring`] that is authenticated with the data key.
+#[derive(Serialize, Deserialize)]
+struct Body {
+    repo: String,
+    data: KeyData,
+    keys: Vec<WrappedKey>,
+    retired: Vec<KeyId>,
+    replaced: Vec<KeyId>,
+}
+
+impl Body {
+    fn to_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).unwrap()
+    }
+}
+
+/// Serialized form of [`Keyring`].
+#[derive(Serialize, Deserialize)]
+struct Sealed<B> {
+    body: B,
+    tag: [u8; 32],
+}
+
+/// Data key that wrapped with the authorized key.
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    id: KeyId,
+    key: Vec<u8>,
+}
+
+/// Key to encrypt files in a repository.
+pub struct DataKey {
+    key: Zeroizing<[u8; 16]>,
+    data: KeyData,
+    cipher: Cipher,
+}
+
+impl DataKey {
+    pub fn generate() -> Result<Self, getrandom::Error> {
+        let mut key = Zeroizing::new([0u8; 16]);
+
+        getrandom(key.as_mut())?;
+
+        // A new key always has a MAC.
+        Ok(Self::new(key, KeyData::new(SystemTime::now())).unwrap())
+    }
+
+    fn new(key: Zeroizing<[u8; 16]>, data: KeyData) -> Result<Self, CipherError> {
+        let cipher = Cipher::new(&key, &data)?;
+
+        Ok(Self { key, data, cipher })
+    }
+
+    pub fn id(&self) -> KeyId {
+        KeyId::from_key(&self.key)
+    }
+
+    pub fn cipher(&self) -> &Cipher {
+        &self.cipher
+    }
+}
+
+impl TryFrom<KeyMaterial> for DataKey {
+    type Error = CipherError;
+
+    /// The repository that was created before [`Keyring`] existed use its key as a data key.
+    fn try_from(value: KeyMaterial) -> Result<Self, Self::Error> {
+        Self::new(value.key, value.data)
+    }
+}
+
+/// Represents an error when [`Keyring::unwrap()`] fails.
+#[derive(Debug, Error)]
+pub enum UnwrapError {
+    #[error("couldn't decrypt the data key")]
+    DecryptFailed(#[from] DecryptError),
+
+    #[error("the decrypted data key is not valid")]
+    InvalidKey,
+
+    #[error("the data key is not supported")]
+    UnsupportedKey(#[source] CipherError),
+
+    #[error("the keyring has been modified by someone without the data key")]
+    AuthenticationFailed,
+}
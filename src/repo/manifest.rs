@@ -0,0 +1,344 @@
+use super::{Ignore, IgnoreError};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+use std::fs::{File, Metadata};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Snapshot of a working tree.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    created: SystemTime,
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Manifest {
+    /// Scan the working tree at `root`, skipping any entry that excluded by `ignore`.
+    ///
+    /// The content hash from `cache` will be reused for any file that has the same size and mtime as
+    /// in `cache` unless its mtime is not older than the time `cache` was created, which mean it can
+    /// be modified after `cache` was created without changing the mtime.
+    pub fn scan(root: &Path, cache: Option<&Self>, ignore: &mut Ignore) -> Result<Self, ScanError> {
+        let mut manifest = Self {
+            created: SystemTime::now(),
+            entries: BTreeMap::new(),
+        };
+
+        manifest.scan_dir(root, root, cache, ignore)?;
+
+        Ok(manifest)
+    }
+
+    /// Create a manifest without any entries, which represents a repository that has never been
+    /// pushed.
+    pub fn empty() -> Self {
+        Self {
+            created: SystemTime::UNIX_EPOCH,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Load a manifest that was written by [`Manifest::save()`]. Returns [`None`] if `path` does
+    /// not exists.
+    pub fn load(path: &Path) -> Result<Option<Self>, ManifestError> {
+        let mut data = Vec::new();
+
+        match File::open(path) {
+            Ok(mut v) => {
+                if let Err(e) = v.read_to_end(&mut data) {
+                    return Err(ManifestError::ReadFileFailed(path.to_owned(), e));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ManifestError::OpenFileFailed(path.to_owned(), e)),
+        }
+
+        match postcard::from_bytes(&data) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(ManifestError::InvalidManifest(path.to_owned(), e)),
+        }
+    }
+
+    /// Write this manifest to `path` atomically.
+    pub fn save(&self, path: &Path) -> Result<(), ManifestError> {
+        // Write to a temporary file.
+        let data = postcard::to_stdvec(self).unwrap();
+        let tmp = path.with_extension("tmp");
+        let mut file = match File::create(&tmp) {
+            Ok(v) => v,
+            Err(e) => return Err(ManifestError::CreateFileFailed(tmp, e)),
+        };
+
+        if let Err(e) = file.write_all(&data).and_then(|_| file.sync_all()) {
+            drop(file);
+            std::fs::remove_file(&tmp).unwrap();
+            return Err(ManifestError::WriteFileFailed(tmp, e));
+        }
+
+        // Replace the old manifest.
+        drop(file);
+
+        if let Err(e) = std::fs::rename(&tmp, path) {
+            std::fs::remove_file(&tmp).unwrap();
+            return Err(ManifestError::WriteFileFailed(path.to_owned(), e));
+        }
+
+        Ok(())
+    }
+
+    /// Returns an entry for `path`, which is relative to the repository root with `/` as a
+    /// separator.
+    pub fn get(&self, path: &str) -> Option<&Entry> {
+        self.entries.get(path)
+    }
+
+    /// Iterate all entries ordered by its path.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    fn scan_dir(
+        &mut self,
+        root: &Path,
+        dir: &Path,
+        cache: Option<&Self>,
+        ignore: &mut Ignore,
+    ) -> Result<(), ScanError> {
+        let rules = ignore.enter(dir).map_err(ScanError::LoadIgnoreFailed)?;
+        let items = match std::fs::read_dir(dir) {
+            Ok(v) => v,
+            Err(e) => return Err(ScanError::ReadDirectoryFailed(dir.to_owned(), e)),
+        };
+
+        for item in items {
+            let item = match item {
+                Ok(v) => v,
+                Err(e) => return Err(ScanError::ReadDirectoryFailed(dir.to_owned(), e)),
+            };
+
+            // Get relative path.
+            let path = item.path();
+            let name = Self::relative_path(root, &path)?;
+
+            if name == ".warp" {
+                continue;
+            }
+
+            // Get metadata.
+            let meta = match std::fs::symlink_metadata(&path) {
+                Ok(v) => v,
+                Err(e) => return Err(ScanError::GetMetadataFailed(path, e)),
+            };
+
+            let mtime = match meta.modified() {
+                Ok(v) => v,
+                Err(e) => return Err(ScanError::GetMetadataFailed(path, e)),
+            };
+
+            // Check if the entry is excluded.
+            let ty = meta.file_type();
+
+            if ignore.is_ignored(&path, ty.is_dir()) {
+                continue;
+            }
+
+            // Build the entry.
+            let mode = Self::mode(&meta);
+            let entry = if ty.is_dir() {
+                Entry {
+                    ty: EntryType::Directory,
+                    size: 0,
+                    mode,
+                    mtime,
+                    hash: None,
+                }
+            } else if ty.is_symlink() {
+                let target = match std::fs::read_link(&path) {
+                    Ok(v) => v,
+                    Err(e) => return Err(ScanError::ReadLinkFailed(path, e)),
+                };
+
+                let target = match target.to_str() {
+                    Some(v) => v.replace(std::path::MAIN_SEPARATOR, "/"),
+                    None => return Err(ScanError::NonUnicodePath(target)),
+                };
+
+                Entry {
+                    ty: EntryType::Symlink,
+                    size: target.len().try_into().unwrap(),
+                    mode,
+                    mtime,
+                    hash: Some(Sha3_256::digest(target.as_bytes()).into()),
+                }
+            } else if ty.is_file() {
+                let size = meta.len();
+                let hash = match cache.and_then(|c| c.cached_hash(&name, size, mtime)) {
+                    Some(v) => v,
+                    None => Self::hash(&path)?,
+                };
+
+                Entry {
+                    ty: EntryType::File,
+                    size,
+                    mode,
+                    mtime,
+                    hash: Some(hash),
+                }
+            } else {
+                // Skip special files like sockets and FIFOs.
+                continue;
+            };
+
+            self.entries.insert(name, entry);
+
+            if ty.is_dir() {
+                self.scan_dir(root, &path, cache, ignore)?;
+            }
+        }
+
+        ignore.leave(rules);
+
+        Ok(())
+    }
+
+    fn cached_hash(&self, path: &str, size: u64, mtime: SystemTime) -> Option<[u8; 32]> {
+        let e = self.entries.get(path)?;
+
+        if e.ty != EntryType::File || e.size != size || e.mtime != mtime || mtime >= self.created {
+            return None;
+        }
+
+        e.hash
+    }
+
+    fn hash(path: &Path) -> Result<[u8; 32], ScanError> {
+        let mut file = match File::open(path) {
+            Ok(v) => v,
+            Err(e) => return Err(ScanError::ReadFileFailed(path.to_owned(), e)),
+        };
+
+        let mut hasher = Sha3_256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+
+        loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ScanError::ReadFileFailed(path.to_owned(), e)),
+            };
+
+            hasher.update(&buf[..n]);
+        }
+
+        Ok(hasher.finalize().into())
+    }
+
+    fn relative_path(root: &Path, path: &Path) -> Result<String, ScanError> {
+        let mut name = String::new();
+
+        for c in path.strip_prefix(root).unwrap().components() {
+            let c = match c.as_os_str().to_str() {
+                Some(v) => v,
+                None => return Err(ScanError::NonUnicodePath(path.to_owned())),
+            };
+
+            if !name.is_empty() {
+                name.push('/');
+            }
+
+            name.push_str(c);
+        }
+
+        Ok(name)
+    }
+
+    #[cfg(unix)]
+    fn mode(meta: &Metadata) -> u32 {
+        std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o7777
+    }
+
+    #[cfg(windows)]
+    fn mode(meta: &Metadata) -> u32 {
+        let base = if meta.is_dir() { 0o755 } else { 0o644 };
+
+        if meta.permissions().readonly() {
+            base & !0o222
+        } else {
+            base
+        }
+    }
+}
+
+#[cfg(test)]
+impl FromIterator<(String, Entry)> for Manifest {
+    fn from_iter<T: IntoIterator<Item = (String, Entry)>>(iter: T) -> Self {
+        Self {
+            created: SystemTime::now(),
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// A single entry in the [`Manifest`].
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub ty: EntryType,
+    pub size: u64,
+    pub mode: u32,
+    pub mtime: SystemTime,
+    /// SHA3-256 of the content for a file or the target for a symlink.
+    pub hash: Option<[u8; 32]>,
+}
+
+/// Type of [`Entry`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntryType {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// Represents an error when [`Manifest::scan()`] fails.
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("couldn't load ignore rules")]
+    LoadIgnoreFailed(#[source] IgnoreError),
+
+    #[error("couldn't read {0}")]
+    ReadDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't get metadata of {0}")]
+    GetMetadataFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadLinkFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid UTF-8")]
+    NonUnicodePath(PathBuf),
+}
+
+/// Represents an error when [`Manifest`] fails to load or save.
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("couldn't open {0}")]
+    OpenFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid manifest")]
+    InvalidManifest(PathBuf, #[source] postcard::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+}
@@ -1,14 +1,135 @@
+pub use self::diff::*;
+pub use self::ignore::*;
+pub use self::keyring::*;
+pub use self::manifest::*;
+pub use self::registry::*;
+use crate::home::Home;
+use crate::key::KeyId;
+use crate::server::Revision;
+use gethostname::gethostname;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir, remove_dir_all, remove_file, File};
+use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
+use url::Url;
+
+mod diff;
+mod ignore;
+mod keyring;
+mod manifest;
+mod registry;
 
 /// Represents a single repository that loaded from `.warp` directory.
-pub struct Repo {}
+pub struct Repo {
+    path: PathBuf,
+    name: String,
+    server: Url,
+    key: KeyId,
+    machine: String,
+    gitignore: bool,
+}
 
 impl Repo {
+    /// Current version of the repository descriptor.
+    pub const VERSION: u32 = 1;
+
+    /// Create `.warp` directory inside `path`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        name: impl Into<String>,
+        server: Url,
+        key: KeyId,
+        gitignore: bool,
+    ) -> Result<Self, RepoCreateError> {
+        // Create .warp directory.
+        let root = path.as_ref();
+        let dir = root.join(".warp");
+
+        if let Err(e) = create_dir(&dir) {
+            return Err(if e.kind() == std::io::ErrorKind::AlreadyExists {
+                RepoCreateError::AlreadyExists
+            } else {
+                RepoCreateError::CreateDirectoryFailed(dir, e)
+            });
+        }
+
+        // Write the descriptor.
+        let desc = Descriptor {
+            version: Self::VERSION,
+            name: name.into(),
+            server,
+            key,
+            machine: gethostname().to_string_lossy().into_owned(),
+            gitignore,
+        };
+
+        if let Err(e) = Self::write_descriptor(&dir.join("repo.yml"), &desc) {
+            // The error from the clean up is less important than the original one.
+            remove_dir_all(&dir).ok();
+            return Err(e);
+        }
+
+        Ok(Self {
+            path: root.to_owned(),
+            name: desc.name,
+            server: desc.server,
+            key: desc.key,
+            machine: desc.machine,
+            gitignore: desc.gitignore,
+        })
+    }
+
+    /// Find the repository that contains `path` by looking for `.warp` on `path` and its parents.
+    ///
+    /// The lookup stops at a filesystem boundary or when it reaches `ceiling`. The `ceiling` itself is
+    /// never checked because it is used for the user home, which contains Warp home.
+    pub fn find(path: impl AsRef<Path>, ceiling: Option<&Path>) -> Result<Self, RepoLoadError> {
+        let path = path.as_ref();
+        let path = match path.canonicalize() {
+            Ok(v) => v,
+            Err(e) => return Err(RepoLoadError::GetMetaDataFailed(path.to_owned(), e)),
+        };
+
+        // The ceiling need to be in the same form as the path otherwise it will never match (e.g.
+        // the user home is a symlink).
+        let ceiling = ceiling.map(|v| v.canonicalize().unwrap_or_else(|_| v.to_owned()));
+
+        #[cfg(unix)]
+        let dev = match std::fs::metadata(&path) {
+            Ok(v) => std::os::unix::fs::MetadataExt::dev(&v),
+            Err(e) => return Err(RepoLoadError::GetMetaDataFailed(path, e)),
+        };
+
+        for dir in path.ancestors() {
+            // Check if we reached the ceiling.
+            if ceiling.as_deref().is_some_and(|v| v == dir) {
+                break;
+            }
+
+            // Check if we crossed a filesystem boundary.
+            #[cfg(unix)]
+            match std::fs::metadata(dir) {
+                Ok(v) if std::os::unix::fs::MetadataExt::dev(&v) == dev => {}
+                Ok(_) => break,
+                Err(e) => return Err(RepoLoadError::GetMetaDataFailed(dir.to_owned(), e)),
+            }
+
+            match Self::load(dir) {
+                Ok(v) => return Ok(v),
+                Err(RepoLoadError::NotWarpRepo) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(RepoLoadError::NotWarpRepo)
+    }
+
     /// `path` is a path to a directory that contains `.warp` directory.
     pub fn load(path: impl AsRef<Path>) -> Result<Self, RepoLoadError> {
         // Check if .warp exists.
-        let path = path.as_ref().join(".warp");
+        let root = path.as_ref();
+        let path = root.join(".warp");
         let meta = match std::fs::symlink_metadata(&path) {
             Ok(v) => v,
             Err(e) => {
@@ -25,8 +146,242 @@ impl Repo {
             return Err(RepoLoadError::NotWarpRepo);
         }
 
-        todo!()
+        // Open the descriptor.
+        let path = path.join("repo.yml");
+        let file = match File::open(&path) {
+            Ok(v) => v,
+            Err(e) => return Err(RepoLoadError::OpenDescriptorFailed(path, e)),
+        };
+
+        // Check version before parsing the rest so a future format can change anything else.
+        let desc: serde_yaml::Value = match serde_yaml::from_reader(BufReader::new(file)) {
+            Ok(v) => v,
+            Err(e) => return Err(RepoLoadError::ReadDescriptorFailed(path, e)),
+        };
+
+        match desc.get("version").and_then(|v| v.as_u64()) {
+            Some(v) if v == u64::from(Self::VERSION) => {}
+            Some(v) => return Err(RepoLoadError::UnsupportedVersion(v)),
+            None => return Err(RepoLoadError::NoDescriptorVersion(path)),
+        }
+
+        // Parse the descriptor.
+        let desc: Descriptor = match serde_yaml::from_value(desc) {
+            Ok(v) => v,
+            Err(e) => return Err(RepoLoadError::ReadDescriptorFailed(path, e)),
+        };
+
+        Ok(Self {
+            path: root.to_owned(),
+            name: desc.name,
+            server: desc.server,
+            key: desc.key,
+            machine: desc.machine,
+            gitignore: desc.gitignore,
+        })
+    }
+
+    /// Add this repository to [`Registry`].
+    pub fn register(&self, home: &Home) -> Result<(), RegistryError> {
+        let mut registry = Registry::load(home)?;
+
+        if registry.add(&self.path) {
+            registry.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove `.warp` directory of this repository.
+    pub fn delete(self) -> Result<(), std::io::Error> {
+        remove_dir_all(self.path.join(".warp"))
+    }
+
+    /// Path to the directory that contains `.warp` directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unique name of the repository on the server.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn server(&self) -> &Url {
+        &self.server
+    }
+
+    /// ID of the [`DataKey`] to encrypt files in this repository.
+    pub fn key(&self) -> &KeyId {
+        &self.key
+    }
+
+    /// Replace the data key after the repository has been re-encrypted by a key rotation.
+    pub fn set_key(&mut self, key: KeyId) -> Result<(), std::io::Error> {
+        let path = self.path.join(".warp").join("repo.yml");
+        let tmp = path.with_extension("tmp");
+        let desc = Descriptor {
+            version: Self::VERSION,
+            name: self.name.clone(),
+            server: self.server.clone(),
+            key,
+            machine: self.machine.clone(),
+            gitignore: self.gitignore,
+        };
+
+        std::fs::write(&tmp, serde_yaml::to_string(&desc).unwrap())?;
+        std::fs::rename(&tmp, &path)?;
+
+        self.key = desc.key;
+
+        Ok(())
+    }
+
+    /// Load the manifest that was stored by [`Repo::set_manifest()`].
+    pub fn manifest(&self) -> Result<Option<Manifest>, ManifestError> {
+        Manifest::load(&self.manifest_path())
     }
+
+    pub fn set_manifest(&self, manifest: &Manifest) -> Result<(), ManifestError> {
+        manifest.save(&self.manifest_path())
+    }
+
+    /// Revision of the remote manifest that the stored manifest is based on. Returns [`None`] if
+    /// the repository has never been synchronized.
+    pub fn revision(&self) -> Result<Option<Revision>, std::io::Error> {
+        match std::fs::read_to_string(self.revision_path()) {
+            Ok(v) => Ok(Some(Revision::from(v))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_revision(&self, rev: &Revision) -> Result<(), std::io::Error> {
+        let path = self.revision_path();
+        let tmp = path.with_extension("tmp");
+
+        std::fs::write(&tmp, rev.to_string())?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    /// Load the manifest that was stored by [`Repo::set_pending()`].
+    pub fn pending(&self) -> Result<Option<Manifest>, ManifestError> {
+        Manifest::load(&self.pending_path())
+    }
+
+    /// Store the manifest of the changes that waiting to push.
+    pub fn set_pending(&self, manifest: &Manifest) -> Result<(), ManifestError> {
+        manifest.save(&self.pending_path())
+    }
+
+    /// Remove the manifest that was stored by [`Repo::set_pending()`] and all staged objects.
+    pub fn clear_pending(&self) -> Result<(), std::io::Error> {
+        let objects = self.objects();
+
+        if let Err(e) = remove_dir_all(&objects) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+
+        match remove_file(self.pending_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Path to the directory to stage encrypted objects that waiting to upload.
+    pub fn objects(&self) -> PathBuf {
+        self.path.join(".warp").join("objects")
+    }
+
+    /// Path to the directory to store downloaded files that waiting to apply.
+    pub fn incoming(&self) -> PathBuf {
+        self.path.join(".warp").join("incoming")
+    }
+
+    /// Scan the working tree with the stored manifest as a cache.
+    pub fn scan(&self, home: &Home) -> Result<Manifest, RepoScanError> {
+        let cache = self.manifest()?;
+        let mut ignore = Ignore::new(&self.path, &home.ignore(), self.gitignore)?;
+
+        Ok(Manifest::scan(&self.path, cache.as_ref(), &mut ignore)?)
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.path.join(".warp").join("manifest")
+    }
+
+    fn revision_path(&self) -> PathBuf {
+        self.path.join(".warp").join("revision")
+    }
+
+    fn pending_path(&self) -> PathBuf {
+        self.path.join(".warp").join("pending")
+    }
+
+    fn write_descriptor(path: &Path, desc: &Descriptor) -> Result<(), RepoCreateError> {
+        let file = match File::create_new(path) {
+            Ok(v) => v,
+            Err(e) => return Err(RepoCreateError::CreateFileFailed(path.to_owned(), e)),
+        };
+
+        let mut file = BufWriter::new(file);
+
+        if let Err(e) = serde_yaml::to_writer(&mut file, desc) {
+            return Err(RepoCreateError::WriteDescriptorFailed(path.to_owned(), e));
+        }
+
+        if let Err(e) = file.flush() {
+            return Err(RepoCreateError::WriteFileFailed(path.to_owned(), e));
+        }
+
+        Ok(())
+    }
+}
+
+/// Content of `.warp/repo.yml`.
+#[derive(Serialize, Deserialize)]
+struct Descriptor {
+    version: u32,
+    name: String,
+    server: Url,
+    key: KeyId,
+    machine: String,
+    #[serde(default)]
+    gitignore: bool,
+}
+
+/// Represents an error when [`Repo::create()`] fails.
+#[derive(Debug, Error)]
+pub enum RepoCreateError {
+    #[error("the specified path is already a Warp repository")]
+    AlreadyExists,
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteDescriptorFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+}
+
+/// Represents an error when [`Repo::scan()`] fails.
+#[derive(Debug, Error)]
+pub enum RepoScanError {
+    #[error("couldn't load the manifest")]
+    LoadManifestFailed(#[from] ManifestError),
+
+    #[error("couldn't load global ignore rules")]
+    LoadIgnoreFailed(#[from] IgnoreError),
+
+    #[error("couldn't scan the working tree")]
+    ScanFailed(#[from] ScanError),
 }
 
 /// Represents an error when [`Repo`] fails to load.
@@ -37,4 +392,16 @@ pub enum RepoLoadError {
 
     #[error("the specified path is not a Warp repository")]
     NotWarpRepo,
+
+    #[error("couldn't open {0}")]
+    OpenDescriptorFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadDescriptorFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("no version in {0}")]
+    NoDescriptorVersion(PathBuf),
+
+    #[error("repository version {0} is not supported by this version of Warp")]
+    UnsupportedVersion(u64),
 }
@@ -0,0 +1,75 @@
+use crate::home::Home;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// List of the repositories on this computer, which is stored in [`Home::repos()`].
+///
+/// A repository is registered when it is created or opened so the registry may contain a path
+/// that is no longer a repository.
+pub struct Registry {
+    path: PathBuf,
+    data: RegistryData,
+}
+
+impl Registry {
+    pub fn load(home: &Home) -> Result<Self, RegistryError> {
+        let path = home.repos();
+        let data = match std::fs::read_to_string(&path) {
+            Ok(v) => match serde_yaml::from_str(&v) {
+                Ok(v) => v,
+                Err(e) => return Err(RegistryError::ReadFailed(path, e)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => RegistryData::default(),
+            Err(e) => return Err(RegistryError::OpenFailed(path, e)),
+        };
+
+        Ok(Self { path, data })
+    }
+
+    /// Returns the path to the directory that contains `.warp` of each repository.
+    pub fn repos(&self) -> impl Iterator<Item = &Path> {
+        self.data.repos.iter().map(|p| p.as_path())
+    }
+
+    /// Returns `false` if `path` is already registered.
+    pub fn add(&mut self, path: impl Into<PathBuf>) -> bool {
+        self.data.repos.insert(path.into())
+    }
+
+    /// Returns `false` if `path` is not registered.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.data.repos.remove(path)
+    }
+
+    pub fn save(&self) -> Result<(), RegistryError> {
+        let tmp = self.path.with_extension("tmp");
+        let data = serde_yaml::to_string(&self.data).unwrap();
+
+        if let Err(e) = std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, &self.path)) {
+            return Err(RegistryError::WriteFailed(self.path.clone(), e));
+        }
+
+        Ok(())
+    }
+}
+
+/// Content of [`Home::repos()`].
+#[derive(Default, Serialize, Deserialize)]
+struct RegistryData {
+    repos: BTreeSet<PathBuf>,
+}
+
+/// Represents an error when [`Registry`] fails to load or save.
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error("couldn't open {0}")]
+    OpenFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFailed(PathBuf, #[source] serde_yaml::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFailed(PathBuf, #[source] std::io::Error),
+}
@@ -0,0 +1,435 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use url::Url;
+use zeroize::Zeroizing;
+
+/// Client for Warp server.
+///
+/// All objects are content-addressed by the ID computed from [`Cipher::object_id()`] so uploading
+/// the same object twice is harmless. The manifest and the keyring use an optimistic concurrency
+/// with [`Revision`] to prevent two computers from overwriting each other changes.
+///
+/// [`Cipher::object_id()`]: crate::crypto::Cipher::object_id()
+pub struct Client {
+    url: Url,
+    auth: Auth,
+}
+
+impl Client {
+    /// The access token is read from `WARP_TOKEN` environment variable. Returns an error if `url`
+    /// is not a HTTP or HTTPS URL.
+    pub fn new(url: Url) -> Result<Self, UrlError> {
+        check_url(&url)?;
+
+        let auth = match std::env::var("WARP_TOKEN") {
+            Ok(v) => Auth::Token(v),
+            Err(_) => Auth::None,
+        };
+
+        Ok(Self { url, auth })
+    }
+
+    /// Use a refresh token from [`Client::login()`] to obtain the access tokens. This has no
+    /// effect if `WARP_TOKEN` is set.
+    pub fn with_refresh_token(mut self, token: Zeroizing<String>) -> Self {
+        if let Auth::None = self.auth {
+            self.auth = Auth::Session {
+                refresh: token,
+                access: Mutex::new(None),
+            };
+        }
+
+        self
+    }
+
+    /// Returns `false` if the requests will be sent without any credentials.
+    pub fn is_authenticated(&self) -> bool {
+        !matches!(self.auth, Auth::None)
+    }
+
+    /// Start a new session with a username and password. Returns [`ClientError::Unauthorized`] if
+    /// the credentials are not valid.
+    pub fn login(&self, username: &str, password: &str) -> Result<Session, ClientError> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            username: &'a str,
+            password: &'a str,
+        }
+
+        let res = ureq::request_url("POST", &self.endpoint(&["auth", "login"]))
+            .send_json(Body { username, password })?;
+
+        res.into_json().map_err(ClientError::InvalidResponse)
+    }
+
+    /// End the session of `refresh_token` that was obtained from [`Client::login()`].
+    pub fn logout(&self, refresh_token: &str) -> Result<(), ClientError> {
+        ureq::request_url("POST", &self.endpoint(&["auth", "logout"]))
+            .send_json(RefreshBody { refresh_token })?;
+
+        Ok(())
+    }
+
+    /// Get the name of the account that the client authenticated as.
+    pub fn whoami(&self) -> Result<String, ClientError> {
+        #[derive(Deserialize)]
+        struct Identity {
+            account: String,
+        }
+
+        let res = self.request("GET", &["auth", "whoami"])?.call()?;
+        let res: Identity = res.into_json().map_err(ClientError::InvalidResponse)?;
+
+        Ok(res.account)
+    }
+
+    /// Create a new repository. Returns [`ClientError::Conflict`] if the name is already taken.
+    pub fn create_repo(&self, name: &str) -> Result<(), ClientError> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            name: &'a str,
+        }
+
+        self.request("POST", &["repos"])?.send_json(Body { name })?;
+
+        Ok(())
+    }
+
+    /// List all repositories that owned by the account.
+    pub fn list_repos(&self) -> Result<Vec<RepoInfo>, ClientError> {
+        let res = self.request("GET", &["repos"])?.call()?;
+
+        res.into_json().map_err(ClientError::InvalidResponse)
+    }
+
+    /// Get information of the repository. Returns [`None`] if it does not exists.
+    pub fn get_repo(&self, name: &str) -> Result<Option<RepoInfo>, ClientError> {
+        let res = match self.request("GET", &["repos", name])?.call() {
+            Ok(v) => v,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        match res.into_json() {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(ClientError::InvalidResponse(e)),
+        }
+    }
+
+    /// Delete the repository and everything in it.
+    pub fn delete_repo(&self, name: &str) -> Result<(), ClientError> {
+        self.request("DELETE", &["repos", name])?.call()?;
+
+        Ok(())
+    }
+
+    /// Download the encrypted manifest of the repository. Returns [`None`] if nothing has been
+    /// pushed to the repository yet.
+    pub fn get_manifest(&self, repo: &str) -> Result<Option<Document>, ClientError> {
+        self.get_document(repo, "manifest")
+    }
+
+    /// Replace the encrypted manifest of the repository. `base` is the revision this manifest was
+    /// computed from or [`None`] if nothing has been pushed yet. Returns
+    /// [`ClientError::Conflict`] if the manifest on the server is not `base`.
+    pub fn put_manifest(
+        &self,
+        repo: &str,
+        data: &[u8],
+        base: Option<&Revision>,
+    ) -> Result<Revision, ClientError> {
+        self.put_document(repo, "manifest", data, base)
+    }
+
+    /// Download the keyring of the repository. Returns [`None`] if the repository has no keyring.
+    pub fn get_keyring(&self, repo: &str) -> Result<Option<Document>, ClientError> {
+        self.get_document(repo, "keyring")
+    }
+
+    /// Replace the keyring of the repository. This use the same concurrency control as
+    /// [`Client::put_manifest()`].
+    pub fn put_keyring(
+        &self,
+        repo: &str,
+        data: &[u8],
+        base: Option<&Revision>,
+    ) -> Result<Revision, ClientError> {
+        self.put_document(repo, "keyring", data, base)
+    }
+
+    /// Check if the object is already exists on the server.
+    pub fn has_object(&self, repo: &str, id: &str) -> Result<bool, ClientError> {
+        match self
+            .request("HEAD", &["repos", repo, "objects", id])?
+            .call()
+        {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::Status(404, _)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List the ID of all objects in the repository.
+    pub fn list_objects(&self, repo: &str) -> Result<Vec<String>, ClientError> {
+        let res = self.request("GET", &["repos", repo, "objects"])?.call()?;
+
+        res.into_json().map_err(ClientError::InvalidResponse)
+    }
+
+    /// Upload an encrypted object. `id` is a hex-encoded keyed hash of the content.
+    pub fn put_object(&self, repo: &str, id: &str, data: impl Read) -> Result<(), ClientError> {
+        self.request("PUT", &["repos", repo, "objects", id])?
+            .set("Content-Type", "application/octet-stream")
+            .send(data)?;
+
+        Ok(())
+    }
+
+    /// Download an encrypted object that was uploaded with [`Client::put_object()`].
+    pub fn get_object(&self, repo: &str, id: &str) -> Result<impl Read, ClientError> {
+        let res = self
+            .request("GET", &["repos", repo, "objects", id])?
+            .call()?;
+
+        Ok(res.into_reader())
+    }
+
+    /// Delete an object that no longer referenced by the manifest. Returns `false` if the object
+    /// does not exists.
+    pub fn delete_object(&self, repo: &str, id: &str) -> Result<bool, ClientError> {
+        match self
+            .request("DELETE", &["repos", repo, "objects", id])?
+            .call()
+        {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::Status(404, _)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get_document(&self, repo: &str, name: &str) -> Result<Option<Document>, ClientError> {
+        let res = match self.request("GET", &["repos", repo, name])?.call() {
+            Ok(v) => v,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let revision = Revision::from_response(&res)?;
+        let mut data = Vec::new();
+
+        if let Err(e) = res.into_reader().read_to_end(&mut data) {
+            return Err(ClientError::ReadResponseFailed(e));
+        }
+
+        Ok(Some(Document { data, revision }))
+    }
+
+    fn put_document(
+        &self,
+        repo: &str,
+        name: &str,
+        data: &[u8],
+        base: Option<&Revision>,
+    ) -> Result<Revision, ClientError> {
+        let req = self
+            .request("PUT", &["repos", repo, name])?
+            .set("Content-Type", "application/octet-stream");
+        let req = match base {
+            Some(v) => req.set("If-Match", &v.0),
+            None => req.set("If-None-Match", "*"),
+        };
+
+        let res = req.send_bytes(data)?;
+
+        Revision::from_response(&res)
+    }
+
+    fn request(&self, method: &str, path: &[&str]) -> Result<ureq::Request, ClientError> {
+        let req = ureq::request_url(method, &self.endpoint(path));
+        let token = match &self.auth {
+            Auth::None => return Ok(req),
+            Auth::Token(v) => v.clone(),
+            Auth::Session { refresh, access } => {
+                // Refresh the access token a bit before it expired so it will not expire while the
+                // request is in-flight.
+                let mut access = access.lock().unwrap();
+
+                match access.as_ref() {
+                    Some(v) if v.expires > Instant::now() + Duration::from_secs(60) => {
+                        v.token.clone()
+                    }
+                    _ => access.insert(self.refresh(refresh)?).token.clone(),
+                }
+            }
+        };
+
+        Ok(req.set("Authorization", &format!("Bearer {token}")))
+    }
+
+    fn refresh(&self, token: &str) -> Result<AccessToken, ClientError> {
+        #[derive(Deserialize)]
+        struct Token {
+            access_token: String,
+            expires_in: u64,
+        }
+
+        let res = ureq::request_url("POST", &self.endpoint(&["auth", "token"])).send_json(
+            RefreshBody {
+                refresh_token: token,
+            },
+        )?;
+        let res: Token = res.into_json().map_err(ClientError::InvalidResponse)?;
+
+        Ok(AccessToken {
+            token: res.access_token,
+            expires: Instant::now() + Duration::from_secs(res.expires_in),
+        })
+    }
+
+    fn endpoint(&self, path: &[&str]) -> Url {
+        // The URL that cannot be a base was rejected by Client::new().
+        let mut url = self.url.clone();
+        url.path_segments_mut().unwrap().pop_if_empty().extend(path);
+        url
+    }
+}
+
+/// Parse the URL of a Warp server. Only HTTP and HTTPS are supported.
+pub fn parse_url(v: &str) -> Result<Url, UrlError> {
+    let url = Url::parse(v)?;
+
+    check_url(&url)?;
+
+    Ok(url)
+}
+
+fn check_url(url: &Url) -> Result<(), UrlError> {
+    match url.scheme() {
+        "http" | "https" if !url.cannot_be_a_base() => Ok(()),
+        v => Err(UrlError::UnsupportedScheme(v.to_owned())),
+    }
+}
+
+/// How [`Client`] authenticate itself to the server.
+enum Auth {
+    None,
+    Token(String),
+    Session {
+        refresh: Zeroizing<String>,
+        access: Mutex<Option<AccessToken>>,
+    },
+}
+
+/// Short-lived token that obtained from a refresh token.
+struct AccessToken {
+    token: String,
+    expires: Instant,
+}
+
+/// Request body that contains a refresh token.
+#[derive(Serialize)]
+struct RefreshBody<'a> {
+    refresh_token: &'a str,
+}
+
+/// Session that was started by [`Client::login()`].
+#[derive(Deserialize)]
+pub struct Session {
+    pub account: String,
+    pub refresh_token: Zeroizing<String>,
+}
+
+/// Information of a repository on the server.
+#[derive(Deserialize)]
+pub struct RepoInfo {
+    pub name: String,
+}
+
+/// Revisioned document of a repository on the server (e.g. the manifest).
+pub struct Document {
+    pub data: Vec<u8>,
+    pub revision: Revision,
+}
+
+/// Revision of a [`Document`] on the server, which is an opaque value from `ETag`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Revision(String);
+
+impl Revision {
+    fn from_response(res: &ureq::Response) -> Result<Self, ClientError> {
+        match res.header("ETag") {
+            Some(v) => Ok(Self(v.to_owned())),
+            None => Err(ClientError::NoRevision),
+        }
+    }
+}
+
+impl From<String> for Revision {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Represents an error when a URL cannot be used for [`Client`].
+#[derive(Debug, Error)]
+pub enum UrlError {
+    #[error("invalid URL")]
+    ParseFailed(#[from] url::ParseError),
+
+    #[error("unsupported scheme '{0}' (only http and https are supported)")]
+    UnsupportedScheme(String),
+}
+
+/// Represents an error when [`Client`] fails to make a request.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("couldn't send the request")]
+    TransportFailed(#[source] Box<ureq::Transport>),
+
+    #[error("authentication required or denied by the server")]
+    Unauthorized,
+
+    #[error("the requested resource does not exists on the server")]
+    NotFound,
+
+    #[error("the resource on the server has been changed by someone else")]
+    Conflict,
+
+    #[error("storage quota exceeded")]
+    QuotaExceeded,
+
+    #[error("server returned status {0}")]
+    UnexpectedStatus(u16),
+
+    #[error("couldn't read the response")]
+    ReadResponseFailed(#[source] std::io::Error),
+
+    #[error("server returned an invalid response")]
+    InvalidResponse(#[source] std::io::Error),
+
+    #[error("server did not return a revision of the document")]
+    NoRevision,
+}
+
+impl From<ureq::Error> for ClientError {
+    fn from(value: ureq::Error) -> Self {
+        match value {
+            ureq::Error::Status(401 | 403, _) => Self::Unauthorized,
+            ureq::Error::Status(404, _) => Self::NotFound,
+            ureq::Error::Status(409 | 412, _) => Self::Conflict,
+            ureq::Error::Status(413 | 507, _) => Self::QuotaExceeded,
+            ureq::Error::Status(v, _) => Self::UnexpectedStatus(v),
+            ureq::Error::Transport(e) => Self::TransportFailed(Box::new(e)),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::crypto::CipherError;
+use crate::key::{KeyId, KeyMgr};
+use crate::repo::{DataKey, Keyring, Repo, UnwrapError};
+use crate::server::{Client, ClientError, Revision};
+use std::error::Error;
+use thiserror::Error;
+
+/// Download the keyring of `repo`. Returns [`None`] if the repository has no keyring, which is the
+/// case when it was created before the keyring existed.
+///
+/// The keyring is not authenticated until it is unwrapped with [`Keyring::unwrap()`].
+pub fn fetch_keyring(
+    client: &Client,
+    repo: &str,
+) -> Result<Option<(Keyring, Revision)>, FetchKeyringError> {
+    let doc = match client
+        .get_keyring(repo)
+        .map_err(FetchKeyringError::DownloadFailed)?
+    {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let keyring = Keyring::from_bytes(&doc.data).map_err(FetchKeyringError::InvalidKeyring)?;
+
+    // The name is authenticated together with the other content when the keyring is unwrapped.
+    if keyring.repo() != repo {
+        return Err(FetchKeyringError::RepoMismatched(keyring.repo().to_owned()));
+    }
+
+    Ok(Some((keyring, doc.revision)))
+}
+
+/// Get the data key of `repo` with the first authorized key that available in `keymgr`.
+///
+/// The ID of the returned key may be different from [`Repo::key()`] if the repository has been
+/// re-encrypted by a key rotation, in which case the caller should update the repository with
+/// [`Repo::set_key()`].
+pub fn unlock(
+    repo: &Repo,
+    keymgr: &KeyMgr,
+    keyring: Option<&Keyring>,
+) -> Result<DataKey, UnlockError> {
+    // The repository that was created before the keyring existed use the key as a data key.
+    let keyring = match keyring {
+        Some(v) => v,
+        None => {
+            let id = repo.key();
+
+            return match keymgr.material(id) {
+                Ok(Some(v)) => {
+                    DataKey::try_from(v).map_err(|e| UnlockError::UnsupportedKey(id.clone(), e))
+                }
+                Ok(None) => Err(UnlockError::NoAuthorizedKey),
+                Err(e) => Err(UnlockError::LoadKeyFailed(id.clone(), e)),
+            };
+        }
+    };
+
+    // Make sure the server did not give us a keyring of the other repository.
+    let key = unwrap_keyring(keymgr, keyring)?;
+    let id = key.id();
+
+    if id != *repo.key() && !keyring.replaces(repo.key()) {
+        return Err(UnlockError::KeyMismatched);
+    }
+
+    Ok(key)
+}
+
+/// Get the data key from `keyring` with the first authorized key that available in `keymgr`.
+pub fn unwrap_keyring(keymgr: &KeyMgr, keyring: &Keyring) -> Result<DataKey, UnlockError> {
+    let mut error = None;
+
+    for id in keyring.keys().filter(|&k| keymgr.get(k).is_some()) {
+        let key = match keymgr.material(id) {
+            Ok(Some(v)) => v,
+            Ok(None) => continue,
+            Err(e) => {
+                error = Some(UnlockError::LoadKeyFailed(id.clone(), e));
+                continue;
+            }
+        };
+
+        match keyring.unwrap(&key) {
+            Ok(Some(v)) => return Ok(v),
+            Ok(None) => continue,
+            Err(e) => error = Some(UnlockError::UnwrapFailed(id.clone(), e)),
+        }
+    }
+
+    Err(error.unwrap_or(UnlockError::NoAuthorizedKey))
+}
+
+/// Represents an error when [`fetch_keyring()`] fails.
+#[derive(Debug, Error)]
+pub enum FetchKeyringError {
+    #[error("couldn't download the keyring")]
+    DownloadFailed(#[source] ClientError),
+
+    #[error("the keyring on the server is not valid")]
+    InvalidKeyring(#[source] postcard::Error),
+
+    #[error("the server returned the keyring of repository '{0}'")]
+    RepoMismatched(String),
+}
+
+/// Represents an error when [`unlock()`] fails.
+#[derive(Debug, Error)]
+pub enum UnlockError {
+    #[error("couldn't load key {0}")]
+    LoadKeyFailed(KeyId, #[source] Box<dyn Error>),
+
+    #[error("key {0} is not supported")]
+    UnsupportedKey(KeyId, #[source] CipherError),
+
+    #[error("none of the keys that authorized to access the repository is available")]
+    NoAuthorizedKey,
+
+    #[error("couldn't unwrap the data key with key {0}")]
+    UnwrapFailed(KeyId, #[source] UnwrapError),
+
+    #[error("the keyring on the server belong to a different repository")]
+    KeyMismatched,
+}
@@ -0,0 +1,9 @@
+pub use self::keyring::*;
+pub use self::pull::*;
+pub use self::push::*;
+pub use self::rotate::*;
+
+mod keyring;
+mod pull;
+mod push;
+mod rotate;
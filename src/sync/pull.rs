@@ -0,0 +1,644 @@
+use crate::crypto::{Cipher, DecryptError, DecryptReader};
+use crate::home::Home;
+use crate::repo::{diff, Change, Entry, EntryType, Manifest, ManifestError, Repo, RepoScanError};
+use crate::server::{Client, ClientError};
+use sha3::{Digest, Sha3_256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{create_dir, create_dir_all, File};
+use std::io::{Read, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Pull the changes that was pushed from the other computers and apply it to the working tree.
+///
+/// Nothing in the working tree will be touched if any of the remote changes would overwrite the
+/// local changes. The content of all changed files is downloaded into [`Repo::incoming()`] and
+/// verified before applying any of it. Each file is written by renaming it from there so the
+/// working tree never contains a partially written file.
+pub fn pull(
+    repo: &Repo,
+    home: &Home,
+    client: &Client,
+    cipher: &Cipher,
+) -> Result<PullSummary, PullError> {
+    // Get the remote manifest.
+    let remote = match client
+        .get_manifest(repo.name())
+        .map_err(PullError::DownloadManifestFailed)?
+    {
+        Some(v) => v,
+        None => return Ok(PullSummary::default()),
+    };
+
+    let revision = remote.revision;
+    let remote = cipher
+        .decrypt(&remote.data)
+        .map_err(PullError::DecryptManifestFailed)?;
+    let remote: Manifest = postcard::from_bytes(&remote).map_err(PullError::InvalidManifest)?;
+
+    // Compute the changes.
+    let base = repo
+        .manifest()
+        .map_err(PullError::LoadManifestFailed)?
+        .unwrap_or_else(Manifest::empty);
+    let changes = diff(&base, &remote);
+
+    if changes.is_empty() {
+        repo.set_revision(&revision)
+            .map_err(PullError::SaveRevisionFailed)?;
+        return Ok(PullSummary::default());
+    }
+
+    // Check if the remote changes would overwrite the local changes.
+    let current = repo.scan(home)?;
+    let local = diff(&base, &current);
+    let mut conflicts = conflicts(&current, &remote, &local, &changes);
+
+    conflicts.extend(blocked(repo.path(), &current, &changes)?);
+
+    if !conflicts.is_empty() {
+        return Err(PullError::Conflicted(
+            conflicts.into_iter().map(String::from).collect(),
+        ));
+    }
+
+    // Download the objects.
+    let root = repo.path();
+    let dir = repo.incoming();
+    let mut summary = PullSummary {
+        changes: changes.len(),
+        files: 0,
+        bytes: 0,
+    };
+
+    if let Err(e) = create_dir_all(&dir) {
+        return Err(PullError::CreateDirectoryFailed(dir, e));
+    }
+
+    let mut uses = HashMap::<[u8; 32], usize>::new();
+
+    for c in &changes {
+        let (from, entry) = match c {
+            Change::Added { entry, .. } | Change::Modified { entry, .. } => (None, entry),
+            Change::Renamed { from, entry, .. } => (Some(from), entry),
+            _ => continue,
+        };
+
+        let hash = match &entry.hash {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // Skip if the same content is already downloaded.
+        let count = uses.entry(*hash).or_default();
+
+        *count += 1;
+
+        if *count != 1 {
+            continue;
+        }
+
+        let path = dir.join(hex::encode(hash));
+
+        if path.exists() {
+            continue;
+        }
+
+        // Use the local content for a rename if it was not modified.
+        if let Some(from) = from {
+            if copy(&root.join(from), entry, &path)? {
+                continue;
+            }
+        }
+
+        download(repo, client, cipher, hash, &path)?;
+
+        summary.files += 1;
+        summary.bytes += entry.size;
+    }
+
+    // Apply deletions. The source of the renames need to be removed first since it may be inside
+    // a deleted directory.
+    for c in &changes {
+        if let Change::Renamed { from, entry, .. } = c {
+            remove(&root.join(from), entry.ty)?;
+        }
+    }
+
+    for c in &changes {
+        if let Change::Deleted { path, entry } = c {
+            remove(&root.join(path), entry.ty)?;
+        }
+    }
+
+    // Apply the remaining changes. The mode of the directories is applied after everything else
+    // since it may prevent their children from being written.
+    let mut dirs = Vec::new();
+
+    for c in &changes {
+        match c {
+            Change::Added { path, entry }
+            | Change::Modified { path, entry }
+            | Change::Renamed {
+                to: path, entry, ..
+            } => {
+                write(&root.join(path), entry, &dir, &mut uses)?;
+
+                if entry.ty == EntryType::Directory {
+                    dirs.push((path, entry.mode));
+                }
+            }
+            Change::ModeChanged { path, mode } => {
+                if remote
+                    .get(path)
+                    .is_some_and(|e| e.ty == EntryType::Directory)
+                {
+                    dirs.push((path, *mode));
+                    continue;
+                }
+
+                let path = root.join(path);
+
+                set_mode(&path, *mode).map_err(|e| PullError::SetModeFailed(path, e))?;
+            }
+            Change::Deleted { .. } => {}
+        }
+    }
+
+    // Apply the mode of the directories with the deepest first so a directory without the search
+    // permission will not prevent its children from being changed.
+    dirs.sort_unstable_by(|a, b| b.0.cmp(a.0));
+
+    for (path, mode) in dirs {
+        let path = root.join(path);
+
+        set_mode(&path, mode).map_err(|e| PullError::SetModeFailed(path, e))?;
+    }
+
+    // Replace the base manifest.
+    repo.set_manifest(&remote)
+        .map_err(PullError::SaveManifestFailed)?;
+    repo.set_revision(&revision)
+        .map_err(PullError::SaveRevisionFailed)?;
+
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        return Err(PullError::RemoveDirectoryFailed(dir, e));
+    }
+
+    Ok(summary)
+}
+
+/// Returns the paths that was changed both locally and remotely.
+fn conflicts<'a>(
+    current: &Manifest,
+    remote: &Manifest,
+    local: &'a [Change],
+    changes: &'a [Change],
+) -> BTreeSet<&'a str> {
+    let is_dir = |e: Option<&Entry>| e.is_some_and(|e| e.ty == EntryType::Directory);
+    let mut touched = BTreeSet::new();
+    let mut conflicts = BTreeSet::new();
+
+    for c in local {
+        if let Change::Renamed { from, .. } = c {
+            touched.insert(from.as_str());
+        }
+
+        touched.insert(c.path());
+    }
+
+    for c in changes {
+        let mut paths = vec![c.path()];
+
+        if let Change::Renamed { from, .. } = c {
+            paths.push(from);
+        }
+
+        for p in paths {
+            // Skip if the local already has the same thing as the remote.
+            let l = current.get(p);
+            let r = remote.get(p);
+            let same = match (l, r) {
+                (Some(l), Some(r)) => l.ty == r.ty && l.hash == r.hash && l.mode == r.mode,
+                (None, None) => true,
+                _ => false,
+            };
+
+            if same {
+                continue;
+            }
+
+            // Check if the path itself was changed.
+            if touched.contains(p) {
+                conflicts.insert(p);
+                continue;
+            }
+
+            // Check if anything inside the path was changed when the path is no longer a directory.
+            let prefix = format!("{p}/");
+
+            if !is_dir(r)
+                && touched
+                    .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                    .next()
+                    .is_some_and(|v| v.starts_with(&prefix))
+            {
+                conflicts.insert(p);
+                continue;
+            }
+
+            // Check if any parent is no longer a directory.
+            if p.match_indices('/')
+                .map(|(i, _)| &p[..i])
+                .any(|a| touched.contains(a) && !is_dir(current.get(a)))
+            {
+                conflicts.insert(p);
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Returns the directories that will be replaced with a non-directory but contains the files that
+/// are not in `current` (e.g. the excluded files), which would prevent the directory from being
+/// removed.
+fn blocked<'a>(
+    root: &Path,
+    current: &Manifest,
+    changes: &'a [Change],
+) -> Result<Vec<&'a str>, PullError> {
+    let mut blocked = Vec::new();
+
+    for c in changes {
+        let (path, entry) = match c {
+            Change::Added { path, entry }
+            | Change::Modified { path, entry }
+            | Change::Renamed {
+                to: path, entry, ..
+            } => (path, entry),
+            _ => continue,
+        };
+
+        if entry.ty == EntryType::Directory
+            || !current
+                .get(path)
+                .is_some_and(|e| e.ty == EntryType::Directory)
+        {
+            continue;
+        }
+
+        if has_untracked(&root.join(path), path, current)? {
+            blocked.push(path.as_str());
+        }
+    }
+
+    Ok(blocked)
+}
+
+/// Returns `true` if the directory `dir` at `path` in the repository contains anything that is
+/// not in `current`.
+fn has_untracked(dir: &Path, path: &str, current: &Manifest) -> Result<bool, PullError> {
+    let items = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(PullError::ReadDirectoryFailed(dir.to_owned(), e)),
+    };
+
+    for item in items {
+        let item = item.map_err(|e| PullError::ReadDirectoryFailed(dir.to_owned(), e))?;
+        let name = match item.file_name().into_string() {
+            Ok(v) => v,
+            Err(_) => return Ok(true),
+        };
+
+        let path = format!("{path}/{name}");
+        let entry = match current.get(&path) {
+            Some(v) => v,
+            None => return Ok(true),
+        };
+
+        if entry.ty == EntryType::Directory && has_untracked(&item.path(), &path, current)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Copy the local content of `src` to `dst` if it is the same as `entry`. Returns `false` if it
+/// is not.
+fn copy(src: &Path, entry: &Entry, dst: &Path) -> Result<bool, PullError> {
+    let hash = entry.hash.as_ref().unwrap();
+    let r = match entry.ty {
+        EntryType::File => match File::open(src) {
+            Ok(v) => receive(v, hash, dst, |e| {
+                PullError::ReadFileFailed(src.to_owned(), e)
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(PullError::ReadFileFailed(src.to_owned(), e)),
+        },
+        EntryType::Symlink => match std::fs::read_link(src) {
+            Ok(v) => match v.to_str() {
+                Some(v) => {
+                    let target = v.replace(std::path::MAIN_SEPARATOR, "/");
+
+                    receive(target.as_bytes(), hash, dst, |e| {
+                        PullError::ReadFileFailed(src.to_owned(), e)
+                    })
+                }
+                None => return Ok(false),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(PullError::ReadFileFailed(src.to_owned(), e)),
+        },
+        EntryType::Directory => unreachable!(),
+    };
+
+    // The local file may be modified or replaced with something else.
+    match r {
+        Err(PullError::ReadFileFailed(_, e)) if e.kind() == std::io::ErrorKind::IsADirectory => {
+            Ok(false)
+        }
+        r => r,
+    }
+}
+
+fn download(
+    repo: &Repo,
+    client: &Client,
+    cipher: &Cipher,
+    hash: &[u8; 32],
+    dst: &Path,
+) -> Result<(), PullError> {
+    let id = hex::encode(cipher.object_id(hash));
+    let data = match client.get_object(repo.name(), &id) {
+        Ok(v) => v,
+        Err(e) => return Err(PullError::DownloadObjectFailed(id, e)),
+    };
+
+    let data = match DecryptReader::new(cipher, data) {
+        Ok(v) => v,
+        Err(e) => return Err(PullError::DecryptObjectFailed(id, e)),
+    };
+
+    if !receive(data, hash, dst, |e| {
+        PullError::ReadObjectFailed(id.clone(), e)
+    })? {
+        return Err(PullError::ObjectMismatched(id));
+    }
+
+    Ok(())
+}
+
+/// Write the content of `src` to `dst` if its hash is `hash`. Returns `false` if the hash is
+/// mismatched.
+fn receive(
+    mut src: impl Read,
+    hash: &[u8; 32],
+    dst: &Path,
+    read_failed: impl Fn(std::io::Error) -> PullError,
+) -> Result<bool, PullError> {
+    let tmp = dst.with_extension("tmp");
+    let mut file = match File::create(&tmp) {
+        Ok(v) => v,
+        Err(e) => return Err(PullError::CreateFileFailed(tmp, e)),
+    };
+
+    // Copy the content.
+    let mut hasher = Sha3_256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                drop(file);
+                std::fs::remove_file(&tmp).ok();
+                return Err(read_failed(e));
+            }
+        };
+
+        hasher.update(&buf[..n]);
+
+        if let Err(e) = file.write_all(&buf[..n]) {
+            drop(file);
+            std::fs::remove_file(&tmp).ok();
+            return Err(PullError::WriteFileFailed(tmp, e));
+        }
+    }
+
+    drop(file);
+
+    // Check the content.
+    let actual: [u8; 32] = hasher.finalize().into();
+
+    if actual != *hash {
+        std::fs::remove_file(&tmp).ok();
+        return Ok(false);
+    }
+
+    if let Err(e) = std::fs::rename(&tmp, dst) {
+        std::fs::remove_file(&tmp).ok();
+        return Err(PullError::WriteFileFailed(dst.to_owned(), e));
+    }
+
+    Ok(true)
+}
+
+fn remove(path: &Path, ty: EntryType) -> Result<(), PullError> {
+    let r = match ty {
+        EntryType::Directory => std::fs::remove_dir(path),
+        EntryType::File | EntryType::Symlink => std::fs::remove_file(path),
+    };
+
+    // A directory that still has some files is the one that contains the excluded files so we
+    // keep it. The directory that will be replaced with a file was already checked by blocked().
+    match r {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => Ok(()),
+        Err(e) => Err(PullError::RemoveFailed(path.to_owned(), e)),
+    }
+}
+
+fn write(
+    path: &Path,
+    entry: &Entry,
+    incoming: &Path,
+    uses: &mut HashMap<[u8; 32], usize>,
+) -> Result<(), PullError> {
+    // Create a directory. Its mode will be applied by the caller once its children are written.
+    let hash = match &entry.hash {
+        Some(v) => v,
+        None => {
+            return match create_dir(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                    Err(PullError::CreateDirectoryFailed(path.to_owned(), e))
+                }
+                _ => Ok(()),
+            };
+        }
+    };
+
+    // Get the downloaded content. We need to copy it if there are other entries with the same
+    // content.
+    let mut src = incoming.join(hex::encode(hash));
+    let count = uses.get_mut(hash).unwrap();
+
+    *count -= 1;
+
+    if entry.ty == EntryType::Symlink {
+        let target = match std::fs::read_to_string(&src) {
+            Ok(v) => v,
+            Err(e) => return Err(PullError::ReadFileFailed(src, e)),
+        };
+
+        src.set_extension("link");
+
+        if let Err(e) = std::fs::remove_file(&src) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(PullError::RemoveFailed(src, e));
+            }
+        }
+
+        if let Err(e) = symlink(&target, &src) {
+            return Err(PullError::CreateFileFailed(src, e));
+        }
+    } else {
+        if *count != 0 {
+            let tmp = src.with_extension("copy");
+
+            if let Err(e) = std::fs::copy(&src, &tmp) {
+                return Err(PullError::CreateFileFailed(tmp, e));
+            }
+
+            src = tmp;
+        }
+
+        // Set the mtime to the same as the manifest so the next scan can reuse the hash from it.
+        let r = File::options()
+            .write(true)
+            .open(&src)
+            .and_then(|f| f.set_modified(entry.mtime));
+
+        if let Err(e) = r {
+            return Err(PullError::WriteFileFailed(src, e));
+        }
+
+        if let Err(e) = set_mode(&src, entry.mode) {
+            return Err(PullError::SetModeFailed(src, e));
+        }
+    }
+
+    // Move to the working tree.
+    if let Err(e) = std::fs::rename(&src, path) {
+        return Err(PullError::WriteFileFailed(path.to_owned(), e));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    let mut perms = std::fs::symlink_metadata(path)?.permissions();
+
+    perms.set_readonly(mode & 0o222 == 0);
+
+    std::fs::set_permissions(path, perms)
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target.replace('/', "\\"), link)
+}
+
+/// Result of [`pull()`].
+#[derive(Default)]
+pub struct PullSummary {
+    /// Number of changes that was applied.
+    pub changes: usize,
+    /// Number of files that was downloaded.
+    pub files: usize,
+    /// Total size of the downloaded files after decryption.
+    pub bytes: u64,
+}
+
+/// Represents an error when [`pull()`] fails.
+#[derive(Debug, Error)]
+pub enum PullError {
+    #[error("couldn't download the manifest")]
+    DownloadManifestFailed(#[source] ClientError),
+
+    #[error("couldn't decrypt the manifest")]
+    DecryptManifestFailed(#[source] DecryptError),
+
+    #[error("the remote manifest is not valid")]
+    InvalidManifest(#[source] postcard::Error),
+
+    #[error("couldn't load the manifest")]
+    LoadManifestFailed(#[source] ManifestError),
+
+    #[error("couldn't scan the working tree")]
+    ScanFailed(#[from] RepoScanError),
+
+    #[error("local changes would be overwritten by the remote changes")]
+    Conflicted(Vec<String>),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't download object {0}")]
+    DownloadObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't decrypt object {0}")]
+    DecryptObjectFailed(String, #[source] DecryptError),
+
+    #[error("couldn't read object {0}")]
+    ReadObjectFailed(String, #[source] std::io::Error),
+
+    #[error("object {0} does not have the expected content")]
+    ObjectMismatched(String),
+
+    #[error("couldn't remove {0}")]
+    RemoveFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't set permissions of {0}")]
+    SetModeFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't save the manifest")]
+    SaveManifestFailed(#[source] ManifestError),
+
+    #[error("couldn't save the revision of the manifest")]
+    SaveRevisionFailed(#[source] std::io::Error),
+
+    #[error("couldn't remove {0}")]
+    RemoveDirectoryFailed(PathBuf, #[source] std::io::Error),
+}
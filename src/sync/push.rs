@@ -0,0 +1,297 @@
+use crate::crypto::{Cipher, EncryptError, EncryptWriter};
+use crate::home::Home;
+use crate::repo::{diff, Change, Entry, EntryType, Manifest, ManifestError, Repo, RepoScanError};
+use crate::server::{Client, ClientError};
+use sha3::{Digest, Sha3_256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Push the changes in the working tree since the last push to the server.
+///
+/// The content of the changed files is encrypted into [`Repo::objects()`] before uploading and the
+/// new manifest is stored with [`Repo::set_pending()`] so the changes stay queued if the push
+/// fails. The base manifest is replaced only when everything has been uploaded.
+pub fn push(
+    repo: &Repo,
+    home: &Home,
+    client: &Client,
+    cipher: &Cipher,
+) -> Result<PushSummary, PushError> {
+    // Compute the changes.
+    let base = repo
+        .manifest()
+        .map_err(PushError::LoadManifestFailed)?
+        .unwrap_or_else(Manifest::empty);
+    let current = repo.scan(home)?;
+    let changes = diff(&base, &current);
+
+    if changes.is_empty() {
+        repo.clear_pending().map_err(PushError::ClearQueueFailed)?;
+        return Ok(PushSummary::default());
+    }
+
+    // Queue the changes.
+    let dir = repo.objects();
+
+    repo.set_pending(&current)
+        .map_err(PushError::QueueChangesFailed)?;
+
+    if let Err(e) = create_dir_all(&dir) {
+        return Err(PushError::CreateDirectoryFailed(dir, e));
+    }
+
+    // Stage the objects. Renames and mode changes do not need any upload since the server already
+    // has the content.
+    let mut summary = PushSummary {
+        changes: changes.len(),
+        files: 0,
+        bytes: 0,
+    };
+
+    let mut checked = HashSet::new();
+    let mut objects = BTreeMap::new();
+
+    for c in &changes {
+        let (path, entry) = match c {
+            Change::Added { path, entry } | Change::Modified { path, entry } => (path, entry),
+            _ => continue,
+        };
+
+        let hash = match &entry.hash {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let id = hex::encode(cipher.object_id(hash));
+
+        if !checked.insert(id.clone()) {
+            continue;
+        }
+
+        // Skip if the server already has it.
+        let staged = dir.join(&id);
+
+        match client.has_object(repo.name(), &id) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => return Err(PushError::CheckObjectFailed(id, e)),
+        }
+
+        if !staged.exists() {
+            stage(cipher, &repo.path().join(path), entry, &staged)?;
+        }
+
+        summary.files += 1;
+        summary.bytes += entry.size;
+
+        objects.insert(id, staged);
+    }
+
+    // Upload the objects.
+    for (id, path) in objects {
+        let file = match File::open(&path) {
+            Ok(v) => v,
+            Err(e) => return Err(PushError::OpenObjectFailed(path, e)),
+        };
+
+        if let Err(e) = client.put_object(repo.name(), &id, BufReader::new(file)) {
+            return Err(PushError::UploadObjectFailed(id, e));
+        }
+    }
+
+    // Upload the manifest.
+    let base = repo.revision().map_err(PushError::LoadRevisionFailed)?;
+    let data = postcard::to_stdvec(&current).unwrap();
+    let data = cipher
+        .encrypt(&data)
+        .map_err(PushError::EncryptManifestFailed)?;
+
+    let revision = client
+        .put_manifest(repo.name(), &data, base.as_ref())
+        .map_err(PushError::UploadManifestFailed)?;
+
+    // Replace the base manifest.
+    repo.set_manifest(&current)
+        .map_err(PushError::SaveManifestFailed)?;
+    repo.set_revision(&revision)
+        .map_err(PushError::SaveRevisionFailed)?;
+    repo.clear_pending().map_err(PushError::ClearQueueFailed)?;
+
+    Ok(summary)
+}
+
+fn stage(cipher: &Cipher, src: &Path, entry: &Entry, dst: &Path) -> Result<(), PushError> {
+    let tmp = dst.with_extension("tmp");
+    let file = match File::create(&tmp) {
+        Ok(v) => v,
+        Err(e) => return Err(PushError::CreateFileFailed(tmp, e)),
+    };
+
+    let r = encrypt(cipher, src, entry, &tmp, file);
+
+    if r.is_ok() {
+        if let Err(e) = std::fs::rename(&tmp, dst) {
+            std::fs::remove_file(&tmp).unwrap();
+            return Err(PushError::WriteFileFailed(dst.to_owned(), e));
+        }
+    } else {
+        std::fs::remove_file(&tmp).unwrap();
+    }
+
+    r
+}
+
+fn encrypt(
+    cipher: &Cipher,
+    src: &Path,
+    entry: &Entry,
+    path: &Path,
+    file: File,
+) -> Result<(), PushError> {
+    let mut writer = match EncryptWriter::new(cipher, BufWriter::new(file)) {
+        Ok(v) => v,
+        Err(e) => return Err(PushError::EncryptFileFailed(src.to_owned(), e)),
+    };
+
+    // Encrypt the content. We need to compute the hash again since the file may be modified after
+    // it was scanned.
+    let mut hasher = Sha3_256::new();
+
+    match entry.ty {
+        EntryType::File => {
+            let mut file = match File::open(src) {
+                Ok(v) => v,
+                Err(e) => return Err(PushError::ReadFileFailed(src.to_owned(), e)),
+            };
+
+            let mut buf = vec![0u8; 64 * 1024];
+
+            loop {
+                let n = match file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(v) => v,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(PushError::ReadFileFailed(src.to_owned(), e)),
+                };
+
+                hasher.update(&buf[..n]);
+
+                if let Err(e) = writer.write_all(&buf[..n]) {
+                    return Err(PushError::WriteFileFailed(path.to_owned(), e));
+                }
+            }
+        }
+        EntryType::Symlink => {
+            let target = match std::fs::read_link(src) {
+                Ok(v) => v,
+                Err(e) => return Err(PushError::ReadFileFailed(src.to_owned(), e)),
+            };
+
+            let target = match target.to_str() {
+                Some(v) => v.replace(std::path::MAIN_SEPARATOR, "/"),
+                None => return Err(PushError::FileChanged(src.to_owned())),
+            };
+
+            hasher.update(target.as_bytes());
+
+            if let Err(e) = writer.write_all(target.as_bytes()) {
+                return Err(PushError::WriteFileFailed(path.to_owned(), e));
+            }
+        }
+        EntryType::Directory => unreachable!(),
+    }
+
+    // Flush the ciphertext.
+    let file = match writer
+        .finish()
+        .and_then(|w| w.into_inner().map_err(|e| e.into_error()))
+    {
+        Ok(v) => v,
+        Err(e) => return Err(PushError::WriteFileFailed(path.to_owned(), e)),
+    };
+
+    if let Err(e) = file.sync_all() {
+        return Err(PushError::WriteFileFailed(path.to_owned(), e));
+    }
+
+    // Check if the content is the same as the manifest.
+    let hash: [u8; 32] = hasher.finalize().into();
+
+    if entry.hash != Some(hash) {
+        return Err(PushError::FileChanged(src.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Result of [`push()`].
+#[derive(Default)]
+pub struct PushSummary {
+    /// Number of changes that was pushed.
+    pub changes: usize,
+    /// Number of files that was uploaded.
+    pub files: usize,
+    /// Total size of the uploaded files before encryption.
+    pub bytes: u64,
+}
+
+/// Represents an error when [`push()`] fails.
+#[derive(Debug, Error)]
+pub enum PushError {
+    #[error("couldn't load the manifest")]
+    LoadManifestFailed(#[source] ManifestError),
+
+    #[error("couldn't scan the working tree")]
+    ScanFailed(#[from] RepoScanError),
+
+    #[error("couldn't queue the changes")]
+    QueueChangesFailed(#[source] ManifestError),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't encrypt {0}")]
+    EncryptFileFailed(PathBuf, #[source] EncryptError),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} was modified during the push")]
+    FileChanged(PathBuf),
+
+    #[error("couldn't check if object {0} exists on the server")]
+    CheckObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't open {0}")]
+    OpenObjectFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't upload object {0}")]
+    UploadObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't load the revision of the manifest")]
+    LoadRevisionFailed(#[source] std::io::Error),
+
+    #[error("couldn't encrypt the manifest")]
+    EncryptManifestFailed(#[source] EncryptError),
+
+    #[error("couldn't upload the manifest")]
+    UploadManifestFailed(#[source] ClientError),
+
+    #[error("couldn't save the manifest")]
+    SaveManifestFailed(#[source] ManifestError),
+
+    #[error("couldn't save the revision of the manifest")]
+    SaveRevisionFailed(#[source] std::io::Error),
+
+    #[error("couldn't clear the queued changes")]
+    ClearQueueFailed(#[source] std::io::Error),
+}
@@ -0,0 +1,642 @@
+use super::{fetch_keyring, unwrap_keyring, FetchKeyringError, UnlockError};
+use crate::crypto::{Cipher, DecryptError, DecryptReader, EncryptError, EncryptWriter};
+use crate::home::Home;
+use crate::key::{KeyId, KeyMaterial, KeyMgr};
+use crate::repo::{DataKey, Keyring, Manifest, UnwrapError};
+use crate::server::{Client, ClientError, Revision};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use url::Url;
+
+/// Re-encrypt `repo` on the server with a new data key and retire the keys in `from`, which default
+/// to all authorized keys other than `to`.
+///
+/// The new data key is wrapped with `to` and the other authorized keys that available on this
+/// computer. The old data key is recorded in the new keyring so the other computers can follow it.
+/// The progress is recorded in [`Home::rotations()`] so calling this again with the same arguments
+/// resumes the rotation if it was interrupted. The journal is removed only when the verification
+/// has passed.
+pub fn rotate(
+    home: &Home,
+    keymgr: &KeyMgr,
+    client: &Client,
+    server: &Url,
+    repo: &str,
+    to: &KeyId,
+    from: Option<&[KeyId]>,
+) -> Result<RotateSummary, RotateError> {
+    if from.is_some_and(|v| v.contains(to)) {
+        return Err(RotateError::RetireTargetKey(to.clone()));
+    }
+
+    // Load the key to rotate to.
+    let key = match keymgr.material(to) {
+        Ok(Some(v)) => v,
+        Ok(None) => return Err(RotateError::UnknownKey(to.clone())),
+        Err(e) => return Err(RotateError::LoadKeyFailed(to.clone(), e)),
+    };
+
+    // Load the journal or start a new rotation. The default keys to retire are resolved from the
+    // keyring before the rotation so it is the set in the journal when resuming since the keyring
+    // may have been replaced.
+    let path = journal_path(home, server, repo);
+    let mut journal = match Journal::load(&path)? {
+        Some(v) if v.to == *to && from.is_none_or(|f| v.retires(f)) => v,
+        Some(v) => return Err(RotateError::InProgress(v.to, v.retired)),
+        None => {
+            let dir = home.rotations();
+
+            if let Err(e) = create_dir_all(&dir) {
+                return Err(RotateError::CreateDirectoryFailed(dir, e));
+            }
+
+            let (keyring, _) = fetch_keyring(client, repo)?.ok_or(RotateError::NoKeyring)?;
+            let from = match from {
+                Some(v) => v.to_vec(),
+                None => keyring.keys().filter(|&k| k != to).cloned().collect(),
+            };
+
+            let journal = Journal::start(keymgr, &keyring, repo, &key, &from)?;
+
+            journal.save(&path)?;
+            journal
+        }
+    };
+
+    let old = journal.unwrap(&journal.old, &key)?;
+    let new = journal.unwrap(&journal.new, &key)?;
+    let mut summary = RotateSummary {
+        key: new.id(),
+        objects: 0,
+        deleted: 0,
+        retired: journal.retired.clone(),
+        unavailable: journal.unavailable.clone(),
+    };
+
+    // Re-encrypt the objects then switch the manifest and the keyring. The other computers will
+    // fail to decrypt the manifest in between these two uploads, which is the same as the manifest
+    // is being pushed so they only need to try again.
+    let tmp = path.with_extension("object");
+
+    if !journal.switched {
+        let old = old.cipher();
+        let new = new.cipher();
+
+        if let Some(remote) = fetch_manifest(client, repo, old, new)? {
+            for hash in blobs(&remote.manifest) {
+                let id = hex::encode(new.object_id(hash));
+
+                match client.has_object(repo, &id) {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(e) => return Err(RotateError::CheckObjectFailed(id, e)),
+                }
+
+                copy(client, repo, old, new, hash, &tmp)?;
+                summary.objects += 1;
+            }
+
+            if !remote.rotated {
+                let data = postcard::to_stdvec(&remote.manifest).unwrap();
+                let data = new
+                    .encrypt(&data)
+                    .map_err(RotateError::EncryptManifestFailed)?;
+
+                client
+                    .put_manifest(repo, &data, Some(&remote.revision))
+                    .map_err(RotateError::UploadManifestFailed)?;
+            }
+        }
+
+        // The keyring may already be uploaded if we were interrupted before updating the journal.
+        let (keyring, revision) = fetch_keyring(client, repo)?.ok_or(RotateError::NoKeyring)?;
+
+        if !keyring
+            .unwrap(&key)
+            .is_ok_and(|v| v.is_some_and(|v| v.id() == summary.key))
+        {
+            client
+                .put_keyring(repo, &journal.new, Some(&revision))
+                .map_err(RotateError::UploadKeyringFailed)?;
+        }
+
+        journal.switched = true;
+        journal.save(&path)?;
+    }
+
+    // Remove the objects that encrypted with the old key, including the ones that no longer
+    // referenced by the manifest.
+    let old = old.cipher();
+    let new = new.cipher();
+    let manifest = fetch_manifest(client, repo, old, new)?;
+    let current = objects(manifest.as_ref(), new);
+    let objects = client
+        .list_objects(repo)
+        .map_err(RotateError::ListObjectsFailed)?;
+    let others = objects.iter().filter(|&v| !current.contains(v));
+
+    for id in encrypted_with(client, repo, old, others)? {
+        match client.delete_object(repo, id) {
+            Ok(true) => summary.deleted += 1,
+            Ok(false) => {}
+            Err(e) => return Err(RotateError::DeleteObjectFailed(id.clone(), e)),
+        }
+    }
+
+    // Verify that nothing on the server still use the old key.
+    verify(client, repo, &key, old, new, manifest.as_ref())?;
+
+    for path in [tmp, path] {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(RotateError::RemoveJournalFailed(path, e));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(summary)
+}
+
+fn journal_path(home: &Home, server: &Url, repo: &str) -> PathBuf {
+    let mut hasher = Sha3_256::new();
+
+    hasher.update(server.as_str());
+    hasher.update([0]);
+    hasher.update(repo);
+
+    home.rotations().join(hex::encode(hasher.finalize()))
+}
+
+fn fetch_manifest(
+    client: &Client,
+    repo: &str,
+    old: &Cipher,
+    new: &Cipher,
+) -> Result<Option<RemoteManifest>, RotateError> {
+    let remote = match client
+        .get_manifest(repo)
+        .map_err(RotateError::DownloadManifestFailed)?
+    {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    // Check if the manifest has already been re-encrypted.
+    let decoded = new
+        .decrypt(&remote.data)
+        .ok()
+        .and_then(|v| postcard::from_bytes(&v).ok());
+
+    if let Some(manifest) = decoded {
+        return Ok(Some(RemoteManifest {
+            manifest,
+            revision: remote.revision,
+            rotated: true,
+        }));
+    }
+
+    let data = old
+        .decrypt(&remote.data)
+        .map_err(RotateError::DecryptManifestFailed)?;
+    let manifest = postcard::from_bytes(&data).map_err(RotateError::InvalidManifest)?;
+
+    Ok(Some(RemoteManifest {
+        manifest,
+        revision: remote.revision,
+        rotated: false,
+    }))
+}
+
+/// Returns the ID of all objects that referenced by `manifest`.
+fn objects(manifest: Option<&RemoteManifest>, cipher: &Cipher) -> BTreeSet<String> {
+    match manifest {
+        Some(v) => blobs(&v.manifest)
+            .into_iter()
+            .map(|h| hex::encode(cipher.object_id(h)))
+            .collect(),
+        None => BTreeSet::new(),
+    }
+}
+
+/// Returns the objects in `ids` that was encrypted with `key`. Only the header of each object is
+/// downloaded.
+fn encrypted_with<'a>(
+    client: &Client,
+    repo: &str,
+    key: &Cipher,
+    ids: impl IntoIterator<Item = &'a String>,
+) -> Result<Vec<&'a String>, RotateError> {
+    let mut found = Vec::new();
+
+    for id in ids {
+        let data = match client.get_object(repo, id) {
+            Ok(v) => v,
+            Err(e) => return Err(RotateError::DownloadObjectFailed(id.clone(), e)),
+        };
+
+        match DecryptReader::new(key, data) {
+            Ok(_) => found.push(id),
+            Err(DecryptError::ReadFailed(e)) => {
+                return Err(RotateError::ReadObjectFailed(id.clone(), e));
+            }
+            Err(_) => {}
+        }
+    }
+
+    Ok(found)
+}
+
+/// Returns the hash of all unique contents in `manifest`.
+fn blobs(manifest: &Manifest) -> BTreeSet<&[u8; 32]> {
+    manifest
+        .entries()
+        .filter_map(|(_, e)| e.hash.as_ref())
+        .collect()
+}
+
+/// Re-encrypt the object with `hash` from `old` to `new`. `tmp` is used to hold the re-encrypted
+/// object while uploading.
+fn copy(
+    client: &Client,
+    repo: &str,
+    old: &Cipher,
+    new: &Cipher,
+    hash: &[u8; 32],
+    tmp: &Path,
+) -> Result<(), RotateError> {
+    // Download.
+    let src = hex::encode(old.object_id(hash));
+    let dst = hex::encode(new.object_id(hash));
+    let data = match client.get_object(repo, &src) {
+        Ok(v) => v,
+        Err(e) => return Err(RotateError::DownloadObjectFailed(src, e)),
+    };
+
+    let mut data = match DecryptReader::new(old, data) {
+        Ok(v) => v,
+        Err(e) => return Err(RotateError::DecryptObjectFailed(src, e)),
+    };
+
+    // Re-encrypt.
+    let file = match File::create(tmp) {
+        Ok(v) => v,
+        Err(e) => return Err(RotateError::CreateFileFailed(tmp.to_owned(), e)),
+    };
+
+    let mut file = match EncryptWriter::new(new, BufWriter::new(file)) {
+        Ok(v) => v,
+        Err(e) => return Err(RotateError::EncryptObjectFailed(dst, e)),
+    };
+
+    let mut hasher = Sha3_256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = match data.read(&mut buf) {
+            Ok(0) => break,
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(RotateError::ReadObjectFailed(src, e)),
+        };
+
+        hasher.update(&buf[..n]);
+
+        if let Err(e) = file.write_all(&buf[..n]) {
+            return Err(RotateError::WriteFileFailed(tmp.to_owned(), e));
+        }
+    }
+
+    if let Err(e) = file.finish() {
+        return Err(RotateError::WriteFileFailed(tmp.to_owned(), e));
+    }
+
+    // The server may give us the other object with the same key.
+    let actual: [u8; 32] = hasher.finalize().into();
+
+    if actual != *hash {
+        return Err(RotateError::ObjectMismatched(src));
+    }
+
+    // Upload.
+    let file = match File::open(tmp) {
+        Ok(v) => v,
+        Err(e) => return Err(RotateError::OpenFileFailed(tmp.to_owned(), e)),
+    };
+
+    if let Err(e) = client.put_object(repo, &dst, BufReader::new(file)) {
+        return Err(RotateError::UploadObjectFailed(dst, e));
+    }
+
+    Ok(())
+}
+
+fn verify(
+    client: &Client,
+    repo: &str,
+    key: &KeyMaterial,
+    old: &Cipher,
+    new: &Cipher,
+    manifest: Option<&RemoteManifest>,
+) -> Result<(), RotateError> {
+    // Check the keyring.
+    let (keyring, _) = fetch_keyring(client, repo)?.ok_or(RotateError::NoKeyring)?;
+
+    match keyring.unwrap(key) {
+        Ok(Some(v)) if v.id() == *new.id() => {}
+        _ => return Err(RotateError::KeyringNotRotated),
+    }
+
+    if keyring.keys().any(|k| keyring.is_retired(k)) {
+        return Err(RotateError::KeyringNotRotated);
+    }
+
+    // Check the manifest.
+    let manifest = match manifest {
+        Some(v) if v.rotated => v,
+        Some(_) => return Err(RotateError::ManifestNotRotated),
+        None => return Ok(()),
+    };
+
+    // Check the objects. The ones that no longer referenced by the manifest are checked too since
+    // they can be decrypted with the old key.
+    let objects = client
+        .list_objects(repo)
+        .map_err(RotateError::ListObjectsFailed)?;
+    let current = self::objects(Some(manifest), new);
+    let missing = current.iter().filter(|&v| !objects.contains(v)).count();
+    let others = objects.iter().filter(|&v| !current.contains(v));
+    let remaining = encrypted_with(client, repo, old, others)?.len();
+
+    if missing != 0 || remaining != 0 {
+        return Err(RotateError::ObjectsNotRotated { missing, remaining });
+    }
+
+    Ok(())
+}
+
+/// Progress of a rotation that stored in [`Home::rotations()`].
+///
+/// Both data keys are wrapped with the key to rotate to so the journal does not contain anything
+/// that usable without it. The keyrings are stored in the same form as the server so the new one
+/// can be uploaded as is.
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    to: KeyId,
+    old: Vec<u8>,
+    new: Vec<u8>,
+    retired: Vec<KeyId>,
+    unavailable: Vec<KeyId>,
+    switched: bool,
+}
+
+impl Journal {
+    /// `keyring` is the current keyring of `repo`.
+    fn start(
+        keymgr: &KeyMgr,
+        keyring: &Keyring,
+        repo: &str,
+        key: &KeyMaterial,
+        from: &[KeyId],
+    ) -> Result<Self, RotateError> {
+        // Get the current data key.
+        let old = unwrap_keyring(keymgr, keyring)?;
+        let to = KeyId::from_key(&key.key);
+
+        if keyring.is_retired(&to) {
+            return Err(RotateError::RetiredKey(to));
+        }
+
+        // Generate a new data key.
+        let new = DataKey::generate().map_err(RotateError::GenerateKeyFailed)?;
+        let mut next = keyring.rotate(&new, old.id());
+        let mut retired = Vec::new();
+        let mut unavailable = Vec::new();
+
+        for id in from {
+            if !retired.contains(id) {
+                next.retire(id.clone());
+                retired.push(id.clone());
+            }
+        }
+
+        next.add(&new, key).map_err(RotateError::WrapKeyFailed)?;
+
+        // Keep the other keys authorized. We can only wrap the new data key with the keys that
+        // available on this computer.
+        for id in keyring.keys().filter(|&k| *k != to && !from.contains(k)) {
+            let key = match keymgr.material(id) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
+                    unavailable.push(id.clone());
+                    continue;
+                }
+                Err(e) => return Err(RotateError::LoadKeyFailed(id.clone(), e)),
+            };
+
+            next.add(&new, &key).map_err(RotateError::WrapKeyFailed)?;
+        }
+
+        // Keep the old data key so we can resume after the keyring has been replaced.
+        let mut prev = Keyring::new(repo, &old);
+
+        prev.add(&old, key).map_err(RotateError::WrapKeyFailed)?;
+
+        Ok(Self {
+            to,
+            old: prev.to_bytes(&old),
+            new: next.to_bytes(&new),
+            retired,
+            unavailable,
+            switched: false,
+        })
+    }
+
+    /// Returns `true` if this journal was started to retire the same keys as `from`.
+    fn retires(&self, from: &[KeyId]) -> bool {
+        from.iter().all(|k| self.retired.contains(k))
+            && self.retired.iter().all(|k| from.contains(k))
+    }
+
+    fn load(path: &Path) -> Result<Option<Self>, RotateError> {
+        let data = match std::fs::read(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(RotateError::ReadJournalFailed(path.to_owned(), e)),
+        };
+
+        match postcard::from_bytes(&data) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(RotateError::InvalidJournal(path.to_owned(), e)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), RotateError> {
+        let tmp = path.with_extension("tmp");
+        let data = postcard::to_stdvec(self).unwrap();
+
+        if let Err(e) = std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, path)) {
+            return Err(RotateError::WriteJournalFailed(path.to_owned(), e));
+        }
+
+        Ok(())
+    }
+
+    fn unwrap(&self, keyring: &[u8], key: &KeyMaterial) -> Result<DataKey, RotateError> {
+        let keyring = Keyring::from_bytes(keyring).map_err(RotateError::InvalidJournalKeyring)?;
+
+        match keyring.unwrap(key) {
+            Ok(Some(v)) => Ok(v),
+            Ok(None) => Err(RotateError::JournalMismatched(self.to.clone())),
+            Err(e) => Err(RotateError::UnwrapKeyFailed(e)),
+        }
+    }
+}
+
+/// Manifest on the server that was decrypted by [`fetch_manifest()`].
+struct RemoteManifest {
+    manifest: Manifest,
+    revision: Revision,
+    rotated: bool,
+}
+
+/// Result of [`rotate()`].
+pub struct RotateSummary {
+    /// ID of the new data key.
+    pub key: KeyId,
+    /// Number of objects that was re-encrypted by this call.
+    pub objects: usize,
+    /// Number of objects that was encrypted with the old key and has been removed.
+    pub deleted: usize,
+    pub retired: Vec<KeyId>,
+    /// Authorized keys that was not retired but are not available on this computer. They lost
+    /// their access to the repository.
+    pub unavailable: Vec<KeyId>,
+}
+
+/// Represents an error when [`rotate()`] fails.
+#[derive(Debug, Error)]
+pub enum RotateError {
+    #[error("key {0} does not exists")]
+    UnknownKey(KeyId),
+
+    #[error("couldn't load key {0}")]
+    LoadKeyFailed(KeyId, #[source] Box<dyn Error>),
+
+    #[error("couldn't create {0}")]
+    CreateDirectoryFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't read {0}")]
+    ReadJournalFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0} is not a valid journal")]
+    InvalidJournal(PathBuf, #[source] postcard::Error),
+
+    #[error("couldn't write {0}")]
+    WriteJournalFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't remove {0}")]
+    RemoveJournalFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("key {0} cannot be retired by a rotation to itself")]
+    RetireTargetKey(KeyId),
+
+    #[error("a rotation to key {0} is in progress")]
+    InProgress(KeyId, Vec<KeyId>),
+
+    #[error("the journal was not created with key {0}")]
+    JournalMismatched(KeyId),
+
+    #[error("the journal contains an invalid keyring")]
+    InvalidJournalKeyring(#[source] postcard::Error),
+
+    #[error("couldn't get the keyring")]
+    FetchKeyringFailed(#[from] FetchKeyringError),
+
+    #[error("the repository has no keyring on the server")]
+    NoKeyring,
+
+    #[error("couldn't unlock the repository")]
+    UnlockFailed(#[from] UnlockError),
+
+    #[error("key {0} has been retired from the repository")]
+    RetiredKey(KeyId),
+
+    #[error("couldn't generate a new data key")]
+    GenerateKeyFailed(#[source] getrandom::Error),
+
+    #[error("couldn't wrap the data key")]
+    WrapKeyFailed(#[source] EncryptError),
+
+    #[error("couldn't unwrap the data key")]
+    UnwrapKeyFailed(#[source] UnwrapError),
+
+    #[error("couldn't download the manifest")]
+    DownloadManifestFailed(#[source] ClientError),
+
+    #[error("couldn't decrypt the manifest")]
+    DecryptManifestFailed(#[source] DecryptError),
+
+    #[error("the manifest on the server is not valid")]
+    InvalidManifest(#[source] postcard::Error),
+
+    #[error("couldn't check object {0} on the server")]
+    CheckObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't download object {0}")]
+    DownloadObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't decrypt object {0}")]
+    DecryptObjectFailed(String, #[source] DecryptError),
+
+    #[error("couldn't read object {0}")]
+    ReadObjectFailed(String, #[source] std::io::Error),
+
+    #[error("object {0} has unexpected content")]
+    ObjectMismatched(String),
+
+    #[error("couldn't create {0}")]
+    CreateFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't encrypt object {0}")]
+    EncryptObjectFailed(String, #[source] EncryptError),
+
+    #[error("couldn't write {0}")]
+    WriteFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't open {0}")]
+    OpenFileFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("couldn't upload object {0}")]
+    UploadObjectFailed(String, #[source] ClientError),
+
+    #[error("couldn't encrypt the manifest")]
+    EncryptManifestFailed(#[source] EncryptError),
+
+    #[error("couldn't upload the manifest")]
+    UploadManifestFailed(#[source] ClientError),
+
+    #[error("couldn't upload the keyring")]
+    UploadKeyringFailed(#[source] ClientError),
+
+    #[error("couldn't list the objects on the server")]
+    ListObjectsFailed(#[source] ClientError),
+
+    #[error("couldn't delete object {0}")]
+    DeleteObjectFailed(String, #[source] ClientError),
+
+    #[error("the keyring on the server was not rotated")]
+    KeyringNotRotated,
+
+    #[error("the manifest on the server is still encrypted with the old key")]
+    ManifestNotRotated,
+
+    #[error("{missing} object(s) are missing and {remaining} object(s) are still encrypted with the old key")]
+    ObjectsNotRotated { missing: usize, remaining: usize },
+}
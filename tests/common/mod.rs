@@ -0,0 +1,79 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Temporary Warp home to run the commands in. It is removed when dropped.
+pub struct Env {
+    pub root: PathBuf,
+    envs: Vec<(String, OsString)>,
+}
+
+impl Env {
+    /// Create a Warp home in a directory named after `name`, which must be unique among the tests,
+    /// with `config` as its `config.yml`.
+    pub fn new(name: &str, config: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("warp-{}-{}", name, std::process::id()));
+        let home = root.join("home");
+
+        if root.exists() {
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+
+        std::fs::create_dir_all(home.join(".warp")).unwrap();
+        std::fs::write(home.join(".warp").join("config.yml"), config).unwrap();
+
+        Self {
+            envs: vec![("HOME".into(), home.into())],
+            root,
+        }
+    }
+
+    /// Set an environment variable for all commands.
+    pub fn set(&mut self, name: &str, value: impl Into<OsString>) {
+        self.envs.push((name.into(), value.into()));
+    }
+
+    /// Run `program` without checking if it was succeeded.
+    pub fn exec(&self, program: &Path, args: &[&str], envs: &[(&str, &str)]) -> Output {
+        Command::new(program)
+            .args(args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .envs(envs.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Run `program` and panic if it fails.
+    pub fn run(&self, program: &Path, args: &[&str], envs: &[(&str, &str)]) -> Output {
+        let out = self.exec(program, args, envs);
+
+        assert!(
+            out.status.success(),
+            "{} {} failed: {}",
+            program.display(),
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr)
+        );
+
+        out
+    }
+
+    /// Run Warp and returns its standard output.
+    pub fn warp(&self, args: &[&str], envs: &[(&str, &str)]) -> String {
+        stdout(self.run(warp(), args, envs))
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+pub fn warp() -> &'static Path {
+    Path::new(env!("CARGO_BIN_EXE_warp"))
+}
+
+pub fn stdout(out: Output) -> String {
+    String::from_utf8(out.stdout).unwrap().trim().to_owned()
+}
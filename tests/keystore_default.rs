@@ -0,0 +1,156 @@
+#![cfg(target_os = "linux")]
+
+use self::common::{stdout, warp, Env};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+mod common;
+
+/// Run with `cargo test --test keystore_default -- --ignored` on a machine that has
+/// `dbus-run-session` and `gnome-keyring-daemon`.
+#[test]
+#[ignore = "requires dbus-run-session and gnome-keyring-daemon"]
+fn secret_service() {
+    // Skip if the tools are not installed.
+    for program in ["dbus-run-session", "dbus-send", "gnome-keyring-daemon"] {
+        if !Command::new("sh")
+            .args(["-c", &format!("command -v {program}")])
+            .output()
+            .is_ok_and(|o| o.status.success())
+        {
+            eprintln!("{program} is not installed, skipping");
+            return;
+        }
+    }
+
+    // Start a private session bus with a keyring daemon that has an unlocked login keyring. There
+    // is no display so the daemon cannot prompt for anything.
+    let mut env = Env::new("keystore-default", "key:\n  default_store: default\n");
+    let runtime = env.root.join("run");
+
+    std::fs::create_dir_all(&runtime).unwrap();
+    std::fs::set_permissions(
+        &runtime,
+        std::os::unix::fs::PermissionsExt::from_mode(0o700),
+    )
+    .unwrap();
+
+    let bus = Bus::start(&env.root.join("home"));
+
+    env.set("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+    env.set("XDG_RUNTIME_DIR", &runtime);
+    env.set("DISPLAY", "");
+    env.set("WAYLAND_DISPLAY", "");
+
+    let sh = Path::new("sh");
+    let unlock = "printf %s secret | gnome-keyring-daemon --unlock --components=secrets";
+
+    env.run(
+        sh,
+        &["-c", &format!("{unlock} --daemonize >/dev/null 2>&1")],
+        &[],
+    );
+
+    // Generate a key.
+    let id = env.warp(&["key", "new"], &[]);
+
+    assert_eq!(id.len(), 32);
+    assert!(env.warp(&["key", "ls"], &[]).contains(&id));
+
+    // Lock the collection. The key should still be listed without unlocking it but loading the key
+    // needs a prompt, which should fail instead of hanging.
+    let collection = env.run(
+        Path::new("dbus-send"),
+        &[
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.secrets",
+            "/org/freedesktop/secrets",
+            "org.freedesktop.Secret.Service.ReadAlias",
+            "string:default",
+        ],
+        &[],
+    );
+    let collection = String::from_utf8(collection.stdout).unwrap();
+    let collection = collection
+        .split('"')
+        .find(|v| v.starts_with("/org/freedesktop/secrets/collection/"))
+        .unwrap_or_else(|| panic!("no default collection: {collection}"));
+
+    env.run(
+        Path::new("dbus-send"),
+        &[
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.secrets",
+            "/org/freedesktop/secrets",
+            "org.freedesktop.Secret.Service.Lock",
+            &format!("array:objpath:{collection}"),
+        ],
+        &[],
+    );
+
+    assert!(env.warp(&["key", "ls"], &[]).contains(&id));
+
+    let out = env.exec(warp(), &["key", "export", "--phrase", &id], &[]);
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unlock"));
+    assert!(stdout(out).is_empty());
+
+    // Unlock the collection again then load and remove the key.
+    env.run(sh, &["-c", &format!("{unlock} >/dev/null 2>&1")], &[]);
+
+    assert!(!env
+        .warp(&["key", "export", "--phrase", &id], &[])
+        .is_empty());
+
+    env.warp(&["key", "rm", "--force", "--yes", &id], &[]);
+
+    assert!(!env.warp(&["key", "ls"], &[]).contains(&id));
+}
+
+/// A session bus started by `dbus-run-session`, which is stopped when dropped.
+struct Bus {
+    address: String,
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Bus {
+    fn start(home: &Path) -> Self {
+        // The session ends when the standard input is closed.
+        let mut child = Command::new("dbus-run-session")
+            .args([
+                "--",
+                "sh",
+                "-c",
+                "echo \"$DBUS_SESSION_BUS_ADDRESS\" && exec cat >/dev/null",
+            ])
+            .env("HOME", home)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take();
+        let mut address = String::new();
+
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        Self {
+            address: address.trim().to_owned(),
+            child,
+            stdin,
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        self.child.wait().ok();
+    }
+}
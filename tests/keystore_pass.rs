@@ -0,0 +1,98 @@
+use self::common::{stdout, warp, Env};
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+#[test]
+fn pass() {
+    // Skip if gpg is not installed.
+    if Command::new("gpg").arg("--version").output().is_err() {
+        eprintln!("gpg is not installed, skipping");
+        return;
+    }
+
+    // Setup a Warp home, a GPG home and a password store.
+    let mut env = Env::new(
+        "keystore-pass",
+        "key:\n  default_store: pass\n  stores:\n    pass:\n      type: pass\n",
+    );
+    let gnupg = env.root.join("gnupg");
+    let store = env.root.join("store");
+    let dir = store.join("warp");
+
+    std::fs::create_dir_all(&gnupg).unwrap();
+    std::fs::create_dir_all(&dir).unwrap();
+
+    #[cfg(unix)]
+    std::fs::set_permissions(&gnupg, std::os::unix::fs::PermissionsExt::from_mode(0o700)).unwrap();
+
+    // The nearest .gpg-id should be used so the one at the root, which has no usable recipient, is
+    // ignored. The other entries in the same folder should not be listed as a key.
+    std::fs::write(store.join(".gpg-id"), "nobody@example.com\n").unwrap();
+    std::fs::write(dir.join(".gpg-id"), "# Warp\nwarp-test@example.com\n").unwrap();
+    std::fs::write(dir.join("notes.gpg"), "").unwrap();
+
+    env.set("GNUPGHOME", &gnupg);
+    env.set("PASSWORD_STORE_DIR", &store);
+
+    let gpg = Path::new("gpg");
+
+    env.run(
+        gpg,
+        &[
+            "--batch",
+            "--passphrase",
+            "",
+            "--quick-gen-key",
+            "warp-test@example.com",
+            "default",
+            "default",
+            "never",
+        ],
+        &[],
+    );
+
+    // Generate a key. It should be encrypted for the recipient in the nearest .gpg-id.
+    let id = env.warp(&["key", "new"], &[]);
+    let entry = dir.join(format!("{id}.gpg"));
+    let data = dir.join(format!("{id}.data"));
+    let decrypt = || stdout(env.run(gpg, &["--quiet", "--decrypt", entry.to_str().unwrap()], &[]));
+    let key = decrypt();
+
+    assert_eq!(id.len(), 32);
+    assert!(data.exists());
+    assert_eq!(key.len(), 32);
+
+    // Only the key should be listed.
+    let ls = env.warp(&["key", "ls"], &[]);
+
+    assert!(ls.contains(&id));
+    assert!(!ls.contains("notes"));
+
+    // Remove the key.
+    let phrase = env.warp(&["key", "export", "--phrase", &id], &[]);
+
+    env.warp(&["key", "rm", "--force", "--yes", &id], &[]);
+
+    assert!(!entry.exists());
+    assert!(!data.exists());
+    assert!(!env
+        .exec(warp(), &["key", "export", "--phrase", &id], &[])
+        .status
+        .success());
+
+    // Store the key back. The same key should be written.
+    let recovered = env.warp(&["key", "recover"], &[("WARP_RECOVERY_PHRASE", &phrase)]);
+
+    assert_eq!(recovered, id);
+    assert!(data.exists());
+    assert_eq!(decrypt(), key);
+
+    // Clean up.
+    Command::new("gpgconf")
+        .args(["--kill", "gpg-agent"])
+        .env("GNUPGHOME", &gnupg)
+        .status()
+        .ok();
+}
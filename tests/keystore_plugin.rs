@@ -0,0 +1,289 @@
+use self::common::{stdout, warp, Env};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod common;
+
+const CONFIG: &str = "key:\n  default_store: file\n  stores:\n    file:\n      type: file\n";
+
+#[test]
+fn plugin() {
+    // Setup a Warp home with the reference plugin on PATH.
+    let mut env = Env::new("keystore-plugin", CONFIG);
+    let bin = env.root.join("bin");
+    let keys = env.root.join("keys");
+
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::copy(
+        build_plugin(),
+        bin.join(format!("warp-keystore-dir{}", std::env::consts::EXE_SUFFIX)),
+    )
+    .unwrap();
+
+    env.set("PATH", &bin);
+    env.set("WARP_KEYSTORE_DIR", &keys);
+
+    // The plugin should be usable without any configurations.
+    assert!(env
+        .warp(&["keystore", "ls"], &[])
+        .lines()
+        .any(|l| l.contains("dir") && l.contains("enabled")));
+
+    let id = env.warp(&["key", "new", "--store", "dir"], &[]);
+
+    assert!(keys.join(&id).exists());
+    assert!(env.warp(&["key", "ls"], &[]).contains(&id));
+
+    // Disabling the plugin should hide its keys without writing the program to the configurations.
+    env.warp(&["keystore", "disable", "dir"], &[]);
+
+    let config =
+        std::fs::read_to_string(env.root.join("home").join(".warp").join("config.yml")).unwrap();
+
+    assert!(!env.warp(&["key", "ls"], &[]).contains(&id));
+    assert!(config.contains("dir"));
+    assert!(!config.contains("command"));
+}
+
+#[cfg(unix)]
+#[test]
+fn protocol() {
+    // Setup a Warp home with the reference plugin behind a program that records the messages.
+    let mut env = Env::new("keystore-plugin-protocol", CONFIG);
+    let keys = env.root.join("keys");
+    let requests = env.root.join("requests");
+    let responses = env.root.join("responses");
+    let plugin = build_plugin();
+    let path = std::env::var("PATH").unwrap();
+    let bin = env.root.join("bin");
+
+    std::fs::create_dir_all(&bin).unwrap();
+
+    program(
+        &bin,
+        "wire",
+        &format!(
+            "export PATH='{path}'\ntee -a '{}' | '{}' | tee -a '{}'",
+            requests.display(),
+            plugin.display(),
+            responses.display()
+        ),
+    );
+
+    env.set("PATH", &bin);
+    env.set("WARP_KEYSTORE_DIR", &keys);
+
+    // The version should be checked before anything else.
+    let id = env.warp(&["key", "new", "--store", "wire"], &[]);
+    let (req, res) = wire(&requests, &responses);
+    let generate = find(&req, "generate");
+
+    assert_eq!(req[0], json!({ "request": "hello", "version": 1 }));
+    assert_eq!(res[0], json!({ "version": 1 }));
+    assert!(generate["data"].as_str().is_some_and(|v| !v.is_empty()));
+    assert_eq!(res[position(&req, "generate")], json!({ "id": id }));
+
+    let data = generate["data"].clone();
+
+    // List the keys.
+    env.warp(&["key", "ls"], &[]);
+
+    let (req, res) = wire(&requests, &responses);
+    let listed = &res[position(&req, "list")]["keys"];
+
+    assert_eq!(find(&req, "list"), json!({ "request": "list" }));
+    assert!(listed
+        .as_array()
+        .unwrap()
+        .contains(&json!({ "id": id, "data": data })));
+
+    // Get the key.
+    let phrase = env.warp(&["key", "export", "--phrase", &id], &[]);
+    let (req, res) = wire(&requests, &responses);
+    let material = &res[position(&req, "get")]["material"];
+    let key = material["key"].clone();
+
+    assert_eq!(find(&req, "get"), json!({ "request": "get", "id": id }));
+    assert_eq!(material["data"], data);
+    assert_eq!(key.as_str().unwrap().len(), 32);
+
+    // Delete the key.
+    env.warp(&["key", "rm", "--force", "--yes", &id], &[]);
+
+    let (req, res) = wire(&requests, &responses);
+
+    assert_eq!(
+        find(&req, "delete"),
+        json!({ "request": "delete", "id": id })
+    );
+    assert_eq!(res[position(&req, "delete")], json!({ "deleted": true }));
+
+    // Store the key back.
+    env.warp(
+        &["key", "recover", "--store", "wire"],
+        &[("WARP_RECOVERY_PHRASE", &phrase)],
+    );
+
+    let (req, res) = wire(&requests, &responses);
+    let store = find(&req, "store");
+
+    assert_eq!(store["id"], json!(id));
+    assert_eq!(store["key"], key);
+    assert!(store["data"].as_str().is_some_and(|v| !v.is_empty()));
+    assert_eq!(res[position(&req, "store")], json!({}));
+
+    // A key that does not match its ID should be rejected.
+    let file = keys.join(&id);
+    let mut stored: Value = serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+
+    stored["key"] = json!("00000000000000000000000000000000");
+    std::fs::write(&file, stored.to_string()).unwrap();
+
+    let out = env.exec(warp(), &["key", "export", "--phrase", &id], &[]);
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("does not match its ID"));
+}
+
+#[cfg(unix)]
+#[test]
+fn unsupported_version() {
+    let env = Env::new("keystore-plugin-version", CONFIG);
+    let command = script(&env.root, "old", r#"echo '{"version":2}'"#);
+
+    enable(&env, "old", &command);
+
+    // The store should not prevent the other commands from working.
+    let out = env.run(warp(), &["key", "ls"], &[]);
+
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unsupported protocol version 2"));
+    assert!(stdout(env.run(warp(), &["keystore", "ls"], &[]))
+        .lines()
+        .any(|l| l.contains("old") && l.contains("invalid")));
+
+    env.warp(&["keystore", "disable", "old"], &[]);
+}
+
+#[cfg(unix)]
+#[test]
+fn error_response() {
+    let env = Env::new("keystore-plugin-error", CONFIG);
+    let command = script(
+        &env.root,
+        "broken",
+        r#"case "$line" in
+  *hello*) echo '{"version":1}' ;;
+  *list*) echo '{"keys":[]}' ;;
+  *) echo '{"error":"out of order"}' ;;
+esac"#,
+    );
+
+    enable(&env, "broken", &command);
+
+    // The message from the plugin should be shown.
+    let out = env.exec(warp(), &["key", "new", "--store", "broken"], &[]);
+
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("out of order"));
+}
+
+/// Build the reference plugin. It is an example so it is not always built before the tests.
+fn build_plugin() -> PathBuf {
+    let mut cmd = Command::new(env!("CARGO"));
+
+    cmd.args([
+        "build",
+        "--example",
+        "warp-keystore-dir",
+        "--message-format=json",
+    ])
+    .current_dir(env!("CARGO_MANIFEST_DIR"));
+
+    if warp().parent().unwrap().ends_with("release") {
+        cmd.arg("--release");
+    }
+
+    let out = cmd.output().unwrap();
+
+    assert!(
+        out.status.success(),
+        "couldn't build the reference plugin: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    // Get the path of the executable.
+    for line in String::from_utf8(out.stdout).unwrap().lines() {
+        let msg: Value = serde_json::from_str(line).unwrap();
+
+        if msg["reason"] == "compiler-artifact" && msg["target"]["name"] == "warp-keystore-dir" {
+            return msg["executable"].as_str().unwrap().into();
+        }
+    }
+
+    panic!("cargo did not report the reference plugin");
+}
+
+/// Write a plugin that runs `body` for each request in `$line`.
+#[cfg(unix)]
+fn script(root: &Path, name: &str, body: &str) -> PathBuf {
+    program(root, name, &format!("while read -r line; do\n{body}\ndone"))
+}
+
+/// Write a plugin that runs `body` once.
+#[cfg(unix)]
+fn program(root: &Path, name: &str, body: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = root.join(format!("warp-keystore-{name}"));
+
+    std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    std::fs::set_permissions(&path, PermissionsExt::from_mode(0o755)).unwrap();
+
+    path
+}
+
+#[cfg(unix)]
+fn enable(env: &Env, id: &str, command: &Path) {
+    let command = format!("command={}", command.display());
+
+    env.warp(
+        &["keystore", "enable", id, "--type", "plugin", "-o", &command],
+        &[],
+    );
+}
+
+/// Returns the requests and responses that was recorded by the `wire` plugin then clear it.
+#[cfg(unix)]
+fn wire(requests: &Path, responses: &Path) -> (Vec<Value>, Vec<Value>) {
+    let read = |path: &Path| -> Vec<Value> {
+        let lines = std::fs::read_to_string(path).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+
+        lines
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    };
+
+    let req = read(requests);
+    let res = read(responses);
+
+    assert_eq!(req.len(), res.len());
+
+    (req, res)
+}
+
+#[cfg(unix)]
+fn position(requests: &[Value], name: &str) -> usize {
+    requests
+        .iter()
+        .position(|r| r["request"] == name)
+        .unwrap_or_else(|| panic!("no '{name}' request in {requests:?}"))
+}
+
+#[cfg(unix)]
+fn find(requests: &[Value], name: &str) -> Value {
+    requests[position(requests, name)].clone()
+}
@@ -0,0 +1,230 @@
+#![cfg(unix)]
+
+use self::common::{stdout, warp, Env};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+mod common;
+
+const CONFIG: &str = "key:\n  default_store: file\n  stores:\n    file:\n      type: file\n";
+
+#[test]
+fn sync() {
+    // Setup a server with an account.
+    let mut env = Env::new("sync", CONFIG);
+    let data = env.root.join("data");
+    let data = data.to_str().unwrap();
+    let server = Path::new(env!("CARGO_BIN_EXE_warp-server"));
+    let token = stdout(env.run(server, &["account", "add", "--data", data, "alice"], &[]));
+
+    assert!(!token.is_empty());
+
+    env.run(
+        server,
+        &["account", "passwd", "--data", data, "alice"],
+        &[("WARP_PASSWORD", "secret")],
+    );
+
+    let server = Server::start(server, data);
+    let url = server.url.as_str();
+
+    // Login and generate a key.
+    env.set("WARP_PASSPHRASE", "passphrase");
+    env.warp(
+        &["login", "--server", url, "--user", "alice"],
+        &[("WARP_PASSWORD", "secret")],
+    );
+
+    let key = env.warp(&["key", "new"], &[]);
+
+    // Setup the first checkout. The read-only directory should not prevent its files from being
+    // written on the other side.
+    let one = env.root.join("one");
+
+    std::fs::create_dir_all(one.join("dir")).unwrap();
+    std::fs::create_dir_all(one.join("ro")).unwrap();
+    std::fs::write(one.join("a.txt"), "a").unwrap();
+    std::fs::write(one.join("dir").join("b.txt"), "b").unwrap();
+    std::fs::write(one.join("ro").join("c.txt"), "c").unwrap();
+    std::fs::write(one.join("run.sh"), "#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(one.join("run.sh"), PermissionsExt::from_mode(0o755)).unwrap();
+    std::fs::set_permissions(one.join("ro"), PermissionsExt::from_mode(0o555)).unwrap();
+    std::os::unix::fs::symlink("a.txt", one.join("link")).unwrap();
+
+    env.warp(
+        &[
+            "init",
+            "--name",
+            "sync",
+            "--server",
+            url,
+            "--key",
+            &key,
+            one.to_str().unwrap(),
+        ],
+        &[],
+    );
+
+    // Push from a session that modify the working tree.
+    let shell = script(
+        &env.root,
+        "one",
+        &format!(
+            "cd '{}' && echo modified > a.txt && mv dir/b.txt dir/moved.txt && echo d > d.txt",
+            one.display()
+        ),
+    );
+
+    session(&env, &one, &shell);
+
+    // Pull into the second checkout.
+    let two = env.root.join("two");
+
+    std::fs::create_dir_all(two.join(".warp")).unwrap();
+    std::fs::copy(
+        one.join(".warp").join("repo.yml"),
+        two.join(".warp").join("repo.yml"),
+    )
+    .unwrap();
+
+    session(&env, &two, Path::new("true"));
+
+    assert_eq!(tree(&two), tree(&one));
+
+    // Push back from the second checkout.
+    let shell = script(
+        &env.root,
+        "two",
+        &format!("cd '{}' && rm -r dir d.txt && mkdir d.txt", two.display()),
+    );
+
+    session(&env, &two, &shell);
+    session(&env, &one, Path::new("true"));
+
+    assert_eq!(tree(&one), tree(&two));
+    assert!(one.join("d.txt").is_dir());
+    assert!(!one.join("dir").exists());
+
+    // Allow the test directory to be removed.
+    for dir in [&one, &two] {
+        std::fs::set_permissions(dir.join("ro"), PermissionsExt::from_mode(0o755)).unwrap();
+    }
+}
+
+/// Run a Warp session on `repo` with `shell` as the shell.
+fn session(env: &Env, repo: &Path, shell: &Path) {
+    let out = env.exec(
+        warp(),
+        &[],
+        &[
+            ("WARP_DIR", repo.to_str().unwrap()),
+            ("SHELL", shell.to_str().unwrap()),
+        ],
+    );
+
+    assert!(
+        out.status.success(),
+        "session on {} failed: {}",
+        repo.display(),
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+/// Write a shell script that runs `body`.
+fn script(root: &Path, name: &str, body: &str) -> PathBuf {
+    let path = root.join(format!("shell-{name}"));
+
+    std::fs::write(&path, format!("#!/bin/sh\nset -e\n{body}\n")).unwrap();
+    std::fs::set_permissions(&path, PermissionsExt::from_mode(0o755)).unwrap();
+
+    path
+}
+
+/// Returns the type, mode and content of everything in the working tree at `root`.
+fn tree(root: &Path) -> BTreeMap<String, (String, u32, Vec<u8>)> {
+    let mut entries = BTreeMap::new();
+    let mut dirs = vec![root.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        for item in std::fs::read_dir(&dir).unwrap() {
+            let path = item.unwrap().path();
+            let name = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned();
+
+            if name == ".warp" {
+                continue;
+            }
+
+            let meta = std::fs::symlink_metadata(&path).unwrap();
+            let mode = meta.permissions().mode() & 0o777;
+            let entry = if meta.is_dir() {
+                dirs.push(path);
+                ("dir".into(), mode, Vec::new())
+            } else if meta.is_symlink() {
+                let target = std::fs::read_link(&path).unwrap();
+
+                ("link".into(), 0, target.to_str().unwrap().into())
+            } else {
+                ("file".into(), mode, std::fs::read(&path).unwrap())
+            };
+
+            entries.insert(name, entry);
+        }
+    }
+
+    entries
+}
+
+/// A running `warp-server` that is killed when dropped.
+struct Server {
+    url: String,
+    child: Child,
+}
+
+impl Server {
+    fn start(program: &Path, data: &str) -> Self {
+        let mut child = Command::new(program)
+            .args(["serve", "--data", data, "--listen", "127.0.0.1:0"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // Get the address from the first line then forward the rest.
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+
+        stderr.read_line(&mut line).unwrap();
+
+        let url = line
+            .trim_end()
+            .strip_prefix("Listening on ")
+            .and_then(|v| v.strip_suffix('.'))
+            .unwrap_or_else(|| panic!("unexpected output from the server: {line}"))
+            .to_owned();
+
+        std::thread::spawn(move || {
+            for line in stderr.lines() {
+                match line {
+                    Ok(v) => eprintln!("{v}"),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Self { url, child }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}